@@ -0,0 +1,113 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{FromSample, SampleFormat, SizedSample, Stream, StreamConfig};
+
+use super::{AudioSink, BUFFER_CAPACITY};
+
+#[derive(Debug)]
+pub enum AudioError {
+    NoDevice,
+    StreamError(String),
+}
+
+impl std::fmt::Display for AudioError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        return match self {
+            AudioError::NoDevice => write!(f, "no output device"),
+            AudioError::StreamError(message) => write!(f, "{}", message),
+        };
+    }
+}
+
+/// Plays the samples on the default output device of the host.
+pub struct HostSink {
+    // Dropping the stream stops the playback.
+    _stream: Stream,
+    queue: Arc<Mutex<VecDeque<[f32; 2]>>>,
+    sample_rate: u32,
+}
+
+impl HostSink {
+    pub fn new() -> Result<Self, AudioError> {
+        let device = cpal::default_host()
+            .default_output_device()
+            .ok_or(AudioError::NoDevice)?;
+        let supported = device
+            .default_output_config()
+            .map_err(|error| AudioError::StreamError(error.to_string()))?;
+        let format = supported.sample_format();
+        let config: StreamConfig = supported.into();
+        let queue = Arc::new(Mutex::new(VecDeque::with_capacity(BUFFER_CAPACITY * 2)));
+        let stream = match format {
+            SampleFormat::F32 => build_stream::<f32>(&device, &config, queue.clone())?,
+            SampleFormat::I16 => build_stream::<i16>(&device, &config, queue.clone())?,
+            SampleFormat::U16 => build_stream::<u16>(&device, &config, queue.clone())?,
+            format => {
+                return Err(AudioError::StreamError(format!(
+                    "unsupported sample format {}",
+                    format
+                )));
+            }
+        };
+        stream
+            .play()
+            .map_err(|error| AudioError::StreamError(error.to_string()))?;
+        return Ok(Self {
+            _stream: stream,
+            queue,
+            sample_rate: config.sample_rate.0,
+        });
+    }
+}
+
+impl AudioSink for HostSink {
+    fn get_sample_rate(&self) -> u32 {
+        return self.sample_rate;
+    }
+
+    fn push(&mut self, samples: &[[f32; 2]]) {
+        let mut queue = self.queue.lock().unwrap();
+        queue.extend(samples);
+        // Past twice the capacity the emulation is running ahead, drop the
+        // oldest samples rather than letting the latency grow.
+        let excess = queue.len().saturating_sub(BUFFER_CAPACITY * 2);
+        queue.drain(..excess);
+    }
+
+    fn get_buffered(&self) -> usize {
+        return self.queue.lock().unwrap().len();
+    }
+}
+
+fn build_stream<T: SizedSample + FromSample<f32>>(
+    device: &cpal::Device,
+    config: &StreamConfig,
+    queue: Arc<Mutex<VecDeque<[f32; 2]>>>,
+) -> Result<Stream, AudioError> {
+    let channels = config.channels as usize;
+    return device
+        .build_output_stream(
+            config,
+            move |data: &mut [T], _| {
+                let mut queue = queue.lock().unwrap();
+                for frame in data.chunks_mut(channels) {
+                    // Underruns are filled with silence.
+                    let [left, right] = queue.pop_front().unwrap_or([0.0; 2]);
+                    for (channel, sample) in frame.iter_mut().enumerate() {
+                        let value = match (channels, channel) {
+                            (1, _) => (left + right) / 2.0,
+                            (_, 0) => left,
+                            (_, 1) => right,
+                            _ => 0.0,
+                        };
+                        *sample = T::from_sample(value);
+                    }
+                }
+            },
+            |error| eprintln!("Audio stream error: {}", error),
+            None,
+        )
+        .map_err(|error| AudioError::StreamError(error.to_string()));
+}
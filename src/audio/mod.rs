@@ -0,0 +1,98 @@
+#[cfg(feature = "cpal")]
+mod host;
+pub mod wav;
+
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "cpal")]
+pub use host::HostSink;
+
+/// Samples buffered ahead of playback, about 85 ms at 48 kHz.
+pub const BUFFER_CAPACITY: usize = 4096;
+/// Largest change of the resampling ratio applied by `rate_adjust`.
+const MAX_RATE_DELTA: f64 = 0.005;
+
+/// Destination of the resampled APU output.
+pub trait AudioSink {
+    fn get_sample_rate(&self) -> u32;
+
+    /// Queues stereo samples for playback.
+    fn push(&mut self, samples: &[[f32; 2]]);
+
+    /// Samples queued but not played yet.
+    fn get_buffered(&self) -> usize;
+}
+
+/// Returns the factor to apply to the resampling ratio so that the sink
+/// buffer level drifts back to half its capacity, producing a bit more
+/// samples when it runs low and a bit fewer when it fills up.
+pub fn rate_adjust(sink: &dyn AudioSink) -> f64 {
+    let fill = sink.get_buffered().min(BUFFER_CAPACITY) as f64 / BUFFER_CAPACITY as f64;
+    return 1.0 + (1.0 - 2.0 * fill) * MAX_RATE_DELTA;
+}
+
+/// Lengthens `samples` by `factor` with linear interpolation, which plays
+/// them slower and lower.
+pub fn stretch(samples: &[[f32; 2]], factor: f64) -> Vec<[f32; 2]> {
+    let Some(last) = samples.last() else {
+        return vec![];
+    };
+    let length = (samples.len() as f64 * factor).round() as usize;
+    return (0..length)
+        .map(|index| {
+            let position = index as f64 / factor;
+            let before = position.floor() as usize;
+            let fraction = (position - before as f64) as f32;
+            let current = samples[before];
+            let next = samples.get(before + 1).unwrap_or(last);
+            [
+                current[0] + (next[0] - current[0]) * fraction,
+                current[1] + (next[1] - current[1]) * fraction,
+            ]
+        })
+        .collect();
+}
+
+/// Opens the host audio output, falling back to a `NullSink` when it is not
+/// available or not compiled in.
+pub fn open_default() -> Box<dyn AudioSink> {
+    #[cfg(feature = "cpal")]
+    match HostSink::new() {
+        Ok(sink) => return Box::new(sink),
+        Err(error) => eprintln!("Could not open the audio output: {}", error),
+    }
+    return Box::new(NullSink::new(crate::hardware::apu::DEFAULT_SAMPLE_RATE));
+}
+
+/// Discards the samples, but plays them in real time so that the emulation
+/// can still be paced by the audio buffer on machines without a sound card.
+pub struct NullSink {
+    sample_rate: u32,
+    /// When the samples queued so far will have been played.
+    drained_at: Instant,
+}
+
+impl NullSink {
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            sample_rate,
+            drained_at: Instant::now(),
+        }
+    }
+}
+
+impl AudioSink for NullSink {
+    fn get_sample_rate(&self) -> u32 {
+        return self.sample_rate;
+    }
+
+    fn push(&mut self, samples: &[[f32; 2]]) {
+        let duration = Duration::from_secs_f64(samples.len() as f64 / self.sample_rate as f64);
+        self.drained_at = self.drained_at.max(Instant::now()) + duration;
+    }
+
+    fn get_buffered(&self) -> usize {
+        let remaining = self.drained_at.saturating_duration_since(Instant::now());
+        return (remaining.as_secs_f64() * self.sample_rate as f64) as usize;
+    }
+}
@@ -0,0 +1,201 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+const HEADER_SIZE: u32 = 44;
+const CHANNELS: u16 = 2;
+const BITS_PER_SAMPLE: u16 = 16;
+const BLOCK_ALIGN: u16 = CHANNELS * BITS_PER_SAMPLE / 8;
+/// Frames fitting in a WAV file, whose sizes are 32 bits, a bit over 6
+/// hours at 48 kHz.
+const MAX_FRAMES: u32 = (u32::MAX - (HEADER_SIZE - 8)) / BLOCK_ALIGN as u32;
+
+/// Writes stereo samples to a 16-bit PCM WAV file. The sizes in the header
+/// are only filled in by `finish`.
+pub struct WavWriter {
+    file: BufWriter<File>,
+    frames: u32,
+}
+
+impl WavWriter {
+    pub fn create(path: &Path, sample_rate: u32) -> io::Result<Self> {
+        let mut file = BufWriter::new(File::create(path)?);
+        file.write_all(b"RIFF")?;
+        file.write_all(&(HEADER_SIZE - 8).to_le_bytes())?;
+        file.write_all(b"WAVEfmt ")?;
+        file.write_all(&16u32.to_le_bytes())?;
+        // PCM
+        file.write_all(&1u16.to_le_bytes())?;
+        file.write_all(&CHANNELS.to_le_bytes())?;
+        file.write_all(&sample_rate.to_le_bytes())?;
+        file.write_all(&(sample_rate * BLOCK_ALIGN as u32).to_le_bytes())?;
+        file.write_all(&BLOCK_ALIGN.to_le_bytes())?;
+        file.write_all(&BITS_PER_SAMPLE.to_le_bytes())?;
+        file.write_all(b"data")?;
+        file.write_all(&0u32.to_le_bytes())?;
+        return Ok(Self { file, frames: 0 });
+    }
+
+    /// Appends `samples`, failing without writing any of them once the file
+    /// would go over the 4 GiB a WAV file can hold.
+    pub fn write(&mut self, samples: &[[f32; 2]]) -> io::Result<()> {
+        let frames = u32::try_from(samples.len())
+            .ok()
+            .and_then(|count| self.frames.checked_add(count))
+            .filter(|frames| *frames <= MAX_FRAMES)
+            .ok_or_else(too_large)?;
+        for sample in samples {
+            for value in sample {
+                let value = (value.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+                self.file.write_all(&value.to_le_bytes())?;
+            }
+        }
+        self.frames = frames;
+        return Ok(());
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        let data_size = self
+            .frames
+            .checked_mul(BLOCK_ALIGN as u32)
+            .ok_or_else(too_large)?;
+        let riff_size = data_size
+            .checked_add(HEADER_SIZE - 8)
+            .ok_or_else(too_large)?;
+        self.file.seek(SeekFrom::Start(4))?;
+        self.file.write_all(&riff_size.to_le_bytes())?;
+        self.file.seek(SeekFrom::Start(HEADER_SIZE as u64 - 4))?;
+        self.file.write_all(&data_size.to_le_bytes())?;
+        self.file.flush()?;
+        return Ok(());
+    }
+}
+
+fn too_large() -> io::Error {
+    return io::Error::new(
+        io::ErrorKind::FileTooLarge,
+        "WAV files are limited to 4 GiB",
+    );
+}
+
+/// Records the emulator output to a WAV file, and optionally each channel to
+/// its own file next to it.
+pub struct Recorder {
+    path: PathBuf,
+    mix: WavWriter,
+    stems: Option<Vec<WavWriter>>,
+    frames: u64,
+    sample_rate: u32,
+}
+
+impl Recorder {
+    pub fn start(path: &Path, sample_rate: u32, stems: bool) -> io::Result<Self> {
+        let mix = WavWriter::create(path, sample_rate)?;
+        let stems = if stems {
+            let writers = (1..=4)
+                .map(|channel| WavWriter::create(&stem_path(path, channel), sample_rate))
+                .collect::<io::Result<Vec<_>>>()?;
+            Some(writers)
+        } else {
+            None
+        };
+        return Ok(Self {
+            path: path.to_path_buf(),
+            mix,
+            stems,
+            frames: 0,
+            sample_rate,
+        });
+    }
+
+    pub fn get_path(&self) -> &Path {
+        return &self.path;
+    }
+
+    pub fn has_stems(&self) -> bool {
+        return self.stems.is_some();
+    }
+
+    /// Recorded duration, in seconds.
+    pub fn get_duration(&self) -> f64 {
+        return self.frames as f64 / self.sample_rate as f64;
+    }
+
+    pub fn write(
+        &mut self,
+        samples: &[[f32; 2]],
+        stems: Option<&Vec<Vec<[f32; 2]>>>,
+    ) -> io::Result<()> {
+        self.mix.write(samples)?;
+        self.frames += samples.len() as u64;
+        if let Some(writers) = self.stems.as_mut()
+            && let Some(stems) = stems
+        {
+            for (writer, samples) in writers.iter_mut().zip(stems) {
+                writer.write(samples)?;
+            }
+        }
+        return Ok(());
+    }
+
+    pub fn stop(self) -> io::Result<()> {
+        self.mix.finish()?;
+        for writer in self.stems.into_iter().flatten() {
+            writer.finish()?;
+        }
+        return Ok(());
+    }
+}
+
+/// Path of the file recording a single channel, `music.wav` giving
+/// `music_ch1.wav` for the first one.
+fn stem_path(path: &Path, channel: usize) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    return path.with_file_name(format!("{}_ch{}.wav", stem, channel));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        return std::env::temp_dir().join(format!("{}_{}.wav", name, std::process::id()));
+    }
+
+    fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+        return u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap());
+    }
+
+    #[test]
+    fn fills_in_the_sizes_when_finished() {
+        let path = temp_path("wav_sizes");
+        let mut writer = WavWriter::create(&path, 48_000).unwrap();
+        writer
+            .write(&[[0.0, 0.5], [1.0, -1.0], [2.0, -2.0]])
+            .unwrap();
+        writer.finish().unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(bytes.len(), HEADER_SIZE as usize + 12);
+        assert_eq!(read_u32(&bytes, 4), bytes.len() as u32 - 8);
+        assert_eq!(read_u32(&bytes, 40), 12);
+        // Samples are clamped.
+        assert_eq!(bytes[HEADER_SIZE as usize + 8..], [0xFF, 0x7F, 0x01, 0x80]);
+    }
+
+    #[test]
+    fn stops_at_the_4_gib_limit() {
+        let path = temp_path("wav_limit");
+        let mut writer = WavWriter::create(&path, 48_000).unwrap();
+        writer.frames = MAX_FRAMES - 1;
+        writer.write(&[[0.0; 2]]).unwrap();
+        let error = writer.write(&[[0.0; 2]]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::FileTooLarge);
+        assert_eq!(writer.frames, MAX_FRAMES);
+        writer.finish().unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(read_u32(&bytes, 4), u32::MAX - 3);
+        assert_eq!(read_u32(&bytes, 40), MAX_FRAMES * BLOCK_ALIGN as u32);
+    }
+}
@@ -0,0 +1,94 @@
+use crate::hardware::memory::Model;
+
+use clap::{Parser, ValueEnum};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// Sizes a Game Boy pixel may take on screen.
+pub const SCALES: std::ops::RangeInclusive<f32> = 1.0..=16.0;
+
+/// Game Boy and Game Boy Color emulator.
+#[derive(Parser)]
+#[command(version, about)]
+pub struct Options {
+    /// ROM to run, or a zip or gzip archive holding it.
+    #[arg(required_unless_present = "gbs")]
+    pub rom: Option<PathBuf>,
+    /// ROM to pick in a zip archive, the first one by default.
+    #[arg(long, value_name = "NAME", requires = "rom")]
+    pub entry: Option<String>,
+    /// IPS, UPS or BPS patch to apply to the ROM, instead of the one named
+    /// after it if any.
+    #[arg(long, value_name = "FILE", requires = "rom")]
+    pub patch: Option<PathBuf>,
+    /// Leave the ROM unpatched even when a patch is named after it.
+    #[arg(long, conflicts_with = "patch")]
+    pub no_patch: bool,
+    /// Boot ROM to run before the cartridge, 256 bytes for a DMG or 2304
+    /// for a CGB, instead of the one configured for the model.
+    #[arg(long, value_name = "FILE")]
+    pub boot_rom: Option<PathBuf>,
+    /// Hardware to emulate, picked from the cartridge header unless
+    /// configured otherwise.
+    #[arg(long, value_enum)]
+    pub model: Option<ModelOption>,
+    /// Size of a Game Boy pixel on screen, 3 unless configured otherwise.
+    #[arg(long, value_parser = parse_scale)]
+    pub scale: Option<f32>,
+    /// Start paused, to step from the first instruction.
+    #[arg(long)]
+    pub paused: bool,
+    /// Emulate that many seconds as fast as possible without opening a
+    /// window, then exit. Exits with a failure status when the emulation
+    /// stops early or the recording fails.
+    #[arg(long, value_name = "SECONDS")]
+    pub headless: Option<f64>,
+    /// Print every instruction executed.
+    #[arg(long)]
+    pub trace: bool,
+    /// Print the disassembly of the ROM and exit.
+    #[arg(long)]
+    pub disassemble: bool,
+    /// WAV file to record the audio to.
+    #[arg(long, value_name = "FILE")]
+    pub record: Option<PathBuf>,
+    /// Also record each channel to its own file.
+    #[arg(long, requires = "record")]
+    pub stems: bool,
+    /// GBS file to play instead of a ROM.
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["rom", "boot_rom", "disassemble"])]
+    pub gbs: Option<PathBuf>,
+    /// GBS track to start with, from 1.
+    #[arg(long, requires = "gbs")]
+    pub track: Option<u8>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ModelOption {
+    Dmg,
+    Cgb,
+}
+
+impl From<ModelOption> for Model {
+    fn from(model: ModelOption) -> Self {
+        return match model {
+            ModelOption::Dmg => Model::DMG,
+            ModelOption::Cgb => Model::CGB,
+        };
+    }
+}
+
+fn parse_scale(value: &str) -> Result<f32, String> {
+    let scale: f32 = value
+        .parse()
+        .map_err(|_| format!("{} is not a number", value))?;
+    if !SCALES.contains(&scale) {
+        return Err(format!(
+            "the scale must be between {} and {}",
+            SCALES.start(),
+            SCALES.end()
+        ));
+    }
+    return Ok(scale);
+}
@@ -0,0 +1,212 @@
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::cli::{ModelOption, SCALES};
+use crate::hardware::memory::Model;
+use crate::hardware::ppu::{Color, DMG_PALETTE};
+use crate::vue::bindings::InputConfig;
+
+const FILE_NAME: &str = "config.toml";
+
+/// Settings kept between runs in the user configuration directory, written
+/// back whenever they are changed in the UI. The command-line options take
+/// precedence over them without being saved.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Hardware to emulate, picked from the cartridge header when unset.
+    pub model: Option<ModelOption>,
+    /// Where the save states and cheats go, next to the ROM when unset.
+    pub save_dir: Option<PathBuf>,
+    /// DMG shades, from lightest to darkest.
+    pub palette: [Color; 4],
+    pub window: WindowConfig,
+    pub audio: AudioConfig,
+    pub boot_roms: BootRomConfig,
+    pub debugger: DebuggerConfig,
+    pub input: InputConfig,
+    /// Set when the file could not be read, so that it is not replaced by
+    /// the defaults used instead.
+    #[serde(skip)]
+    read_only: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WindowConfig {
+    /// Inner size, in points.
+    pub width: f32,
+    pub height: f32,
+    /// Size of a Game Boy pixel on screen.
+    pub scale: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AudioConfig {
+    /// In percent.
+    pub volume: u8,
+    pub muted: bool,
+}
+
+/// Boot ROM run before the cartridge on each model, none when unset.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BootRomConfig {
+    pub dmg: Option<PathBuf>,
+    pub cgb: Option<PathBuf>,
+}
+
+/// Panels shown around the screen.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DebuggerConfig {
+    pub memory_panel: bool,
+    pub tools_panel: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            model: None,
+            save_dir: None,
+            palette: DMG_PALETTE,
+            window: WindowConfig::default(),
+            audio: AudioConfig::default(),
+            boot_roms: BootRomConfig::default(),
+            debugger: DebuggerConfig::default(),
+            input: InputConfig::default(),
+            read_only: false,
+        }
+    }
+}
+
+impl Default for WindowConfig {
+    fn default() -> Self {
+        Self {
+            width: 1280.0,
+            height: 800.0,
+            scale: 3.0,
+        }
+    }
+}
+
+impl Default for AudioConfig {
+    fn default() -> Self {
+        Self {
+            volume: 100,
+            muted: false,
+        }
+    }
+}
+
+impl Default for DebuggerConfig {
+    fn default() -> Self {
+        Self {
+            memory_panel: true,
+            tools_panel: true,
+        }
+    }
+}
+
+impl AudioConfig {
+    /// Factor the samples are played at.
+    pub fn get_gain(&self) -> f32 {
+        if self.muted {
+            return 0.0;
+        }
+        return self.volume.min(100) as f32 / 100.0;
+    }
+}
+
+impl BootRomConfig {
+    pub fn get(&self, model: Model) -> Option<&PathBuf> {
+        return match model {
+            Model::DMG => self.dmg.as_ref(),
+            Model::CGB => self.cgb.as_ref(),
+        };
+    }
+
+    pub fn set(&mut self, model: Model, path: Option<PathBuf>) {
+        match model {
+            Model::DMG => self.dmg = path,
+            Model::CGB => self.cgb = path,
+        }
+    }
+}
+
+impl Config {
+    /// Reads the settings saved by a previous run, the defaults when there
+    /// are none or they cannot be read. The file is then left as it is
+    /// until the next run, for the user to fix it.
+    pub fn load() -> Self {
+        let Some(path) = file_path() else {
+            return Self::default();
+        };
+        let read_only = Self {
+            read_only: true,
+            ..Self::default()
+        };
+        let contents = match std::fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Self::default(),
+            Err(error) => {
+                eprintln!("Could not read {}: {}", path.display(), error);
+                return read_only;
+            }
+        };
+        let mut config = match toml::from_str::<Self>(&contents) {
+            Ok(config) => config,
+            Err(error) => {
+                eprintln!(
+                    "Invalid configuration in {}, the changes made will not be saved: {}",
+                    path.display(),
+                    error
+                );
+                return read_only;
+            }
+        };
+        config.sanitize();
+        return config;
+    }
+
+    /// Brings the values out of range back in it.
+    fn sanitize(&mut self) {
+        let defaults = WindowConfig::default();
+        let window = &mut self.window;
+        window.scale = if window.scale.is_nan() {
+            defaults.scale
+        } else {
+            window.scale.clamp(*SCALES.start(), *SCALES.end())
+        };
+        if !(window.width.is_finite() && window.width > 0.0) {
+            window.width = defaults.width;
+        }
+        if !(window.height.is_finite() && window.height > 0.0) {
+            window.height = defaults.height;
+        }
+        if self.input.profiles.is_empty() {
+            self.input = InputConfig::default();
+        }
+    }
+
+    pub fn save(&self) {
+        if self.read_only {
+            return;
+        }
+        let Some(path) = file_path() else {
+            return;
+        };
+        let contents = toml::to_string(self).expect("the configuration is always serializable");
+        let result = std::fs::create_dir_all(path.parent().unwrap())
+            .and_then(|()| std::fs::write(&path, contents));
+        if let Err(error) = result {
+            eprintln!("Could not save the configuration: {}", error);
+        }
+    }
+}
+
+fn file_path() -> Option<PathBuf> {
+    return dirs::config_dir().map(|dir| dir.join(env!("CARGO_PKG_NAME")).join(FILE_NAME));
+}
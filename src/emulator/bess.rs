@@ -0,0 +1,487 @@
+use crate::hardware::apu::{APU_END, APU_START, NR14, NR24, NR34, NR44, NR52};
+use crate::hardware::cpu::{CPU, Register};
+use crate::hardware::memory::{BCPD, DIV, DMA, HDMA5, KEY1, MemoryMap, Model, OCPD, Speed};
+use crate::hardware::ppu::OAM;
+
+/// Best Effort Save State, the save state format shared with SameBoy and
+/// other emulators. The file is made of raw memory buffers followed by
+/// blocks, each an identifier and a length, then a footer pointing at the
+/// first block.
+const FOOTER_SIGNATURE: &[u8; 4] = b"BESS";
+const MAJOR_VERSION: u16 = 1;
+const MINOR_VERSION: u16 = 1;
+const CORE_SIZE: usize = 0xD0;
+/// Offset of the IO registers in the CORE block.
+const CORE_IO: usize = 0x18;
+/// Offset of the size and offset pairs of the memory buffers in the CORE
+/// block, in the order of `Buffer`.
+const CORE_BUFFERS: usize = 0x98;
+const IO_START: usize = 0xFF00;
+const IO_SIZE: usize = 0x80;
+/// DMG compatibility mode register of the CGB, set by its boot ROM.
+const KEY0: usize = 0xFF4C;
+const KEY0_DMG_MODE: u8 = 0x04;
+const IE: usize = 0xFFFF;
+const CARTRIDGE_RAM: usize = 0xA000;
+const CARTRIDGE_RAM_SIZE: usize = 0x2000;
+const OAM_SIZE: usize = 0xA0;
+/// Unusable area after OAM, saved in the XOAM block.
+const EXTRA_OAM_SIZE: usize = 0x60;
+const HRAM: usize = 0xFF80;
+const HRAM_SIZE: usize = 0x7F;
+/// Header bytes identifying the ROM a state belongs to, in the INFO block.
+const TITLE: usize = 0x134;
+const TITLE_SIZE: usize = 0x10;
+const GLOBAL_CHECKSUM: usize = 0x14E;
+/// Only the first two banks are mapped, there is no mapper.
+const ROM_SIZE: usize = 0x8000;
+/// Registers restored other than by writing them as the CPU would.
+const SPECIAL_REGISTERS: [usize; 7] = [DIV, DMA, KEY1, HDMA5, BCPD, OCPD, NR52];
+
+#[derive(Debug)]
+pub enum BessError {
+    Io(std::io::Error),
+    InvalidFooter,
+    Truncated,
+    MissingCore,
+    UnsupportedVersion(u16),
+    UnsupportedModel(String),
+    /// The state was saved with another ROM, whose title is given.
+    OtherRom(String),
+}
+
+impl std::fmt::Display for BessError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        return match self {
+            BessError::Io(error) => write!(f, "{}", error),
+            BessError::InvalidFooter => write!(f, "not a BESS file"),
+            BessError::Truncated => write!(f, "truncated BESS file"),
+            BessError::MissingCore => write!(f, "no CORE block"),
+            BessError::UnsupportedVersion(version) => {
+                write!(f, "unsupported BESS version {}", version)
+            }
+            BessError::UnsupportedModel(model) => write!(f, "unsupported model {:?}", model),
+            BessError::OtherRom(title) => write!(f, "saved with another ROM ({})", title),
+        };
+    }
+}
+
+impl From<std::io::Error> for BessError {
+    fn from(error: std::io::Error) -> Self {
+        return BessError::Io(error);
+    }
+}
+
+/// Memory buffers pointed at by the CORE block, in order.
+#[derive(Clone, Copy)]
+enum Buffer {
+    Ram,
+    Vram,
+    CartridgeRam,
+    Oam,
+    Hram,
+    BgPalettes,
+    ObjPalettes,
+}
+
+impl Buffer {
+    const ALL: [Buffer; 7] = [
+        Buffer::Ram,
+        Buffer::Vram,
+        Buffer::CartridgeRam,
+        Buffer::Oam,
+        Buffer::Hram,
+        Buffer::BgPalettes,
+        Buffer::ObjPalettes,
+    ];
+
+    fn read(&self, mem_map: &MemoryMap) -> Vec<u8> {
+        use Buffer::*;
+        let cgb = mem_map.get_model() == Model::CGB;
+        return match self {
+            Ram => mem_map.get_wram()[..if cgb { 0x8000 } else { 0x2000 }].to_vec(),
+            Vram => mem_map.get_vram()[..if cgb { 0x4000 } else { 0x2000 }].to_vec(),
+            CartridgeRam => peek_range(mem_map, CARTRIDGE_RAM, CARTRIDGE_RAM_SIZE),
+            Oam => peek_range(mem_map, OAM, OAM_SIZE),
+            Hram => peek_range(mem_map, HRAM, HRAM_SIZE),
+            BgPalettes if cgb => mem_map.get_palette_ram(false).to_vec(),
+            ObjPalettes if cgb => mem_map.get_palette_ram(true).to_vec(),
+            BgPalettes | ObjPalettes => vec![],
+        };
+    }
+
+    /// Copies `bytes` in, a buffer of another size filling what fits.
+    fn write(&self, mem_map: &mut MemoryMap, bytes: &[u8]) {
+        use Buffer::*;
+        let destination = match self {
+            Ram => mem_map.get_wram_mut(),
+            Vram => mem_map.get_vram_mut(),
+            BgPalettes => mem_map.get_palette_ram_mut(false),
+            ObjPalettes => mem_map.get_palette_ram_mut(true),
+            CartridgeRam => return poke_range(mem_map, CARTRIDGE_RAM, CARTRIDGE_RAM_SIZE, bytes),
+            Oam => return poke_range(mem_map, OAM, OAM_SIZE, bytes),
+            Hram => return poke_range(mem_map, HRAM, HRAM_SIZE, bytes),
+        };
+        let length = bytes.len().min(destination.len());
+        destination[..length].copy_from_slice(&bytes[..length]);
+    }
+}
+
+/// Saves the machine as a BESS file. The PPU and APU internals are not part
+/// of the format, the other emulator rebuilds them from the registers.
+pub fn export(cpu: &CPU, mem_map: &MemoryMap) -> Vec<u8> {
+    let mut file = vec![];
+    let mut core = Vec::with_capacity(CORE_SIZE);
+    core.extend_from_slice(&MAJOR_VERSION.to_le_bytes());
+    core.extend_from_slice(&MINOR_VERSION.to_le_bytes());
+    core.extend_from_slice(match mem_map.get_model() {
+        Model::DMG => b"GDB ",
+        Model::CGB => b"CCE ",
+    });
+    for register in [
+        Register::PC,
+        Register::AF,
+        Register::BC,
+        Register::DE,
+        Register::HL,
+        Register::SP,
+    ] {
+        core.extend_from_slice(&cpu.read_word(&register).to_le_bytes());
+    }
+    // Always running, HALT and STOP do not suspend the CPU yet.
+    core.extend_from_slice(&[cpu.get_ime() as u8, peek(mem_map, IE), 0, 0]);
+    for address in IO_START..IO_START + IO_SIZE {
+        core.push(match address {
+            APU_START..=APU_END => mem_map.get_apu().peek_register(address),
+            KEY0 if mem_map.get_model() == Model::CGB && !mem_map.is_cgb_mode() => KEY0_DMG_MODE,
+            _ => peek(mem_map, address),
+        });
+    }
+    for buffer in Buffer::ALL {
+        let bytes = buffer.read(mem_map);
+        core.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+        core.extend_from_slice(&(file.len() as u32).to_le_bytes());
+        file.extend_from_slice(&bytes);
+    }
+
+    let first_block = file.len() as u32;
+    let name = format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+    write_block(&mut file, b"NAME", name.as_bytes());
+    let mut info = peek_range(mem_map, TITLE, TITLE_SIZE);
+    info.extend(peek_range(mem_map, GLOBAL_CHECKSUM, 2));
+    write_block(&mut file, b"INFO", &info);
+    write_block(&mut file, b"CORE", &core);
+    write_block(
+        &mut file,
+        b"XOAM",
+        &peek_range(mem_map, OAM + OAM_SIZE, EXTRA_OAM_SIZE),
+    );
+    // No mapper is emulated, so there are no mapper registers to restore.
+    write_block(&mut file, b"MBC ", &[]);
+    write_block(&mut file, b"END ", &[]);
+    file.extend_from_slice(&first_block.to_le_bytes());
+    file.extend_from_slice(FOOTER_SIGNATURE);
+    return file;
+}
+
+/// Rebuilds the CPU and memory saved in a BESS file, for the ROM mapped in
+/// `current`. The PPU starts over from the saved registers, and blocks
+/// unknown to this emulator are skipped.
+pub fn import(bytes: &[u8], current: &MemoryMap) -> Result<(CPU, MemoryMap), BessError> {
+    if bytes.len() < 8 || &bytes[bytes.len() - 4..] != FOOTER_SIGNATURE {
+        return Err(BessError::InvalidFooter);
+    }
+    let mut offset = read_u32(bytes, bytes.len() - 8)? as usize;
+    let mut core = None;
+    let mut extra_oam = None;
+    while offset < bytes.len() {
+        let id = slice(bytes, offset, 4)?;
+        let length = read_u32(bytes, offset + 4)? as usize;
+        let contents = slice(bytes, offset + 8, length)?;
+        match id {
+            b"INFO" => check_rom(contents, current)?,
+            b"CORE" => core = Some(contents),
+            b"XOAM" => extra_oam = Some(contents),
+            b"END " => break,
+            // Mapper registers are ignored with no mapper to restore them to.
+            _ => (),
+        }
+        offset += 8 + length;
+    }
+    let core = core.ok_or(BessError::MissingCore)?;
+    if core.len() < CORE_SIZE {
+        return Err(BessError::Truncated);
+    }
+    let major_version = u16::from_le_bytes([core[0], core[1]]);
+    if major_version != MAJOR_VERSION {
+        return Err(BessError::UnsupportedVersion(major_version));
+    }
+    let model = match core[4] {
+        // Super Game Boys run as a DMG.
+        b'G' | b'S' => Model::DMG,
+        b'C' => Model::CGB,
+        _ => {
+            let model = String::from_utf8_lossy(&core[4..8]).into_owned();
+            return Err(BessError::UnsupportedModel(model));
+        }
+    };
+
+    let mut mem_map = MemoryMap::new(model);
+    let rom = peek_range(current, 0, ROM_SIZE);
+    poke_range(&mut mem_map, 0, ROM_SIZE, &rom);
+    let io = &core[CORE_IO..CORE_IO + IO_SIZE];
+    if model == Model::CGB && io[KEY0 - IO_START] & KEY0_DMG_MODE != 0 {
+        mem_map.set_dmg_compatibility(true);
+    }
+    for (index, buffer) in Buffer::ALL.iter().enumerate() {
+        let pointer = CORE_BUFFERS + index * 8;
+        let size = read_u32(core, pointer)? as usize;
+        let offset = read_u32(core, pointer + 4)? as usize;
+        buffer.write(&mut mem_map, slice(bytes, offset, size)?);
+    }
+    if let Some(extra_oam) = extra_oam {
+        poke_range(&mut mem_map, OAM + OAM_SIZE, EXTRA_OAM_SIZE, extra_oam);
+    }
+    restore_registers(&mut mem_map, io);
+    write(&mut mem_map, IE, core[0x15]);
+
+    let mut cpu = CPU::new();
+    for (index, register) in [
+        Register::PC,
+        Register::AF,
+        Register::BC,
+        Register::DE,
+        Register::HL,
+        Register::SP,
+    ]
+    .iter()
+    .enumerate()
+    {
+        let offset = 8 + index * 2;
+        cpu.write_word(
+            register,
+            u16::from_le_bytes([core[offset], core[offset + 1]]),
+        );
+    }
+    cpu.set_ime(core[0x14] != 0);
+    return Ok((cpu, mem_map));
+}
+
+/// Writes the saved IO registers as the CPU would, except for the ones
+/// whose write has side effects: the DMA transfers are not started again,
+/// DIV and the CPU speed are set directly and the palettes come from their
+/// own buffers. The APU is powered first for the other writes to stick,
+/// and channels that were stopped are not triggered again.
+fn restore_registers(mem_map: &mut MemoryMap, io: &[u8]) {
+    let register = |address: usize| io[address - IO_START];
+    write(mem_map, NR52, register(NR52));
+    mem_map.set_div(register(DIV));
+    if mem_map.get_model() == Model::CGB && register(KEY1) & 0x80 != 0 {
+        mem_map.set_speed(Speed::Double);
+    }
+    let channels_on = register(NR52);
+    for address in IO_START..IO_START + IO_SIZE {
+        if SPECIAL_REGISTERS.contains(&address) {
+            continue;
+        }
+        let mut byte = register(address);
+        if let Some(channel) = [NR14, NR24, NR34, NR44]
+            .iter()
+            .position(|&control| control == address)
+            && channels_on & (1 << channel) == 0
+        {
+            byte &= 0x7F;
+        }
+        write(mem_map, address, byte);
+    }
+}
+
+/// Compares the title and global checksum of the INFO block with the ROM.
+fn check_rom(info: &[u8], current: &MemoryMap) -> Result<(), BessError> {
+    if info.len() < TITLE_SIZE + 2 {
+        return Err(BessError::Truncated);
+    }
+    let mut expected = peek_range(current, TITLE, TITLE_SIZE);
+    expected.extend(peek_range(current, GLOBAL_CHECKSUM, 2));
+    if info[..TITLE_SIZE + 2] != expected[..] {
+        let title = String::from_utf8_lossy(&info[..TITLE_SIZE]);
+        return Err(BessError::OtherRom(
+            title.trim_end_matches('\0').to_string(),
+        ));
+    }
+    return Ok(());
+}
+
+fn write_block(file: &mut Vec<u8>, id: &[u8; 4], contents: &[u8]) {
+    file.extend_from_slice(id);
+    file.extend_from_slice(&(contents.len() as u32).to_le_bytes());
+    file.extend_from_slice(contents);
+}
+
+fn slice(bytes: &[u8], offset: usize, length: usize) -> Result<&[u8], BessError> {
+    return bytes
+        .get(offset..offset.checked_add(length).ok_or(BessError::Truncated)?)
+        .ok_or(BessError::Truncated);
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Result<u32, BessError> {
+    let bytes = slice(bytes, offset, 4)?;
+    return Ok(u32::from_le_bytes(bytes.try_into().unwrap()));
+}
+
+fn peek(mem_map: &MemoryMap, address: usize) -> u8 {
+    return mem_map.peek_byte(address).unwrap();
+}
+
+fn write(mem_map: &mut MemoryMap, address: usize, byte: u8) {
+    mem_map.write_byte(address, byte).unwrap();
+}
+
+fn peek_range(mem_map: &MemoryMap, address: usize, length: usize) -> Vec<u8> {
+    return (address..address + length)
+        .map(|address| peek(mem_map, address))
+        .collect();
+}
+
+/// Pokes up to `length` bytes from `address`, as many as `bytes` holds.
+fn poke_range(mem_map: &mut MemoryMap, address: usize, length: usize, bytes: &[u8]) {
+    for (offset, byte) in bytes.iter().take(length).enumerate() {
+        mem_map.poke_byte(address + offset, *byte).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hardware::ppu::{BGP, SCX, SCY, WY};
+
+    const REGISTERS: [Register; 6] = [
+        Register::PC,
+        Register::AF,
+        Register::BC,
+        Register::DE,
+        Register::HL,
+        Register::SP,
+    ];
+
+    /// Machine running a ROM titled `title`, with some of everything the
+    /// format saves set.
+    fn machine(model: Model, title: &str) -> (CPU, MemoryMap) {
+        let mut mem_map = MemoryMap::new(model);
+        poke_range(&mut mem_map, TITLE, TITLE_SIZE, title.as_bytes());
+        poke_range(&mut mem_map, GLOBAL_CHECKSUM, 2, &[0x12, 0x34]);
+        for (index, byte) in mem_map.get_wram_mut().iter_mut().enumerate() {
+            *byte = (index * 7) as u8;
+        }
+        for (index, byte) in mem_map.get_vram_mut().iter_mut().enumerate() {
+            *byte = (index * 3) as u8;
+        }
+        for (index, byte) in mem_map.get_palette_ram_mut(true).iter_mut().enumerate() {
+            *byte = index as u8;
+        }
+        poke_range(&mut mem_map, OAM, 4, &[0x10, 0x20, 0x30, 0x40]);
+        poke_range(&mut mem_map, HRAM, 3, &[0xAA, 0xBB, 0xCC]);
+        poke_range(&mut mem_map, CARTRIDGE_RAM, 2, &[0x55, 0x66]);
+        for (address, byte) in [
+            (SCY, 0x12),
+            (SCX, 0x34),
+            (BGP, 0xE4),
+            (WY, 0x56),
+            (IE, 0x1F),
+        ] {
+            write(&mut mem_map, address, byte);
+        }
+        let mut cpu = CPU::new();
+        for (index, register) in REGISTERS.iter().enumerate() {
+            cpu.write_word(register, 0x1110 * (index as u16 + 1));
+        }
+        cpu.set_ime(true);
+        return (cpu, mem_map);
+    }
+
+    /// File made of `blocks` alone, with the footer pointing at the first.
+    fn file(blocks: &[(&[u8; 4], &[u8])]) -> Vec<u8> {
+        let mut file = vec![];
+        for (id, contents) in blocks {
+            write_block(&mut file, id, contents);
+        }
+        file.extend_from_slice(&0u32.to_le_bytes());
+        file.extend_from_slice(FOOTER_SIGNATURE);
+        return file;
+    }
+
+    #[test]
+    fn round_trips() {
+        for model in [Model::DMG, Model::CGB] {
+            let (cpu, mem_map) = machine(model, "ROUND TRIP");
+            let (imported_cpu, imported) = import(&export(&cpu, &mem_map), &mem_map).unwrap();
+            for register in &REGISTERS {
+                assert_eq!(imported_cpu.read_word(register), cpu.read_word(register));
+            }
+            assert!(imported_cpu.get_ime());
+            assert_eq!(imported.get_model(), model);
+            assert_eq!(imported.is_cgb_mode(), model == Model::CGB);
+            for address in [SCY, SCX, BGP, WY, IE] {
+                assert_eq!(peek(&imported, address), peek(&mem_map, address));
+            }
+            for buffer in Buffer::ALL {
+                assert_eq!(buffer.read(&imported), buffer.read(&mem_map));
+            }
+        }
+    }
+
+    #[test]
+    fn keeps_the_dmg_compatibility_mode() {
+        let (cpu, mut mem_map) = machine(Model::CGB, "DMG GAME");
+        mem_map.set_dmg_compatibility(true);
+        let (_, imported) = import(&export(&cpu, &mem_map), &mem_map).unwrap();
+        assert_eq!(imported.get_model(), Model::CGB);
+        assert!(!imported.is_cgb_mode());
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        let (_, mem_map) = machine(Model::DMG, "GAME");
+        assert!(matches!(
+            import(b"not a save state", &mem_map),
+            Err(BessError::InvalidFooter)
+        ));
+    }
+
+    #[test]
+    fn rejects_truncated_blocks() {
+        let (_, mem_map) = machine(Model::DMG, "GAME");
+        let mut bytes = file(&[(b"NAME", b"name")]);
+        // Claims more bytes than left.
+        bytes[4] = 0xFF;
+        assert!(matches!(
+            import(&bytes, &mem_map),
+            Err(BessError::Truncated)
+        ));
+        let bytes = file(&[(b"CORE", &[1, 0, 1, 0]), (b"END ", &[])]);
+        assert!(matches!(
+            import(&bytes, &mem_map),
+            Err(BessError::Truncated)
+        ));
+    }
+
+    #[test]
+    fn rejects_files_without_core() {
+        let (_, mem_map) = machine(Model::DMG, "GAME");
+        let bytes = file(&[(b"NAME", b"name"), (b"END ", &[])]);
+        assert!(matches!(
+            import(&bytes, &mem_map),
+            Err(BessError::MissingCore)
+        ));
+    }
+
+    #[test]
+    fn rejects_states_of_other_roms() {
+        let (cpu, mem_map) = machine(Model::DMG, "SAVED");
+        let (_, other) = machine(Model::DMG, "LOADED");
+        assert!(matches!(
+            import(&export(&cpu, &mem_map), &other),
+            Err(BessError::OtherRom(title)) if title == "SAVED"
+        ));
+    }
+}
@@ -0,0 +1,187 @@
+use std::path::PathBuf;
+
+use crate::config::BootRomConfig;
+use crate::hardware::cpu::{CPU, Register};
+use crate::hardware::memory::{CGB_BOOT_ROM_SIZE, DMG_BOOT_ROM_SIZE, MemoryMap, Model};
+use crate::hardware::ppu::palette;
+
+/// Only the first two banks are mapped, there is no mapper.
+const MAPPED_SIZE: usize = 0x8000;
+const CGB_FLAG: usize = 0x143;
+
+#[derive(Debug)]
+pub enum CartridgeError {
+    /// The boot ROM does not have the size of the model it runs on.
+    BootRomSize(Model, usize),
+    ReadBootRom(PathBuf, std::io::Error),
+}
+
+impl std::fmt::Display for CartridgeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        return match self {
+            CartridgeError::BootRomSize(model, size) => write!(
+                f,
+                "a {:?} boot ROM is {} bytes, not {}",
+                model,
+                boot_rom_size(*model),
+                size
+            ),
+            CartridgeError::ReadBootRom(path, error) => {
+                write!(f, "could not read {}: {}", path.display(), error)
+            }
+        };
+    }
+}
+
+/// ROM loaded along with the hardware it runs on, kept to power the
+/// machine on again.
+#[derive(Clone)]
+pub struct Cartridge {
+    rom: Vec<u8>,
+    /// Forced hardware, otherwise picked from the cartridge header.
+    model: Option<Model>,
+    boot_rom: Option<Vec<u8>>,
+}
+
+impl Cartridge {
+    /// Runs `rom` on `model`, or on the hardware its header asks for.
+    pub fn new(rom: Vec<u8>, model: Option<Model>) -> Self {
+        return Self {
+            rom,
+            model,
+            boot_rom: None,
+        };
+    }
+
+    /// Runs `boot_rom` before the cartridge, which must be the boot ROM of
+    /// the model the cartridge runs on.
+    pub fn set_boot_rom(&mut self, boot_rom: Vec<u8>) -> Result<(), CartridgeError> {
+        let model = self.get_model();
+        if boot_rom.len() != boot_rom_size(model) {
+            return Err(CartridgeError::BootRomSize(model, boot_rom.len()));
+        }
+        self.boot_rom = Some(boot_rom);
+        return Ok(());
+    }
+
+    pub fn get_rom(&self) -> &[u8] {
+        return &self.rom;
+    }
+
+    /// Whether the ROM has banks beyond the ones mapped.
+    pub fn is_truncated(&self) -> bool {
+        return self.rom.len() > MAPPED_SIZE;
+    }
+
+    pub fn get_model(&self) -> Model {
+        return pick_model(self.model, &self.rom);
+    }
+
+    fn is_cgb_cartridge(&self) -> bool {
+        return is_cgb_rom(&self.rom);
+    }
+
+    /// Sets up the machine as at power on. Without a boot ROM the registers
+    /// are left as the boot ROM would, otherwise it runs from 0.
+    pub fn power_on(&self) -> (MemoryMap, CPU) {
+        let model = self.get_model();
+        let mut mem_map = MemoryMap::new(model);
+        if model == Model::CGB && !self.is_cgb_cartridge() {
+            palette::load_dmg_compatibility(&mut mem_map);
+        }
+        let mut cpu = CPU::new();
+        match &self.boot_rom {
+            Some(boot_rom) => {
+                mem_map.set_boot_rom(boot_rom.clone());
+                cpu.write_word(&Register::PC, 0);
+            }
+            None if model == Model::CGB => {
+                // Games detect a CGB by the value left in A by the boot ROM.
+                cpu.write_byte(&Register::A, 0x11);
+            }
+            None => (),
+        }
+        let mapped = &self.rom[..self.rom.len().min(MAPPED_SIZE)];
+        mem_map.write_bytes(0, mapped.to_vec()).unwrap();
+        return (mem_map, cpu);
+    }
+}
+
+/// How the ROMs opened are run, on the forced hardware or the one their
+/// header asks for, with the boot ROM set for that model if any.
+#[derive(Debug, Clone, Default)]
+pub struct Hardware {
+    pub model: Option<Model>,
+    pub boot_roms: BootRomConfig,
+}
+
+impl Hardware {
+    /// Sets `rom` up to run.
+    pub fn load(&self, rom: Vec<u8>) -> Result<Cartridge, CartridgeError> {
+        let mut cartridge = Cartridge::new(rom, self.model);
+        if let Some(path) = self.boot_roms.get(cartridge.get_model()) {
+            let boot_rom = std::fs::read(path)
+                .map_err(|error| CartridgeError::ReadBootRom(path.clone(), error))?;
+            cartridge.set_boot_rom(boot_rom)?;
+        }
+        return Ok(cartridge);
+    }
+
+    /// Hardware `rom` runs on.
+    pub fn get_model(&self, rom: &[u8]) -> Model {
+        return pick_model(self.model, rom);
+    }
+}
+
+/// `model` if forced, otherwise the hardware the header of `rom` asks for.
+fn pick_model(model: Option<Model>, rom: &[u8]) -> Model {
+    return model.unwrap_or(if is_cgb_rom(rom) {
+        Model::CGB
+    } else {
+        Model::DMG
+    });
+}
+
+fn is_cgb_rom(rom: &[u8]) -> bool {
+    return rom.get(CGB_FLAG).is_some_and(|flag| flag & 0x80 != 0);
+}
+
+fn boot_rom_size(model: Model) -> usize {
+    return match model {
+        Model::DMG => DMG_BOOT_ROM_SIZE,
+        Model::CGB => CGB_BOOT_ROM_SIZE,
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cgb_rom() -> Vec<u8> {
+        let mut rom = vec![0; MAPPED_SIZE];
+        rom[CGB_FLAG] = 0x80;
+        return rom;
+    }
+
+    #[test]
+    fn accepts_the_boot_rom_of_the_model() {
+        let mut cartridge = Cartridge::new(vec![0; MAPPED_SIZE], None);
+        assert!(cartridge.set_boot_rom(vec![0; DMG_BOOT_ROM_SIZE]).is_ok());
+        let mut cartridge = Cartridge::new(cgb_rom(), None);
+        assert!(cartridge.set_boot_rom(vec![0; CGB_BOOT_ROM_SIZE]).is_ok());
+    }
+
+    #[test]
+    fn rejects_the_boot_rom_of_another_model() {
+        let mut cartridge = Cartridge::new(cgb_rom(), None);
+        assert!(matches!(
+            cartridge.set_boot_rom(vec![0; DMG_BOOT_ROM_SIZE]),
+            Err(CartridgeError::BootRomSize(Model::CGB, DMG_BOOT_ROM_SIZE))
+        ));
+        let mut cartridge = Cartridge::new(cgb_rom(), Some(Model::DMG));
+        assert!(matches!(
+            cartridge.set_boot_rom(vec![0; CGB_BOOT_ROM_SIZE]),
+            Err(CartridgeError::BootRomSize(Model::DMG, CGB_BOOT_ROM_SIZE))
+        ));
+    }
+}
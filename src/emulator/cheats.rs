@@ -0,0 +1,318 @@
+use crate::hardware::memory::{MemoryMap, ROM_END, RomPatch};
+
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// First GameShark type writing to a given WRAM bank, the bank being in the
+/// lowest 3 bits.
+const WRAM_BANK_TYPE: u8 = 0x90;
+const WRAM_BANKED_START: u16 = 0xD000;
+const WRAM_BANKED_END: u16 = 0xE000;
+
+#[derive(Debug)]
+pub enum CheatError {
+    Empty,
+    /// Neither 8 hexadecimal digits for a GameShark code nor 6 or 9 for a
+    /// Game Genie one.
+    InvalidCode(String),
+    RomWrite(String),
+    BankOutsideWram(String),
+}
+
+impl std::fmt::Display for CheatError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        return match self {
+            CheatError::Empty => write!(f, "no code"),
+            CheatError::InvalidCode(code) => write!(
+                f,
+                "{} is neither a GameShark (01VVAAAA) nor a Game Genie (ABC-DEF or ABC-DEF-GHI) code",
+                code
+            ),
+            CheatError::RomWrite(code) => write!(
+                f,
+                "{} writes to the ROM, which takes a Game Genie code",
+                code
+            ),
+            CheatError::BankOutsideWram(code) => write!(
+                f,
+                "{} selects a WRAM bank for an address outside of D000-DFFF",
+                code
+            ),
+        };
+    }
+}
+
+#[derive(Debug)]
+pub enum CheatFileError {
+    Io(std::io::Error),
+    Invalid(toml::de::Error),
+}
+
+impl std::fmt::Display for CheatFileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        return match self {
+            CheatFileError::Io(error) => write!(f, "{}", error),
+            CheatFileError::Invalid(error) => write!(f, "invalid cheat list: {}", error),
+        };
+    }
+}
+
+/// One code, a cheat being made of one or more.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CheatCode {
+    /// Writes `value` to RAM at `address` every frame, in WRAM bank `bank`
+    /// when set. The cartridge RAM bank of the other types is ignored as
+    /// there is no mapper.
+    GameShark {
+        bank: Option<u8>,
+        address: u16,
+        value: u8,
+    },
+    GameGenie(RomPatch),
+}
+
+impl CheatCode {
+    pub fn parse(code: &str) -> Result<Self, CheatError> {
+        let digits: String = code.chars().filter(|c| *c != '-').collect();
+        let invalid = || CheatError::InvalidCode(code.to_string());
+        if !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(invalid());
+        }
+        let hex = |range: std::ops::Range<usize>| u32::from_str_radix(&digits[range], 16).unwrap();
+        let nibble = |index: usize| hex(index..index + 1) as u8;
+        return match digits.len() {
+            8 => {
+                let kind = hex(0..2) as u8;
+                let value = hex(2..4) as u8;
+                // The address is little endian.
+                let address = (hex(6..8) << 8 | hex(4..6)) as u16;
+                if (address as usize) < ROM_END {
+                    return Err(CheatError::RomWrite(code.to_string()));
+                }
+                let bank = (kind & 0xF8 == WRAM_BANK_TYPE).then_some(kind & 0x07);
+                if bank.is_some() && !(WRAM_BANKED_START..WRAM_BANKED_END).contains(&address) {
+                    return Err(CheatError::BankOutsideWram(code.to_string()));
+                }
+                Ok(CheatCode::GameShark {
+                    bank,
+                    address,
+                    value,
+                })
+            }
+            6 | 9 => {
+                let value = hex(0..2) as u8;
+                // Digits CDEF hold the address as FCDE, F inverted.
+                let address = ((nibble(5) ^ 0xF) as u16) << 12 | hex(2..5) as u16;
+                if address as usize >= ROM_END {
+                    return Err(invalid());
+                }
+                // Digits G and I hold the byte compared, rotated left by
+                // 2 and scrambled, H being unused.
+                let compare = (digits.len() == 9)
+                    .then(|| (nibble(6) << 4 | nibble(8)).rotate_right(2) ^ 0xBA);
+                Ok(CheatCode::GameGenie(RomPatch {
+                    address,
+                    value,
+                    compare,
+                }))
+            }
+            _ => Err(invalid()),
+        };
+    }
+}
+
+/// Named set of codes, enabled together.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Cheat {
+    pub name: String,
+    /// Codes separated by `+` or whitespace, as typed in.
+    pub code: String,
+    pub enabled: bool,
+}
+
+impl Cheat {
+    /// Makes a cheat out of the codes typed in, enabled.
+    pub fn new(name: String, code: String) -> Result<Self, CheatError> {
+        let cheat = Self {
+            name,
+            code: code.trim().to_string(),
+            enabled: true,
+        };
+        if cheat.parse()?.is_empty() {
+            return Err(CheatError::Empty);
+        }
+        return Ok(cheat);
+    }
+
+    pub fn parse(&self) -> Result<Vec<CheatCode>, CheatError> {
+        return self
+            .code
+            .split(|c: char| c == '+' || c.is_whitespace())
+            .filter(|code| !code.is_empty())
+            .map(CheatCode::parse)
+            .collect();
+    }
+}
+
+/// Cheats of a ROM as saved, see `path`.
+#[derive(Default, Serialize, Deserialize)]
+struct CheatFile {
+    #[serde(default)]
+    cheat: Vec<Cheat>,
+}
+
+/// Cheat list of the ROM at `rom`, next to it.
+pub fn path(rom: &Path) -> PathBuf {
+    return rom.with_extension("cht");
+}
+
+/// Reads the cheats saved for the ROM at `rom`, none when there are none.
+pub fn load(rom: &Path) -> Result<Vec<Cheat>, CheatFileError> {
+    let contents = match std::fs::read_to_string(path(rom)) {
+        Ok(contents) => contents,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(error) => return Err(CheatFileError::Io(error)),
+    };
+    let file: CheatFile = toml::from_str(&contents).map_err(CheatFileError::Invalid)?;
+    return Ok(file.cheat);
+}
+
+/// Saves the cheats of the ROM at `rom`, removing the file once there are
+/// none left.
+pub fn save(rom: &Path, cheats: &[Cheat]) -> std::io::Result<()> {
+    let path = path(rom);
+    if cheats.is_empty() {
+        return match std::fs::remove_file(&path) {
+            Err(error) if error.kind() != std::io::ErrorKind::NotFound => Err(error),
+            _ => Ok(()),
+        };
+    }
+    let file = CheatFile {
+        cheat: cheats.to_vec(),
+    };
+    let contents = toml::to_string(&file).expect("cheats are always serializable");
+    return std::fs::write(&path, contents);
+}
+
+/// Codes of the enabled cheats, the ones failing to parse left out.
+pub fn enabled_codes(cheats: &[Cheat]) -> Vec<CheatCode> {
+    return cheats
+        .iter()
+        .filter(|cheat| cheat.enabled)
+        .filter_map(|cheat| cheat.parse().ok())
+        .flatten()
+        .collect();
+}
+
+/// Applies the GameShark codes among `codes`, done once per frame.
+pub fn write_ram(mem_map: &mut MemoryMap, codes: &[CheatCode]) {
+    for code in codes {
+        if let CheatCode::GameShark {
+            bank,
+            address,
+            value,
+        } = *code
+        {
+            match bank {
+                Some(bank) => mem_map.poke_wram(bank as usize, address as usize, value),
+                None => mem_map.poke_byte(address as usize, value).unwrap(),
+            }
+        }
+    }
+}
+
+/// ROM patches of the Game Genie codes among `codes`.
+pub fn rom_patches(codes: &[CheatCode]) -> Vec<RomPatch> {
+    return codes
+        .iter()
+        .filter_map(|code| match code {
+            CheatCode::GameGenie(patch) => Some(*patch),
+            CheatCode::GameShark { .. } => None,
+        })
+        .collect();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_gameshark_codes() {
+        assert_eq!(
+            CheatCode::parse("01FF23C1").unwrap(),
+            CheatCode::GameShark {
+                bank: None,
+                address: 0xC123,
+                value: 0xFF,
+            }
+        );
+    }
+
+    #[test]
+    fn parses_banked_gameshark_codes() {
+        assert_eq!(
+            CheatCode::parse("930510D0").unwrap(),
+            CheatCode::GameShark {
+                bank: Some(3),
+                address: 0xD010,
+                value: 0x05,
+            }
+        );
+        assert!(matches!(
+            CheatCode::parse("930510C0"),
+            Err(CheatError::BankOutsideWram(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_gameshark_codes_writing_to_rom() {
+        assert!(matches!(
+            CheatCode::parse("01FF2345"),
+            Err(CheatError::RomWrite(_))
+        ));
+    }
+
+    #[test]
+    fn parses_game_genie_codes() {
+        assert_eq!(
+            CheatCode::parse("3C1-23B").unwrap(),
+            CheatCode::GameGenie(RomPatch {
+                address: 0x4123,
+                value: 0x3C,
+                compare: None,
+            })
+        );
+        assert_eq!(
+            CheatCode::parse("3C1-23B-8EA").unwrap(),
+            CheatCode::GameGenie(RomPatch {
+                address: 0x4123,
+                value: 0x3C,
+                compare: Some(0x18),
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_codes() {
+        for code in ["01FF23C", "01FF23CG", "3C1-230", "3C1-23B-8"] {
+            assert!(
+                matches!(CheatCode::parse(code), Err(CheatError::InvalidCode(_))),
+                "{}",
+                code
+            );
+        }
+    }
+
+    #[test]
+    fn splits_cheats_into_codes() {
+        let cheat = Cheat::new(
+            "Lives".to_string(),
+            " 01FF23C1+3C1-23B 010123C1 ".to_string(),
+        );
+        assert_eq!(cheat.unwrap().parse().unwrap().len(), 3);
+        assert!(matches!(
+            Cheat::new("Nothing".to_string(), " + ".to_string()),
+            Err(CheatError::Empty)
+        ));
+    }
+}
@@ -0,0 +1,205 @@
+use super::cartridge::CartridgeError;
+use super::patch::{self, PatchError};
+
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// Extensions of the ROMs looked for, in archives as well as on disk.
+pub const ROM_EXTENSIONS: [&str; 3] = ["gb", "gbc", "sgb"];
+pub const ARCHIVE_EXTENSIONS: [&str; 2] = ["zip", "gz"];
+/// Largest ROM of a cartridge, more is likely a decompression bomb.
+const MAX_ROM_SIZE: u64 = 8 << 20;
+
+#[derive(Debug)]
+pub enum LoadError {
+    Io(std::io::Error),
+    Zip(zip::result::ZipError),
+    Gzip(std::io::Error),
+    NoRom,
+    MissingEntry(String),
+    TooLarge,
+    Patch(PathBuf, PatchError),
+    Cartridge(CartridgeError),
+}
+
+impl std::fmt::Display for LoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        return match self {
+            LoadError::Io(error) => write!(f, "{}", error),
+            LoadError::Zip(error) => write!(f, "corrupted zip archive: {}", error),
+            LoadError::Gzip(error) => write!(f, "corrupted gzip archive: {}", error),
+            LoadError::NoRom => write!(f, "no .gb, .gbc or .sgb file in the archive"),
+            LoadError::MissingEntry(entry) => write!(f, "no {} in the archive", entry),
+            LoadError::TooLarge => write!(f, "larger than {} MiB", MAX_ROM_SIZE >> 20),
+            LoadError::Patch(path, error) => {
+                write!(f, "could not apply {}: {}", path.display(), error)
+            }
+            LoadError::Cartridge(error) => write!(f, "{}", error),
+        };
+    }
+}
+
+impl From<std::io::Error> for LoadError {
+    fn from(error: std::io::Error) -> Self {
+        return LoadError::Io(error);
+    }
+}
+
+impl From<CartridgeError> for LoadError {
+    fn from(error: CartridgeError) -> Self {
+        return LoadError::Cartridge(error);
+    }
+}
+
+impl From<zip::result::ZipError> for LoadError {
+    fn from(error: zip::result::ZipError) -> Self {
+        return LoadError::Zip(error);
+    }
+}
+
+/// Where a ROM is read from, a file on disk or an entry of a zip archive.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RomSource {
+    pub path: PathBuf,
+    /// Entry picked in a zip archive, the first ROM in it otherwise.
+    pub entry: Option<String>,
+}
+
+impl RomSource {
+    pub fn new(path: PathBuf) -> Self {
+        return Self { path, entry: None };
+    }
+}
+
+impl std::fmt::Display for RomSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        return match &self.entry {
+            Some(entry) => write!(f, "{} ({})", self.path.display(), entry),
+            None => write!(f, "{}", self.path.display()),
+        };
+    }
+}
+
+pub struct LoadedRom {
+    pub rom: Vec<u8>,
+    /// Path of the ROM as if it were not archived, next to the archive,
+    /// which the save files are named after.
+    pub save_path: PathBuf,
+}
+
+pub fn has_extension(path: &Path, extensions: &[&str]) -> bool {
+    return path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| {
+            extensions
+                .iter()
+                .any(|other| extension.eq_ignore_ascii_case(other))
+        });
+}
+
+/// Reads the ROM from `source`, extracting it when archived.
+pub fn load(source: &RomSource) -> Result<LoadedRom, LoadError> {
+    let path = &source.path;
+    if has_extension(path, &["zip"]) {
+        return load_zip(path, source.entry.as_deref());
+    }
+    if has_extension(path, &["gz"]) {
+        return load_gzip(path);
+    }
+    let file = std::fs::File::open(path)?;
+    return Ok(LoadedRom {
+        rom: read_limited(file, LoadError::Io)?,
+        save_path: path.clone(),
+    });
+}
+
+/// Patches the ROM loaded with the IPS, UPS or BPS patch at `path`.
+pub fn apply_patch(loaded: &mut LoadedRom, path: &Path) -> Result<(), LoadError> {
+    let error = |error| LoadError::Patch(path.to_path_buf(), error);
+    let bytes = std::fs::read(path).map_err(|io| error(PatchError::Io(io)))?;
+    loaded.rom = patch::apply(&bytes, &loaded.rom).map_err(error)?;
+    return Ok(());
+}
+
+/// Names of the ROMs in a zip archive, in the archive order.
+pub fn list_zip(path: &Path) -> Result<Vec<String>, LoadError> {
+    let archive = zip::ZipArchive::new(std::fs::File::open(path)?)?;
+    return Ok(rom_entries(&archive));
+}
+
+fn rom_entries(archive: &zip::ZipArchive<std::fs::File>) -> Vec<String> {
+    return archive
+        .file_names()
+        .filter(|name| has_extension(Path::new(name), &ROM_EXTENSIONS))
+        .map(str::to_string)
+        .collect();
+}
+
+fn load_zip(path: &Path, entry: Option<&str>) -> Result<LoadedRom, LoadError> {
+    let mut archive = zip::ZipArchive::new(std::fs::File::open(path)?)?;
+    let name = match entry {
+        Some(entry) => entry.to_string(),
+        None => rom_entries(&archive)
+            .into_iter()
+            .next()
+            .ok_or(LoadError::NoRom)?,
+    };
+    let file = match archive.by_name(&name) {
+        Ok(file) => file,
+        Err(zip::result::ZipError::FileNotFound) => return Err(LoadError::MissingEntry(name)),
+        Err(error) => return Err(LoadError::Zip(error)),
+    };
+    // Reading fails on a CRC mismatch at the end of the entry.
+    let rom = read_limited(file, |error| LoadError::Zip(error.into()))?;
+    return Ok(LoadedRom {
+        rom,
+        save_path: next_to(path, &name),
+    });
+}
+
+fn load_gzip(path: &Path) -> Result<LoadedRom, LoadError> {
+    let mut decoder = flate2::read::GzDecoder::new(std::fs::File::open(path)?);
+    let rom = read_limited(&mut decoder, LoadError::Gzip)?;
+    // The original name when the header has it, otherwise the archive name
+    // without `.gz`.
+    let name = decoder
+        .header()
+        .and_then(|header| header.filename())
+        .map(|name| String::from_utf8_lossy(name).into_owned())
+        .filter(|name| has_extension(Path::new(name), &ROM_EXTENSIONS))
+        .or_else(|| {
+            path.file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+        })
+        .unwrap_or_default();
+    return Ok(LoadedRom {
+        rom,
+        save_path: next_to(path, &name),
+    });
+}
+
+/// Path of the archived file `name` if it were next to the archive.
+fn next_to(archive: &Path, name: &str) -> PathBuf {
+    return match Path::new(name).file_name() {
+        Some(file_name) => archive.with_file_name(file_name),
+        None => archive.to_path_buf(),
+    };
+}
+
+/// Reads up to `MAX_ROM_SIZE` bytes, `error` telling what a read error
+/// means for the file.
+fn read_limited(
+    reader: impl Read,
+    error: impl FnOnce(std::io::Error) -> LoadError,
+) -> Result<Vec<u8>, LoadError> {
+    let mut rom = vec![];
+    reader
+        .take(MAX_ROM_SIZE + 1)
+        .read_to_end(&mut rom)
+        .map_err(error)?;
+    if rom.len() as u64 > MAX_ROM_SIZE {
+        return Err(LoadError::TooLarge);
+    }
+    return Ok(rom);
+}
@@ -0,0 +1,1241 @@
+mod bess;
+pub mod cartridge;
+pub mod cheats;
+pub mod loader;
+mod movie;
+pub mod patch;
+mod rewind;
+pub mod state;
+pub mod thread;
+
+use crate::audio::wav::Recorder;
+use crate::audio::{self, AudioSink};
+use crate::gbs::{GbsHeader, GbsPlayer};
+use crate::hardware::apu::{CHANNEL_COUNT, CLOCK_RATE};
+use crate::hardware::cpu::{CPU, Register};
+use crate::hardware::joypad::Button;
+use crate::hardware::memory::MemoryMap;
+use crate::hardware::ppu::{Color, DOTS_PER_FRAME, PPU, Renderer};
+use crate::interpreter::disassembler::{self, DisassemblyError, Instruction};
+use crate::interpreter::{self, ExecutionError};
+use bess::BessError;
+use cartridge::{Cartridge, Hardware};
+use cheats::{Cheat, CheatCode};
+use loader::{LoadError, RomSource};
+use movie::{ActiveMovie, CHECKPOINT_INTERVAL, Movie, MovieError, MovieMode, RomId};
+use rewind::{InputChange, RewindBuffer};
+use state::{SLOT_COUNT, Slot, StateError};
+
+use std::collections::{BTreeSet, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Most frames emulated in a row to catch up after a slow one, the limiter
+/// starts over from the current time beyond that.
+const MAX_CATCH_UP_FRAMES: u32 = 4;
+/// Most M-cycles skipped at once while a GBS driver waits for its next call.
+const MAX_IDLE_CYCLES: u32 = 1024;
+/// Speed while the fast-forward key is held.
+pub const FAST_FORWARD_SPEED: f64 = 4.0;
+/// Frames per second on hardware, about 59.73.
+pub const FRAME_RATE: f64 = CLOCK_RATE as f64 / DOTS_PER_FRAME as f64;
+/// Time over which the emulated frame rate is averaged.
+const FPS_INTERVAL: Duration = Duration::from_millis(500);
+/// Frames between two rewind snapshots. Rewinding to the frames in between
+/// replays them from the previous snapshot.
+const REWIND_INTERVAL: u64 = 4;
+pub const DEFAULT_REWIND_BUDGET: usize = 64 << 20;
+
+/// Machine state as serialized, everything but the host side output. The
+/// last field is the dots already run in the current frame.
+type Machine = (CPU, MemoryMap, PPU, Option<GbsPlayer>, u32);
+
+/// Requests sent by the UI to the emulation thread.
+#[derive(Debug)]
+pub enum Command {
+    SetPaused(bool),
+    /// Executes a single instruction while paused.
+    Step,
+    /// Runs until the end of the current frame while paused.
+    AdvanceFrame,
+    /// Speed factor, below 1 for slow motion.
+    SetSpeed(f64),
+    SetFastForward(bool),
+    /// Runs as fast as possible.
+    SetUnlimited(bool),
+    /// Runs backwards while set.
+    SetRewinding(bool),
+    /// Bytes the rewind snapshots may take, 0 disabling rewind.
+    SetRewindBudget(usize),
+    SaveState(usize),
+    LoadState(usize),
+    ExportBess(PathBuf),
+    ImportBess(PathBuf),
+    /// Records the input to a movie, from power on or from the current
+    /// state when set.
+    StartMovieRecording(PathBuf, bool),
+    PlayMovie(PathBuf),
+    StopMovie,
+    AddCheat(Cheat),
+    RemoveCheat(usize),
+    SetCheatEnabled(usize, bool),
+    RenameCheat(usize, String),
+    SetButton(Button, bool),
+    /// Presses and releases a button this many times per second while
+    /// set, None releasing it.
+    SetTurbo(Button, Option<u32>),
+    AddBreakpoint(u16),
+    RemoveBreakpoint(u16),
+    SetTrace(bool),
+    SetRenderer(Renderer),
+    /// Shades of the DMG screen, from lightest to darkest.
+    SetPalette([Color; 4]),
+    /// Factor applied to the samples played, not to the ones recorded.
+    SetVolume(f32),
+    /// Where the save states and cheats go, next to the ROM when None.
+    SetSaveDir(Option<PathBuf>),
+    SetAudibleChannels([bool; CHANNEL_COUNT]),
+    StartRecording(PathBuf, bool),
+    StopRecording,
+    StartGbsTrack(u8),
+    /// Loads a ROM, on the hardware picked for it.
+    OpenRom(RomSource),
+    /// Restarts the cartridge, keeping the contents of the work RAM.
+    Reset,
+    /// Restarts the cartridge from a blank machine.
+    PowerCycle,
+    Quit,
+}
+
+/// Why a headless run ended before emulating all the time asked for.
+#[derive(Debug)]
+pub enum HeadlessError {
+    /// The emulation stopped early, on an error or a breakpoint.
+    Stopped(String),
+    Recording(String),
+}
+
+impl std::fmt::Display for HeadlessError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        return match self {
+            HeadlessError::Stopped(reason) | HeadlessError::Recording(reason) => {
+                write!(f, "{}", reason)
+            }
+        };
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RecordingStatus {
+    pub path: PathBuf,
+    pub stems: bool,
+    /// Recorded duration, in seconds.
+    pub duration: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct MovieStatus {
+    /// Frame running, from the start of the movie.
+    pub frame: u64,
+    /// Frames in the movie when playing it back, None when recording.
+    pub length: Option<u64>,
+    /// First frame found out of sync, when playing back.
+    pub desync: Option<u64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct GbsStatus {
+    pub header: GbsHeader,
+    /// Current song, from 0.
+    pub track: u8,
+    /// Whether the play routine is called on the timer rather than VBlank.
+    pub on_timer: bool,
+    /// Dots between two calls of the play routine.
+    pub period: u32,
+}
+
+/// Copy of the machine state published after every frame, for the UI to
+/// show without waiting on the emulation.
+#[derive(Clone)]
+pub struct Snapshot {
+    pub cpu: CPU,
+    pub mem_map: MemoryMap,
+    pub framebuffer: Vec<Color>,
+    /// None when it cannot be decoded.
+    pub instruction: Option<Instruction>,
+    pub renderer: Renderer,
+    pub paused: bool,
+    pub speed: f64,
+    pub unlimited: bool,
+    /// Frames emulated per second of real time.
+    pub fps: f64,
+    pub rewinding: bool,
+    pub rewind_budget: usize,
+    pub rewind_used: usize,
+    /// How far back one can rewind, in seconds.
+    pub rewind_length: f64,
+    /// Where the ROM running was read from, None when playing a GBS.
+    pub rom_source: Option<RomSource>,
+    pub slots: Arc<Vec<Slot>>,
+    pub cheats: Arc<Vec<Cheat>>,
+    pub movie: Option<MovieStatus>,
+    /// Outcome of the last save, load, export or import, or of the movie or
+    /// of opening a ROM.
+    pub message: Option<String>,
+    pub trace: bool,
+    pub breakpoints: Vec<u16>,
+    pub recording: Option<RecordingStatus>,
+    pub gbs: Option<GbsStatus>,
+}
+
+/// Real time taken by a frame on hardware.
+fn frame_duration() -> Duration {
+    return Duration::from_secs_f64(1.0 / FRAME_RATE);
+}
+
+/// Bit of `button` in the host input, see `Emulator::held_buttons`.
+fn button_index(button: Button) -> usize {
+    return Button::ALL
+        .iter()
+        .position(|other| *other == button)
+        .unwrap();
+}
+
+/// The emulated machine along with its audio output, driven by `Command`s.
+pub struct Emulator {
+    mem_map: MemoryMap,
+    cpu: CPU,
+    ppu: PPU,
+    audio: Box<dyn AudioSink>,
+    recorder: Option<Recorder>,
+    /// Cartridge running, to power it on again, and where it was read from.
+    /// None when playing a GBS.
+    cartridge: Option<Cartridge>,
+    rom_source: Option<RomSource>,
+    /// How the ROMs opened from the UI are run.
+    hardware: Hardware,
+    gbs: Option<GbsPlayer>,
+    audible: [bool; CHANNEL_COUNT],
+    /// Factor applied to the samples played.
+    volume: f32,
+    paused: bool,
+    /// Speed factor picked by the user, see `get_speed` for the one used.
+    speed: f64,
+    fast_forward: bool,
+    unlimited: bool,
+    /// Prints every instruction executed.
+    trace: bool,
+    breakpoints: BTreeSet<u16>,
+    /// Lets the instruction under a breakpoint run when resuming from it.
+    ignore_breakpoint: bool,
+    /// Dots already run in the current frame.
+    frame_dots: u32,
+    /// When the next frame is due.
+    next_frame: Instant,
+    /// Frames completed since `fps_start`.
+    fps_frames: u32,
+    fps_start: Instant,
+    fps: f64,
+    /// Frames completed since power on.
+    frame_count: u64,
+    rewind: RewindBuffer,
+    rewinding: bool,
+    /// Input changes left to apply while replaying frames to rewind, the
+    /// host input being ignored meanwhile.
+    replay: Option<VecDeque<InputChange>>,
+    /// ROM the save states belong to, they are stored next to it unless
+    /// `save_dir` is set.
+    rom_path: Option<PathBuf>,
+    save_dir: Option<PathBuf>,
+    slots: Arc<Vec<Slot>>,
+    /// Cheats of the ROM, saved next to it.
+    cheats: Arc<Vec<Cheat>>,
+    /// Codes of the enabled cheats.
+    cheat_codes: Vec<CheatCode>,
+    message: Option<String>,
+    /// Machine at power on, where movies start from.
+    power_on: Vec<u8>,
+    movie: Option<ActiveMovie>,
+    /// Buttons held on the host, a bit per button in `Button::ALL` order.
+    /// Movies apply them at frame boundaries only.
+    held_buttons: u8,
+    /// Buttons held with turbo on the host, in `Button::ALL` order.
+    turbo: [Option<Turbo>; Button::ALL.len()],
+}
+
+/// Button pressed repeatedly, for the first half of every period.
+#[derive(Clone, Copy)]
+struct Turbo {
+    /// In frames.
+    period: u64,
+    /// Frame it was pressed on.
+    start: u64,
+}
+
+impl Emulator {
+    pub fn new(
+        mem_map: MemoryMap,
+        cpu: CPU,
+        gbs: Option<GbsPlayer>,
+        audio: Box<dyn AudioSink>,
+    ) -> Self {
+        let mut emulator = Self {
+            mem_map,
+            cpu,
+            ppu: PPU::new(),
+            audio,
+            recorder: None,
+            cartridge: None,
+            rom_source: None,
+            hardware: Hardware::default(),
+            gbs,
+            audible: [true; CHANNEL_COUNT],
+            volume: 1.0,
+            paused: false,
+            speed: 1.0,
+            fast_forward: false,
+            unlimited: false,
+            trace: false,
+            breakpoints: BTreeSet::new(),
+            ignore_breakpoint: false,
+            frame_dots: 0,
+            next_frame: Instant::now(),
+            fps_frames: 0,
+            fps_start: Instant::now(),
+            fps: 0.0,
+            frame_count: 0,
+            rewind: RewindBuffer::new(DEFAULT_REWIND_BUDGET),
+            rewinding: false,
+            replay: None,
+            rom_path: None,
+            save_dir: None,
+            slots: Arc::new(vec![Slot::Empty; SLOT_COUNT]),
+            cheats: Arc::new(vec![]),
+            cheat_codes: vec![],
+            message: None,
+            power_on: vec![],
+            movie: None,
+            held_buttons: 0,
+            turbo: [None; Button::ALL.len()],
+        };
+        let sample_rate = emulator.audio.get_sample_rate();
+        emulator.mem_map.get_apu_mut().set_sample_rate(sample_rate);
+        emulator.power_on = emulator.save_machine();
+        return emulator;
+    }
+
+    /// Sets the ROM the save states and cheats belong to, and looks for its
+    /// slots and cheats.
+    pub fn set_rom_path(&mut self, path: PathBuf) {
+        self.rom_path = Some(path);
+        self.read_save_files();
+    }
+
+    /// Moves the save states and cheats to `dir`, or next to the ROM when
+    /// None, reading the ones already there.
+    fn set_save_dir(&mut self, dir: Option<PathBuf>) {
+        if let Some(dir) = &dir
+            && let Err(error) = std::fs::create_dir_all(dir)
+        {
+            self.message = Some(format!("Could not create {}: {}", dir.display(), error));
+        }
+        self.save_dir = dir;
+        self.read_save_files();
+    }
+
+    /// Path the save states and cheats are named after, see `rom_path`.
+    fn get_save_path(&self) -> Option<PathBuf> {
+        let rom_path = self.rom_path.as_ref()?;
+        return match (&self.save_dir, rom_path.file_name()) {
+            (Some(dir), Some(name)) => Some(dir.join(name)),
+            _ => Some(rom_path.clone()),
+        };
+    }
+
+    fn read_save_files(&mut self) {
+        let Some(path) = self.get_save_path() else {
+            return;
+        };
+        self.slots = Arc::new(
+            (0..SLOT_COUNT)
+                .map(|slot| Slot::read(&state::slot_path(&path, slot)))
+                .collect(),
+        );
+        let cheats = cheats::load(&path).unwrap_or_else(|error| {
+            let cheats_path = cheats::path(&path);
+            self.message = Some(format!(
+                "Could not read {}: {}",
+                cheats_path.display(),
+                error
+            ));
+            vec![]
+        });
+        self.cheats = Arc::new(cheats);
+        self.refresh_cheats();
+    }
+
+    /// Changes the cheats with `change`, and saves them.
+    fn edit_cheats(&mut self, change: impl FnOnce(&mut Vec<Cheat>)) {
+        change(Arc::make_mut(&mut self.cheats));
+        self.refresh_cheats();
+        if let Some(path) = self.get_save_path()
+            && let Err(error) = cheats::save(&path, &self.cheats)
+        {
+            let cheats_path = cheats::path(&path);
+            self.message = Some(format!(
+                "Could not save {}: {}",
+                cheats_path.display(),
+                error
+            ));
+        }
+    }
+
+    /// Applies the cheats enabled from now on.
+    fn refresh_cheats(&mut self) {
+        self.cheat_codes = cheats::enabled_codes(&self.cheats);
+        self.mem_map
+            .set_rom_patches(cheats::rom_patches(&self.cheat_codes));
+    }
+
+    /// Replaces whatever was running with `cartridge`, read from `source`,
+    /// just powered on. The save files are named after `save_path`.
+    pub fn load_cartridge(&mut self, cartridge: Cartridge, source: RomSource, save_path: PathBuf) {
+        self.cartridge = Some(cartridge);
+        self.rom_source = Some(source);
+        self.gbs = None;
+        self.set_rom_path(save_path);
+        self.restart(false);
+    }
+
+    pub fn set_hardware(&mut self, hardware: Hardware) {
+        self.hardware = hardware;
+    }
+
+    /// Reads the ROM from `source`, patched by the patch named after it if
+    /// any, and runs it as set by `set_hardware`. Returns the patch applied.
+    fn open_rom(&mut self, source: RomSource) -> Result<Option<PathBuf>, LoadError> {
+        let mut loaded = loader::load(&source)?;
+        let patch = patch::find(&loaded.save_path);
+        if let Some(patch) = &patch {
+            loader::apply_patch(&mut loaded, patch)?;
+        }
+        let cartridge = self.hardware.load(loaded.rom)?;
+        self.load_cartridge(cartridge, source, loaded.save_path);
+        return Ok(patch);
+    }
+
+    pub fn get_gbs(&self) -> Option<&GbsPlayer> {
+        return self.gbs.as_ref();
+    }
+
+    /// Applies a command, returns false on `Command::Quit`.
+    pub fn handle(&mut self, command: Command) -> bool {
+        match command {
+            Command::SetPaused(paused) => self.set_paused(paused),
+            Command::Step => {
+                if let Err(error) = self.execute_next() {
+                    self.stop_on_error(error);
+                }
+                self.flush_audio();
+            }
+            Command::AdvanceFrame => {
+                self.ignore_breakpoint = true;
+                self.run_frame();
+                self.flush_audio();
+            }
+            Command::SetSpeed(speed) => self.speed = speed,
+            Command::SetFastForward(fast_forward) => self.fast_forward = fast_forward,
+            Command::SetUnlimited(unlimited) => self.unlimited = unlimited,
+            Command::SetRewinding(rewinding) => self.set_rewinding(rewinding),
+            Command::SetRewindBudget(budget) => self.rewind.set_budget(budget),
+            Command::SaveState(slot) => {
+                self.message = Some(match self.save_state(slot) {
+                    Ok(()) => format!("Saved slot {}", slot + 1),
+                    Err(error) => format!("Could not save slot {}: {}", slot + 1, error),
+                });
+            }
+            Command::LoadState(slot) => {
+                self.message = Some(match self.load_state(slot) {
+                    Ok(()) => format!("Loaded slot {}", slot + 1),
+                    Err(error) => format!("Could not load slot {}: {}", slot + 1, error),
+                });
+            }
+            Command::ExportBess(path) => {
+                self.message = Some(match self.export_bess(&path) {
+                    Ok(()) => format!("Exported {}", path.display()),
+                    Err(error) => format!("Could not export {}: {}", path.display(), error),
+                });
+            }
+            Command::StartMovieRecording(path, from_state) => {
+                self.start_movie_recording(path, from_state)
+            }
+            Command::PlayMovie(path) => {
+                if let Err(error) = self.play_movie(&path) {
+                    self.message = Some(format!("Could not play {}: {}", path.display(), error));
+                }
+            }
+            Command::StopMovie => self.stop_movie(),
+            Command::AddCheat(cheat) => self.edit_cheats(|cheats| cheats.push(cheat)),
+            Command::RemoveCheat(index) => self.edit_cheats(|cheats| {
+                if index < cheats.len() {
+                    cheats.remove(index);
+                }
+            }),
+            Command::SetCheatEnabled(index, enabled) => self.edit_cheats(|cheats| {
+                if let Some(cheat) = cheats.get_mut(index) {
+                    cheat.enabled = enabled;
+                }
+            }),
+            Command::RenameCheat(index, name) => self.edit_cheats(|cheats| {
+                if let Some(cheat) = cheats.get_mut(index) {
+                    cheat.name = name;
+                }
+            }),
+            Command::ImportBess(path) => {
+                self.message = Some(match self.import_bess(&path) {
+                    Ok(()) => format!("Imported {}", path.display()),
+                    Err(error) => format!("Could not import {}: {}", path.display(), error),
+                });
+            }
+            Command::SetButton(button, pressed) => self.set_button(button, pressed),
+            Command::SetTurbo(button, rate) => self.set_turbo(button, rate),
+            Command::AddBreakpoint(address) => {
+                self.breakpoints.insert(address);
+            }
+            Command::RemoveBreakpoint(address) => {
+                self.breakpoints.remove(&address);
+            }
+            Command::SetTrace(trace) => self.trace = trace,
+            Command::SetRenderer(renderer) => self.ppu.set_renderer(renderer),
+            Command::SetPalette(palette) => self.ppu.set_palette(palette),
+            Command::SetVolume(volume) => self.volume = volume,
+            Command::SetSaveDir(dir) => self.set_save_dir(dir),
+            Command::SetAudibleChannels(audible) => self.audible = audible,
+            Command::StartRecording(path, stems) => {
+                if let Err(error) = self.start_recording(&path, stems) {
+                    self.message =
+                        Some(format!("Could not record to {}: {}", path.display(), error));
+                }
+            }
+            Command::StopRecording => self.stop_recording(),
+            Command::StartGbsTrack(track) => self.start_gbs_track(track),
+            Command::OpenRom(source) => {
+                let name = source.to_string();
+                self.message = Some(match self.open_rom(source) {
+                    Ok(patch) => {
+                        let mut message = format!("Opened {}", name);
+                        if let Some(patch) = patch {
+                            message += &format!(", patched with {}", patch.display());
+                        }
+                        if self.cartridge.as_ref().is_some_and(Cartridge::is_truncated) {
+                            message += ", only its first 32 KiB are mapped";
+                        }
+                        message
+                    }
+                    Err(error) => format!("Could not open {}: {}", name, error),
+                });
+            }
+            Command::Reset => self.restart(true),
+            Command::PowerCycle => self.restart(false),
+            Command::Quit => return false,
+        }
+        return true;
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        if self.paused && !paused {
+            self.ignore_breakpoint = true;
+            self.next_frame = Instant::now();
+            self.fps_frames = 0;
+            self.fps_start = Instant::now();
+        }
+        self.paused = paused;
+    }
+
+    fn set_rewinding(&mut self, rewinding: bool) {
+        // Rewinding held the input of the frames rewound to.
+        if self.rewinding && !rewinding && self.movie.is_none() {
+            self.apply_input(self.get_input());
+        }
+        self.rewinding = rewinding;
+    }
+
+    /// Speed factor to run at, or None when unlimited.
+    fn get_speed(&self) -> Option<f64> {
+        if self.unlimited {
+            return None;
+        }
+        if self.fast_forward {
+            return Some(FAST_FORWARD_SPEED);
+        }
+        return Some(self.speed);
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        return Snapshot {
+            cpu: self.cpu.clone(),
+            mem_map: self.mem_map.clone(),
+            framebuffer: self.ppu.get_framebuffer().to_vec(),
+            instruction: self.next_instruction().ok(),
+            renderer: self.ppu.get_renderer(),
+            paused: self.paused,
+            speed: self.speed,
+            unlimited: self.unlimited,
+            fps: if self.paused { 0.0 } else { self.fps },
+            rewinding: self.rewinding,
+            rom_source: self.rom_source.clone(),
+            slots: self.slots.clone(),
+            cheats: self.cheats.clone(),
+            message: self.message.clone(),
+            rewind_budget: self.rewind.get_budget(),
+            rewind_used: self.rewind.get_used(),
+            rewind_length: self.rewind.get_oldest_frame().map_or(0.0, |frame| {
+                self.frame_count.saturating_sub(frame) as f64 / FRAME_RATE
+            }),
+            trace: self.trace,
+            breakpoints: self.breakpoints.iter().copied().collect(),
+            recording: self.recorder.as_ref().map(|recorder| RecordingStatus {
+                path: recorder.get_path().to_path_buf(),
+                stems: recorder.has_stems(),
+                duration: recorder.get_duration(),
+            }),
+            movie: self.movie.as_ref().map(|active| {
+                let (length, desync) = match active.mode {
+                    MovieMode::Recording(_) => (None, None),
+                    MovieMode::Playing { desync } => (Some(active.movie.len()), desync),
+                };
+                MovieStatus {
+                    frame: active.frame,
+                    length,
+                    desync,
+                }
+            }),
+            gbs: self.gbs.as_ref().map(|gbs| GbsStatus {
+                header: gbs.get_header().clone(),
+                track: gbs.get_track(),
+                on_timer: gbs.is_on_timer(&self.mem_map).unwrap_or(false),
+                period: gbs
+                    .get_period(&self.mem_map)
+                    .unwrap_or(gbs.get_header().play_period()),
+            }),
+        };
+    }
+
+    /// When the next frame is due, or None while paused.
+    pub fn get_next_frame(&self) -> Option<Instant> {
+        if self.paused {
+            return None;
+        }
+        return Some(self.next_frame);
+    }
+
+    /// Runs the next instruction, or lets the hardware run while a GBS
+    /// driver is idle. Returns the dots elapsed.
+    fn execute_next(&mut self) -> Result<u32, ExecutionError> {
+        let dots = match &self.gbs {
+            Some(gbs) if gbs.is_idle(&self.cpu) => {
+                let dots_per_cycle = self.mem_map.get_speed().dots_per_cycle();
+                let cycles = (gbs.get_countdown() / dots_per_cycle).clamp(1, MAX_IDLE_CYCLES);
+                self.tick_hardware(cycles)?
+            }
+            _ => {
+                let instruction = self.next_instruction()?;
+                self.step(instruction)?
+            }
+        };
+        if let Some(gbs) = self.gbs.as_mut() {
+            gbs.tick(&mut self.mem_map, &mut self.cpu, dots)?;
+        }
+        self.ignore_breakpoint = false;
+        return Ok(dots);
+    }
+
+    /// Executes one instruction, returns the dots it took. PC is left on
+    /// the instruction when it fails.
+    fn step(&mut self, instruction: Instruction) -> Result<u32, ExecutionError> {
+        if self.trace {
+            println!("{:X?}", instruction);
+        }
+        let pc = self.cpu.read_word(&Register::PC);
+        self.cpu
+            .add_word(&Register::PC, instruction.get_size() as u16);
+        let cycles = match interpreter::execute(&mut self.mem_map, &mut self.cpu, &instruction) {
+            Ok(cycles) => cycles,
+            Err(error) => {
+                self.cpu.write_word(&Register::PC, pc);
+                return Err(error);
+            }
+        };
+        self.cpu.refresh_interupt_flag();
+        return self.tick_hardware(cycles);
+    }
+
+    fn tick_hardware(&mut self, cycles: u32) -> Result<u32, ExecutionError> {
+        let mut cycles = cycles;
+        let mut elapsed = 0;
+        while cycles > 0 {
+            // The PPU runs at the same pace whatever the CPU speed is.
+            let dots = cycles * self.mem_map.get_speed().dots_per_cycle();
+            self.mem_map.tick(cycles)?;
+            self.ppu.tick(&mut self.mem_map, dots)?;
+            elapsed += dots;
+            // HDMA transfers stall the CPU while the rest keeps running.
+            cycles = self.mem_map.take_stall_cycles();
+        }
+        return Ok(elapsed);
+    }
+
+    /// Pauses on an error of the emulated machine, left as it was for the
+    /// debugger to inspect.
+    fn stop_on_error(&mut self, error: ExecutionError) {
+        let pc = self.cpu.read_word(&Register::PC);
+        self.paused = true;
+        self.message = Some(format!("Stopped at {:04X}: {}", pc, error));
+    }
+
+    /// Emulates the rest of the current frame. Instructions do not stop on
+    /// the frame boundary, the overrun is taken from the next frame. Stops
+    /// early and pauses when reaching a breakpoint or on an error, returns
+    /// whether the frame was completed.
+    fn run_frame(&mut self) -> bool {
+        while self.frame_dots < DOTS_PER_FRAME {
+            self.replay_input();
+            let pc = self.cpu.read_word(&Register::PC);
+            if !self.ignore_breakpoint && self.breakpoints.contains(&pc) {
+                self.paused = true;
+                return false;
+            }
+            match self.execute_next() {
+                Ok(dots) => self.frame_dots += dots,
+                Err(error) => {
+                    self.stop_on_error(error);
+                    return false;
+                }
+            }
+        }
+        self.frame_dots -= DOTS_PER_FRAME;
+        cheats::write_ram(&mut self.mem_map, &self.cheat_codes);
+        self.fps_frames += 1;
+        self.frame_count += 1;
+        if !self.rewinding && self.frame_count.is_multiple_of(REWIND_INTERVAL) {
+            let state = self.save_machine();
+            self.rewind.push(self.frame_count, state);
+        }
+        if let Some(active) = &mut self.movie {
+            active.frame += 1;
+            self.start_movie_frame();
+        } else if self.replay.is_none() && self.turbo.iter().any(Option::is_some) {
+            self.apply_input(self.get_input());
+        }
+        return true;
+    }
+
+    fn set_button(&mut self, button: Button, pressed: bool) {
+        let index = button_index(button);
+        if pressed {
+            self.held_buttons |= 1 << index;
+        } else {
+            self.held_buttons &= !(1 << index);
+        }
+        if self.movie.is_none() {
+            self.apply_input(self.get_input());
+        }
+    }
+
+    fn set_turbo(&mut self, button: Button, rate: Option<u32>) {
+        self.turbo[button_index(button)] = rate.map(|rate| Turbo {
+            period: (FRAME_RATE / rate.max(1) as f64).round().max(2.0) as u64,
+            start: self.frame_count,
+        });
+        if self.movie.is_none() {
+            self.apply_input(self.get_input());
+        }
+    }
+
+    /// Buttons held on the host, the turbo ones while in the pressed half
+    /// of their period.
+    fn get_input(&self) -> u8 {
+        let mut input = self.held_buttons;
+        for (index, turbo) in self.turbo.iter().enumerate() {
+            if let Some(turbo) = turbo
+                && self.frame_count.saturating_sub(turbo.start) % turbo.period < turbo.period / 2
+            {
+                input |= 1 << index;
+            }
+        }
+        return input;
+    }
+
+    /// Holds the buttons of `input`, see `held_buttons`, noting the change
+    /// for rewinding.
+    fn apply_input(&mut self, input: u8) {
+        let joypad = self.mem_map.get_joypad();
+        let current = Button::ALL
+            .iter()
+            .enumerate()
+            .filter(|(_, button)| joypad.is_pressed(**button))
+            .fold(0, |current, (index, _)| current | 1 << index);
+        if self.replay.is_none() && input != current {
+            self.rewind
+                .push_input(self.frame_count, self.frame_dots, input);
+        }
+        for (index, button) in Button::ALL.iter().enumerate() {
+            self.mem_map.set_button(*button, input & (1 << index) != 0);
+        }
+    }
+
+    /// Starts recording the input from power on, or from the current state
+    /// which is then embedded in the movie.
+    pub fn start_movie_recording(&mut self, path: PathBuf, from_state: bool) {
+        let Some(rom) = self.get_rom_id() else {
+            self.message = Some(format!(
+                "Could not record {}: {}",
+                path.display(),
+                MovieError::NoRom
+            ));
+            return;
+        };
+        self.stop_movie();
+        let start = if from_state {
+            Some(self.save_machine())
+        } else {
+            let power_on = std::mem::take(&mut self.power_on);
+            self.load_machine(&power_on)
+                .expect("the power on state is always readable");
+            self.power_on = power_on;
+            None
+        };
+        let movie = Movie::new(rom, start);
+        self.begin_movie(movie, MovieMode::Recording(path));
+    }
+
+    /// Plays a movie back from its start, its input replacing the host one.
+    pub fn play_movie(&mut self, path: &Path) -> Result<(), MovieError> {
+        let rom = self.get_rom_id().ok_or(MovieError::NoRom)?;
+        let movie = Movie::load(path, &rom)?;
+        let start = movie.get_start().unwrap_or(&self.power_on).to_vec();
+        self.stop_movie();
+        self.load_machine(&start).map_err(MovieError::Corrupted)?;
+        self.begin_movie(movie, MovieMode::Playing { desync: None });
+        return Ok(());
+    }
+
+    fn get_rom_id(&self) -> Option<RomId> {
+        return self
+            .cartridge
+            .as_ref()
+            .map(|cartridge| RomId::new(cartridge.get_rom()));
+    }
+
+    fn begin_movie(&mut self, movie: Movie, mode: MovieMode) {
+        self.rewind.clear();
+        // The buttons held in the starting state depend on the host, the
+        // movie starts with all of them released.
+        self.mem_map.release_buttons();
+        self.movie = Some(ActiveMovie {
+            movie,
+            mode,
+            frame: 0,
+        });
+        self.start_movie_frame();
+    }
+
+    /// Applies the input of the movie frame starting, recording it from the
+    /// host or checking the machine against the recording.
+    fn start_movie_frame(&mut self) {
+        let host_input = self.get_input();
+        let Some(active) = &mut self.movie else {
+            return;
+        };
+        let frame = active.frame;
+        let checkpoint = frame.is_multiple_of(CHECKPOINT_INTERVAL);
+        let input = match &mut active.mode {
+            MovieMode::Recording(_) => {
+                if checkpoint {
+                    let hash = movie::hash_machine(&self.cpu, &self.mem_map);
+                    active.movie.add_checkpoint(frame, hash);
+                }
+                active.movie.push_input(host_input);
+                host_input
+            }
+            MovieMode::Playing { desync } => {
+                if desync.is_none()
+                    && let Some(expected) = active.movie.get_checkpoint(frame)
+                    && movie::hash_machine(&self.cpu, &self.mem_map) != expected
+                {
+                    *desync = Some(frame);
+                    self.message = Some(format!("Movie desynced at frame {}", frame));
+                }
+                match active.movie.get_input(frame) {
+                    Some(input) => input,
+                    None => return self.stop_movie(),
+                }
+            }
+        };
+        self.apply_input(input);
+    }
+
+    /// Stops the movie, saving it when recording. The host input applies
+    /// again.
+    pub fn stop_movie(&mut self) {
+        let Some(active) = self.movie.take() else {
+            return;
+        };
+        self.message = Some(match active.mode {
+            MovieMode::Recording(path) => match active.movie.save(&path) {
+                Ok(()) => format!("Saved {} ({} frames)", path.display(), active.movie.len()),
+                Err(error) => format!("Could not save {}: {}", path.display(), error),
+            },
+            MovieMode::Playing {
+                desync: Some(frame),
+            } => {
+                format!("Movie stopped, desynced at frame {}", frame)
+            }
+            MovieMode::Playing { desync: None } => {
+                format!("Movie stopped after {} frames, in sync", active.frame)
+            }
+        });
+        self.apply_input(self.get_input());
+    }
+
+    /// Applies the input changes reached while replaying frames.
+    fn replay_input(&mut self) {
+        let position = (self.frame_count, self.frame_dots);
+        while let Some(replay) = &mut self.replay
+            && replay
+                .front()
+                .is_some_and(|change| (change.frame, change.dots) <= position)
+        {
+            let input = replay.pop_front().unwrap().input;
+            self.apply_input(input);
+        }
+    }
+
+    /// Goes back one frame, restoring the newest snapshot not after it and
+    /// replaying the frames in between with the input they ran with. The
+    /// replayed audio is neither heard nor recorded.
+    fn rewind_frame(&mut self) {
+        // Movies only run forward.
+        if self.movie.is_some() {
+            return;
+        }
+        let Some(target) = self.frame_count.checked_sub(1) else {
+            return;
+        };
+        while let Some((frame, _)) = self.rewind.get_newest()
+            && frame > target
+        {
+            self.rewind.pop();
+        }
+        let Some((frame, state)) = self.rewind.get_newest() else {
+            return;
+        };
+        let machine: Machine =
+            bincode::deserialize(state).expect("rewind snapshots are always readable");
+        let joypad = machine.1.get_joypad().clone();
+        self.restore(machine);
+        // The buttons held back then rather than the ones held now.
+        self.mem_map.keep_pressed(&joypad);
+        self.frame_count = frame;
+        self.replay = Some(self.rewind.get_inputs_since(frame, self.frame_dots));
+        let breakpoints = std::mem::take(&mut self.breakpoints);
+        while self.frame_count < target {
+            if !self.run_frame() {
+                break;
+            }
+        }
+        self.replay_input();
+        self.replay = None;
+        self.rewind
+            .truncate_inputs(self.frame_count, self.frame_dots);
+        self.breakpoints = breakpoints;
+        let apu = self.mem_map.get_apu_mut();
+        apu.take_samples();
+        apu.take_stems();
+    }
+
+    /// Writes the machine to quick-save `slot`, along with a thumbnail of
+    /// the screen.
+    fn save_state(&mut self, slot: usize) -> Result<(), StateError> {
+        let path = state::slot_path(&self.get_save_path().ok_or(StateError::NoRom)?, slot);
+        let thumbnail = state::thumbnail(self.ppu.get_framebuffer());
+        state::write(&path, &thumbnail, &self.save_machine())?;
+        Arc::make_mut(&mut self.slots)[slot] = Slot::read(&path);
+        return Ok(());
+    }
+
+    /// Restores quick-save `slot`. The running machine is only replaced once
+    /// the whole state was read, so a failed load leaves it untouched.
+    fn load_state(&mut self, slot: usize) -> Result<(), StateError> {
+        let path = state::slot_path(&self.get_save_path().ok_or(StateError::NoRom)?, slot);
+        let machine = state::read(&path)?;
+        self.load_machine(&machine).map_err(StateError::Corrupted)?;
+        self.stop_movie();
+        // Rewinding past the load would mix both timelines.
+        self.rewind.clear();
+        return Ok(());
+    }
+
+    /// Serializes the whole machine, see `Machine`.
+    fn save_machine(&self) -> Vec<u8> {
+        let machine = (
+            &self.cpu,
+            &self.mem_map,
+            &self.ppu,
+            &self.gbs,
+            self.frame_dots,
+        );
+        return bincode::serialize(&machine).expect("the machine state is always serializable");
+    }
+
+    /// Restores a machine serialized by `save_machine`, keeping the audio
+    /// output, the buttons held and the renderer picked in the UI.
+    fn load_machine(&mut self, bytes: &[u8]) -> bincode::Result<()> {
+        self.restore(bincode::deserialize(bytes)?);
+        return Ok(());
+    }
+
+    /// Replaces the machine, see `load_machine`.
+    fn restore(&mut self, machine: Machine) {
+        let (cpu, mut mem_map, mut ppu, gbs, frame_dots) = machine;
+        mem_map.keep_host_state(&mut self.mem_map);
+        ppu.keep_host_state(&self.ppu);
+        self.cpu = cpu;
+        self.mem_map = mem_map;
+        self.ppu = ppu;
+        self.gbs = gbs;
+        self.frame_dots = frame_dots;
+    }
+
+    pub fn export_bess(&self, path: &Path) -> std::io::Result<()> {
+        return std::fs::write(path, bess::export(&self.cpu, &self.mem_map));
+    }
+
+    /// Replaces the CPU and memory with the ones of a BESS file saved for
+    /// the loaded ROM, starting the PPU over.
+    pub fn import_bess(&mut self, path: &Path) -> Result<(), BessError> {
+        let bytes = std::fs::read(path)?;
+        let (cpu, mem_map) = bess::import(&bytes, &self.mem_map)?;
+        self.stop_movie();
+        let gbs = self.gbs.take();
+        self.restore((cpu, mem_map, PPU::new(), gbs, 0));
+        self.rewind.clear();
+        return Ok(());
+    }
+
+    /// Runs the frames due by now, so that emulation keeps the selected
+    /// pace. The audio follows through the rate control rather than pacing
+    /// the emulation. When unlimited, runs for a frame worth of real time
+    /// so that commands are still handled in between.
+    pub fn run_frames(&mut self) {
+        let now = Instant::now();
+        match self.get_speed() {
+            Some(speed) => {
+                let interval = frame_duration().div_f64(speed);
+                let mut frames = 0;
+                while !self.paused && self.next_frame <= now && frames < MAX_CATCH_UP_FRAMES {
+                    if self.rewinding {
+                        self.rewind_frame();
+                    } else {
+                        self.run_frame();
+                    }
+                    self.flush_audio();
+                    self.next_frame += interval;
+                    frames += 1;
+                }
+                if self.next_frame <= now {
+                    // Too far behind, skip the missed frames.
+                    self.next_frame = now + interval;
+                }
+            }
+            None => {
+                let deadline = now + frame_duration();
+                while !self.paused && Instant::now() < deadline {
+                    if self.rewinding {
+                        self.rewind_frame();
+                    } else {
+                        self.run_frame();
+                    }
+                    self.flush_audio();
+                }
+                self.next_frame = Instant::now();
+            }
+        }
+        self.measure_fps();
+    }
+
+    fn measure_fps(&mut self) {
+        let elapsed = self.fps_start.elapsed();
+        if elapsed >= FPS_INTERVAL {
+            self.fps = self.fps_frames as f64 / elapsed.as_secs_f64();
+            self.fps_frames = 0;
+            self.fps_start = Instant::now();
+        }
+    }
+
+    /// Sends the samples produced so far to the audio output. They are
+    /// stretched in slow motion, which lowers the pitch, and dropped when
+    /// the output is full while running faster than hardware.
+    fn flush_audio(&mut self) {
+        let speed = self.get_speed();
+        // The sink level says nothing about the drift while running fast.
+        let adjust = match speed {
+            Some(speed) if speed <= 1.0 => audio::rate_adjust(self.audio.as_ref()),
+            _ => 1.0,
+        };
+        let apu = self.mem_map.get_apu_mut();
+        apu.set_rate_adjust(adjust);
+        apu.set_audible_channels(self.audible);
+        let mut samples = self.drain_audio();
+        for sample in &mut samples {
+            sample[0] *= self.volume;
+            sample[1] *= self.volume;
+        }
+        match speed {
+            Some(speed) if speed < 1.0 => self.audio.push(&audio::stretch(&samples, 1.0 / speed)),
+            Some(1.0) => self.audio.push(&samples),
+            _ => {
+                if self.audio.get_buffered() < audio::BUFFER_CAPACITY {
+                    self.audio.push(&samples);
+                }
+            }
+        }
+    }
+
+    /// Takes the samples produced by the APU, recording them when needed.
+    fn drain_audio(&mut self) -> Vec<[f32; 2]> {
+        let apu = self.mem_map.get_apu_mut();
+        let samples = apu.take_samples();
+        let stems = apu.take_stems();
+        if let Some(recorder) = self.recorder.as_mut()
+            && let Err(error) = recorder.write(&samples, stems.as_ref())
+        {
+            self.stop_recording();
+            self.message = Some(format!("Recording failed: {}", error));
+        }
+        return samples;
+    }
+
+    pub fn start_recording(&mut self, path: &Path, stems: bool) -> std::io::Result<()> {
+        self.stop_recording();
+        // Samples produced before the recording starts are not part of it.
+        self.flush_audio();
+        let apu = self.mem_map.get_apu_mut();
+        apu.set_stems_enabled(stems);
+        self.recorder = Some(Recorder::start(path, apu.get_sample_rate(), stems)?);
+        return Ok(());
+    }
+
+    pub fn stop_recording(&mut self) {
+        if let Err(error) = self.finish_recording() {
+            self.message = Some(format!("Could not finish the recording: {}", error));
+        }
+    }
+
+    /// Stops recording, returning the error met while finishing the files.
+    fn finish_recording(&mut self) -> std::io::Result<()> {
+        let Some(recorder) = self.recorder.take() else {
+            return Ok(());
+        };
+        self.mem_map.get_apu_mut().set_stems_enabled(false);
+        return recorder.stop();
+    }
+
+    /// Powers the cartridge on again, or starts the GBS track over. Resetting
+    /// keeps the work RAM, which the hardware does not clear.
+    fn restart(&mut self, keep_wram: bool) {
+        if let Some(gbs) = &self.gbs {
+            return self.start_gbs_track(gbs.get_track());
+        }
+        let Some(cartridge) = &self.cartridge else {
+            return;
+        };
+        let (mut mem_map, cpu) = cartridge.power_on();
+        if keep_wram {
+            mem_map
+                .get_wram_mut()
+                .copy_from_slice(self.mem_map.get_wram());
+        }
+        self.stop_movie();
+        self.flush_audio();
+        self.restore((cpu, mem_map, PPU::new(), None, 0));
+        self.frame_count = 0;
+        self.rewind.clear();
+        if !keep_wram {
+            self.power_on = self.save_machine();
+        }
+    }
+
+    /// Resets the machine and starts playing `track` of the loaded GBS.
+    pub fn start_gbs_track(&mut self, track: u8) {
+        let Some(model) = self.gbs.as_ref().map(|gbs| gbs.get_header().get_model()) else {
+            return;
+        };
+        self.stop_movie();
+        self.flush_audio();
+        let sample_rate = self.mem_map.get_apu().get_sample_rate();
+        self.mem_map = MemoryMap::new(model);
+        let apu = self.mem_map.get_apu_mut();
+        apu.set_sample_rate(sample_rate);
+        apu.set_stems_enabled(self.recorder.as_ref().is_some_and(Recorder::has_stems));
+        self.cpu = CPU::new();
+        let mut ppu = PPU::new();
+        ppu.keep_host_state(&self.ppu);
+        self.ppu = ppu;
+        self.frame_dots = 0;
+        self.frame_count = 0;
+        self.rewind.clear();
+        if let Some(gbs) = self.gbs.as_mut()
+            && let Err(error) = gbs.start_track(&mut self.mem_map, &mut self.cpu, track)
+        {
+            self.stop_on_error(error);
+        }
+        self.refresh_cheats();
+        self.power_on = self.save_machine();
+    }
+
+    /// Emulates `seconds` of machine time as fast as possible, without any
+    /// audio output, for recording. Fails when the emulation stops early or
+    /// the recording cannot be written, keeping what was recorded so far.
+    pub fn run_headless(&mut self, seconds: f64) -> Result<(), HeadlessError> {
+        let recording = self.recorder.is_some();
+        let frames = (seconds * CLOCK_RATE as f64 / DOTS_PER_FRAME as f64).ceil();
+        for _ in 0..frames as u64 {
+            let completed = self.run_frame();
+            self.drain_audio();
+            if recording && self.recorder.is_none() {
+                return Err(HeadlessError::Recording(
+                    self.message.take().unwrap_or_default(),
+                ));
+            }
+            if !completed {
+                let pc = self.cpu.read_word(&Register::PC);
+                let reason = self
+                    .message
+                    .take()
+                    .unwrap_or_else(|| format!("Stopped at the breakpoint at {:04X}", pc));
+                self.stop_recording();
+                return Err(HeadlessError::Stopped(reason));
+            }
+        }
+        return self.finish_recording().map_err(|error| {
+            HeadlessError::Recording(format!("Could not finish the recording: {}", error))
+        });
+    }
+
+    fn next_instruction(&self) -> Result<Instruction, ExecutionError> {
+        let pc = self.cpu.read_word(&Register::PC) as usize;
+        // Instructions take up to 3 bytes, fewer are left at the very end
+        // of the address space.
+        let end = (pc + 3).min(self.mem_map.size());
+        let next_bytes = self.mem_map.read_bytes(pc, end.saturating_sub(pc))?;
+        return disassembler::get_instruction(&next_bytes).map_err(|error| match error {
+            DisassemblyError::MissingOperand(opcode) => ExecutionError::IllegalInstructionError(
+                Instruction::Unkown(opcode),
+                "operands past the end of memory".to_string(),
+            ),
+            DisassemblyError::EOF => ExecutionError::MemoryOutOfBoundsError(end),
+        });
+    }
+}
@@ -0,0 +1,200 @@
+use crate::hardware::cpu::CPU;
+use crate::hardware::memory::MemoryMap;
+
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Frames between two hashes of the machine, to detect a desync.
+pub const CHECKPOINT_INTERVAL: u64 = 60;
+const SIGNATURE: &[u8; 8] = b"GBMOVIE\0";
+/// Format of the movie files, to bump whenever the movie or the serialized
+/// machine changes shape.
+const VERSION: u32 = 2;
+const HEADER_CHECKSUM: usize = 0x14D;
+const GLOBAL_CHECKSUM: usize = 0x14E;
+
+#[derive(Debug)]
+pub enum MovieError {
+    Io(std::io::Error),
+    InvalidSignature,
+    UnsupportedVersion(u32),
+    Corrupted(bincode::Error),
+    OtherRom,
+    NoRom,
+}
+
+impl std::fmt::Display for MovieError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        return match self {
+            MovieError::Io(error) => write!(f, "{}", error),
+            MovieError::InvalidSignature => write!(f, "not a movie"),
+            MovieError::UnsupportedVersion(version) => write!(
+                f,
+                "recorded in format version {}, this build only reads version {}",
+                version, VERSION
+            ),
+            MovieError::Corrupted(error) => write!(f, "corrupted movie: {}", error),
+            MovieError::OtherRom => write!(f, "recorded with another ROM"),
+            MovieError::NoRom => write!(f, "no ROM is loaded"),
+        };
+    }
+}
+
+impl From<std::io::Error> for MovieError {
+    fn from(error: std::io::Error) -> Self {
+        return MovieError::Io(error);
+    }
+}
+
+/// Identifies the ROM a movie was recorded with, from the whole cartridge
+/// ROM whatever is mapped at the time.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct RomId {
+    header_checksum: u8,
+    global_checksum: u16,
+    hash: u64,
+}
+
+impl RomId {
+    pub fn new(rom: &[u8]) -> Self {
+        let byte = |address: usize| rom.get(address).copied().unwrap_or(0);
+        return Self {
+            header_checksum: byte(HEADER_CHECKSUM),
+            global_checksum: u16::from_be_bytes([byte(GLOBAL_CHECKSUM), byte(GLOBAL_CHECKSUM + 1)]),
+            hash: hash(rom),
+        };
+    }
+}
+
+/// Joypad input of every frame, from power on or from a saved machine,
+/// along with hashes of the machine taken while recording.
+#[derive(Serialize, Deserialize)]
+pub struct Movie {
+    rom: RomId,
+    /// Machine the movie starts from, as serialized by the emulator, or
+    /// None when starting from power on.
+    start: Option<Vec<u8>>,
+    /// Buttons held during each frame, a bit per button in `Button::ALL`
+    /// order.
+    inputs: Vec<u8>,
+    /// Frames and hashes of the machine at their start, in frame order.
+    checkpoints: Vec<(u64, u64)>,
+}
+
+impl Movie {
+    pub fn new(rom: RomId, start: Option<Vec<u8>>) -> Self {
+        Self {
+            rom,
+            start,
+            inputs: vec![],
+            checkpoints: vec![],
+        }
+    }
+
+    /// Reads a movie, which must have been recorded with the ROM `rom`.
+    pub fn load(path: &Path, rom: &RomId) -> Result<Self, MovieError> {
+        let bytes = std::fs::read(path)?;
+        if bytes.len() < SIGNATURE.len() + 4 || &bytes[0..SIGNATURE.len()] != SIGNATURE {
+            return Err(MovieError::InvalidSignature);
+        }
+        let version = u32::from_le_bytes(bytes[8..12].try_into().unwrap());
+        if version != VERSION {
+            return Err(MovieError::UnsupportedVersion(version));
+        }
+        let movie: Movie = bincode::deserialize(&bytes[12..]).map_err(MovieError::Corrupted)?;
+        if movie.rom != *rom {
+            return Err(MovieError::OtherRom);
+        }
+        return Ok(movie);
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), MovieError> {
+        let mut bytes = SIGNATURE.to_vec();
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        bytes.extend(bincode::serialize(self).map_err(MovieError::Corrupted)?);
+        std::fs::write(path, bytes)?;
+        return Ok(());
+    }
+
+    pub fn get_start(&self) -> Option<&[u8]> {
+        return self.start.as_deref();
+    }
+
+    /// Frames recorded.
+    pub fn len(&self) -> u64 {
+        return self.inputs.len() as u64;
+    }
+
+    pub fn get_input(&self, frame: u64) -> Option<u8> {
+        return self.inputs.get(frame as usize).copied();
+    }
+
+    pub fn push_input(&mut self, input: u8) {
+        self.inputs.push(input);
+    }
+
+    pub fn get_checkpoint(&self, frame: u64) -> Option<u64> {
+        return self
+            .checkpoints
+            .binary_search_by_key(&frame, |(checkpoint, _)| *checkpoint)
+            .ok()
+            .map(|index| self.checkpoints[index].1);
+    }
+
+    pub fn add_checkpoint(&mut self, frame: u64, hash: u64) {
+        self.checkpoints.push((frame, hash));
+    }
+}
+
+pub enum MovieMode {
+    /// Saved to the path once stopped.
+    Recording(PathBuf),
+    Playing {
+        /// First frame whose checkpoint did not match.
+        desync: Option<u64>,
+    },
+}
+
+/// Movie being recorded or played back.
+pub struct ActiveMovie {
+    pub movie: Movie,
+    pub mode: MovieMode,
+    /// Frame running, from the start of the movie.
+    pub frame: u64,
+}
+
+/// Hash of the CPU and memory, compared between recording and playback.
+pub fn hash_machine(cpu: &CPU, mem_map: &MemoryMap) -> u64 {
+    return hash(&bincode::serialize(&(cpu, mem_map)).unwrap());
+}
+
+/// 64-bit FNV-1a, which unlike the standard library hashers is guaranteed
+/// to stay the same from one build to the other.
+fn hash(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xCBF29CE484222325;
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001B3);
+    }
+    return hash;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identifies_the_whole_rom() {
+        let rom = vec![0; 0x10000];
+        let mut other = rom.clone();
+        other[0xC000] = 1;
+        assert_eq!(RomId::new(&rom), RomId::new(&rom.clone()));
+        assert_ne!(RomId::new(&rom), RomId::new(&other));
+    }
+
+    #[test]
+    fn identifies_roms_without_header() {
+        assert_eq!(RomId::new(&[]).header_checksum, 0);
+        assert_ne!(RomId::new(&[]), RomId::new(&[0x18]));
+    }
+}
@@ -0,0 +1,523 @@
+use std::path::{Path, PathBuf};
+
+/// Extensions of the patches looked for next to a ROM, in order.
+pub const PATCH_EXTENSIONS: [&str; 3] = ["ips", "ups", "bps"];
+const IPS_SIGNATURE: &[u8] = b"PATCH";
+const IPS_END: &[u8] = b"EOF";
+const UPS_SIGNATURE: &[u8] = b"UPS1";
+const BPS_SIGNATURE: &[u8] = b"BPS1";
+/// Source, target and patch CRC32 at the end of UPS and BPS patches.
+const FOOTER_SIZE: usize = 12;
+/// Largest patched ROM, a larger target size means a corrupted patch.
+const MAX_TARGET_SIZE: usize = 16 << 20;
+
+#[derive(Debug)]
+pub enum PatchError {
+    Io(std::io::Error),
+    UnknownFormat,
+    Truncated,
+    /// The patch itself is damaged.
+    PatchChecksum {
+        expected: u32,
+        actual: u32,
+    },
+    /// The patch was made for another ROM, or another revision of it.
+    SourceChecksum {
+        expected: u32,
+        actual: u32,
+    },
+    TargetChecksum {
+        expected: u32,
+        actual: u32,
+    },
+    OutOfBounds,
+    TooLarge,
+}
+
+impl std::fmt::Display for PatchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        return match self {
+            PatchError::Io(error) => write!(f, "{}", error),
+            PatchError::UnknownFormat => write!(f, "not an IPS, UPS or BPS patch"),
+            PatchError::Truncated => write!(f, "truncated patch"),
+            PatchError::PatchChecksum { expected, actual } => write!(
+                f,
+                "corrupted patch, its CRC32 is {:08X} instead of {:08X}",
+                actual, expected
+            ),
+            PatchError::SourceChecksum { expected, actual } => write!(
+                f,
+                "made for another ROM, whose CRC32 is {:08X} while this one has {:08X}",
+                expected, actual
+            ),
+            PatchError::TargetChecksum { expected, actual } => write!(
+                f,
+                "patched ROM has CRC32 {:08X} instead of {:08X}",
+                actual, expected
+            ),
+            PatchError::OutOfBounds => write!(f, "patch reaches past the end of the ROM"),
+            PatchError::TooLarge => {
+                write!(f, "patched ROM larger than {} MiB", MAX_TARGET_SIZE >> 20)
+            }
+        };
+    }
+}
+
+/// Patch named after the ROM at `rom`, next to it, if there is one.
+pub fn find(rom: &Path) -> Option<PathBuf> {
+    return PATCH_EXTENSIONS
+        .iter()
+        .map(|extension| rom.with_extension(extension))
+        .find(|path| path.is_file());
+}
+
+/// Returns `rom` patched, the format being told by the signature of
+/// `patch`.
+pub fn apply(patch: &[u8], rom: &[u8]) -> Result<Vec<u8>, PatchError> {
+    if patch.starts_with(IPS_SIGNATURE) {
+        return apply_ips(patch, rom);
+    }
+    if patch.starts_with(UPS_SIGNATURE) {
+        return apply_ups(patch, rom);
+    }
+    if patch.starts_with(BPS_SIGNATURE) {
+        return apply_bps(patch, rom);
+    }
+    return Err(PatchError::UnknownFormat);
+}
+
+/// Reads the patch from its start.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8], position: usize) -> Self {
+        return Self { bytes, position };
+    }
+
+    fn take(&mut self, length: usize) -> Result<&'a [u8], PatchError> {
+        let end = self
+            .position
+            .checked_add(length)
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or(PatchError::Truncated)?;
+        let taken = &self.bytes[self.position..end];
+        self.position = end;
+        return Ok(taken);
+    }
+
+    fn byte(&mut self) -> Result<u8, PatchError> {
+        return Ok(self.take(1)?[0]);
+    }
+
+    /// Big-endian number of `length` bytes.
+    fn number(&mut self, length: usize) -> Result<usize, PatchError> {
+        return Ok(self
+            .take(length)?
+            .iter()
+            .fold(0, |number, byte| number << 8 | *byte as usize));
+    }
+
+    /// Variable-length number of UPS and BPS, 7 bits per byte with the
+    /// last byte flagged and an offset per byte to keep encodings unique.
+    fn varint(&mut self) -> Result<usize, PatchError> {
+        let mut number: usize = 0;
+        let mut shift: usize = 1;
+        loop {
+            let byte = self.byte()?;
+            number = (byte as usize & 0x7F)
+                .checked_mul(shift)
+                .and_then(|value| number.checked_add(value))
+                .ok_or(PatchError::TooLarge)?;
+            if byte & 0x80 != 0 {
+                return Ok(number);
+            }
+            shift = shift.checked_mul(0x80).ok_or(PatchError::TooLarge)?;
+            number = number.checked_add(shift).ok_or(PatchError::TooLarge)?;
+        }
+    }
+
+    fn starts_with(&self, bytes: &[u8]) -> bool {
+        return self.bytes[self.position..].starts_with(bytes);
+    }
+
+    fn is_at(&self, position: usize) -> bool {
+        return self.position >= position;
+    }
+}
+
+/// Records of bytes or runs of a byte written at 24-bit offsets, growing
+/// the ROM when past its end. A 24-bit size may follow the end marker to
+/// truncate the ROM.
+fn apply_ips(patch: &[u8], rom: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let mut target = rom.to_vec();
+    let mut reader = Reader::new(patch, IPS_SIGNATURE.len());
+    while !reader.starts_with(IPS_END) {
+        let offset = reader.number(3)?;
+        let (length, data) = match reader.number(2)? {
+            0 => {
+                let length = reader.number(2)?;
+                (length, vec![reader.byte()?; length])
+            }
+            length => (length, reader.take(length)?.to_vec()),
+        };
+        if target.len() < offset + length {
+            target.resize(offset + length, 0);
+        }
+        target[offset..offset + length].copy_from_slice(&data);
+    }
+    reader.take(IPS_END.len())?;
+    if let Ok(size) = reader.number(3) {
+        target.truncate(size);
+    }
+    return Ok(target);
+}
+
+/// CRC32 of the source, the target and the patch itself, stored little
+/// endian at the end of UPS and BPS patches. The source one is checked
+/// before patching.
+fn check_footer(patch: &[u8], rom: &[u8]) -> Result<u32, PatchError> {
+    if patch.len() < FOOTER_SIZE {
+        return Err(PatchError::Truncated);
+    }
+    let footer = &patch[patch.len() - FOOTER_SIZE..];
+    let checksum =
+        |index: usize| u32::from_le_bytes(footer[index * 4..index * 4 + 4].try_into().unwrap());
+    let actual = crc32fast::hash(&patch[..patch.len() - 4]);
+    if actual != checksum(2) {
+        return Err(PatchError::PatchChecksum {
+            expected: checksum(2),
+            actual,
+        });
+    }
+    let actual = crc32fast::hash(rom);
+    if actual != checksum(0) {
+        return Err(PatchError::SourceChecksum {
+            expected: checksum(0),
+            actual,
+        });
+    }
+    return Ok(checksum(1));
+}
+
+fn check_target(target: &[u8], expected: u32) -> Result<(), PatchError> {
+    let actual = crc32fast::hash(target);
+    if actual != expected {
+        return Err(PatchError::TargetChecksum { expected, actual });
+    }
+    return Ok(());
+}
+
+fn read_target_size(reader: &mut Reader) -> Result<usize, PatchError> {
+    let size = reader.varint()?;
+    if size > MAX_TARGET_SIZE {
+        return Err(PatchError::TooLarge);
+    }
+    return Ok(size);
+}
+
+/// Runs of bytes XORed with the source, each after a number of bytes left
+/// unchanged, a zero ending a run.
+fn apply_ups(patch: &[u8], rom: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let target_checksum = check_footer(patch, rom)?;
+    let end = patch.len() - FOOTER_SIZE;
+    let mut reader = Reader::new(&patch[..end], UPS_SIGNATURE.len());
+    reader.varint()?;
+    let target_size = read_target_size(&mut reader)?;
+    let mut target = rom.to_vec();
+    target.resize(target_size, 0);
+    let mut position: usize = 0;
+    while !reader.is_at(end) {
+        position = position
+            .checked_add(reader.varint()?)
+            .ok_or(PatchError::OutOfBounds)?;
+        loop {
+            let byte = reader.byte()?;
+            if byte == 0 {
+                break;
+            }
+            *target.get_mut(position).ok_or(PatchError::OutOfBounds)? ^= byte;
+            position += 1;
+        }
+        position = position.checked_add(1).ok_or(PatchError::OutOfBounds)?;
+    }
+    check_target(&target, target_checksum)?;
+    return Ok(target);
+}
+
+/// Commands copying from the source, the patch or the target written so
+/// far, the copies from the source and target at relative offsets.
+fn apply_bps(patch: &[u8], rom: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let target_checksum = check_footer(patch, rom)?;
+    let end = patch.len() - FOOTER_SIZE;
+    let mut reader = Reader::new(&patch[..end], BPS_SIGNATURE.len());
+    reader.varint()?;
+    let target_size = read_target_size(&mut reader)?;
+    let metadata_size = reader.varint()?;
+    reader.take(metadata_size)?;
+    let mut target = Vec::with_capacity(target_size);
+    let mut source_offset: usize = 0;
+    let mut target_offset: usize = 0;
+    while !reader.is_at(end) {
+        let command = reader.varint()?;
+        let length = (command >> 2) + 1;
+        if target.len() + length > target_size {
+            return Err(PatchError::OutOfBounds);
+        }
+        match command & 3 {
+            // Source read, from the same offset as the target.
+            0 => {
+                let start = target.len();
+                let bytes = rom
+                    .get(start..start + length)
+                    .ok_or(PatchError::OutOfBounds)?;
+                target.extend_from_slice(bytes);
+            }
+            // Target read, from the patch.
+            1 => target.extend_from_slice(reader.take(length)?),
+            // Source copy.
+            2 => {
+                source_offset = relative(source_offset, reader.varint()?)?;
+                let source_end = source_offset
+                    .checked_add(length)
+                    .ok_or(PatchError::OutOfBounds)?;
+                let bytes = rom
+                    .get(source_offset..source_end)
+                    .ok_or(PatchError::OutOfBounds)?;
+                target.extend_from_slice(bytes);
+                source_offset += length;
+            }
+            // Target copy, one byte at a time since it may overlap what it
+            // writes.
+            _ => {
+                target_offset = relative(target_offset, reader.varint()?)?;
+                for _ in 0..length {
+                    let byte = *target.get(target_offset).ok_or(PatchError::OutOfBounds)?;
+                    target.push(byte);
+                    target_offset += 1;
+                }
+            }
+        }
+    }
+    if target.len() != target_size {
+        return Err(PatchError::Truncated);
+    }
+    check_target(&target, target_checksum)?;
+    return Ok(target);
+}
+
+/// Moves `offset` by the signed amount encoded in `data`, its lowest bit
+/// being the sign.
+fn relative(offset: usize, data: usize) -> Result<usize, PatchError> {
+    let amount = data >> 1;
+    let moved = if data & 1 != 0 {
+        offset.checked_sub(amount)
+    } else {
+        offset.checked_add(amount)
+    };
+    return moved.ok_or(PatchError::OutOfBounds);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROM: &[u8] = b"0123456789ABCDEF";
+
+    /// Encodes `number` as read by `Reader::varint`.
+    fn varint(number: usize) -> Vec<u8> {
+        let mut number = number;
+        let mut bytes = vec![];
+        loop {
+            let low = (number & 0x7F) as u8;
+            number >>= 7;
+            if number == 0 {
+                bytes.push(0x80 | low);
+                return bytes;
+            }
+            bytes.push(low);
+            number -= 1;
+        }
+    }
+
+    /// Ends a UPS or BPS patch with the checksums of `source`, `target`
+    /// and of the patch itself.
+    fn with_footer(mut patch: Vec<u8>, source: &[u8], target: &[u8]) -> Vec<u8> {
+        patch.extend_from_slice(&crc32fast::hash(source).to_le_bytes());
+        patch.extend_from_slice(&crc32fast::hash(target).to_le_bytes());
+        patch.extend_from_slice(&crc32fast::hash(&patch).to_le_bytes());
+        return patch;
+    }
+
+    /// UPS patch XORing `target` over `source`.
+    fn ups(source: &[u8], target: &[u8]) -> Vec<u8> {
+        let mut patch = UPS_SIGNATURE.to_vec();
+        patch.extend(varint(source.len()));
+        patch.extend(varint(target.len()));
+        let mut skipped = 0;
+        let mut position = 0;
+        while position < target.len() {
+            let xor = |index: usize| source.get(index).unwrap_or(&0) ^ target[index];
+            if xor(position) == 0 {
+                skipped += 1;
+                position += 1;
+                continue;
+            }
+            patch.extend(varint(skipped));
+            while position < target.len() && xor(position) != 0 {
+                patch.push(xor(position));
+                position += 1;
+            }
+            patch.push(0);
+            // The zero ending the run stands for an unchanged byte.
+            position += 1;
+            skipped = 0;
+        }
+        return with_footer(patch, source, target);
+    }
+
+    /// BPS patch from `source` to `target` running `commands`.
+    fn bps(source: &[u8], target: &[u8], commands: &[Vec<u8>]) -> Vec<u8> {
+        let mut patch = BPS_SIGNATURE.to_vec();
+        patch.extend(varint(source.len()));
+        patch.extend(varint(target.len()));
+        patch.extend(varint(0));
+        for command in commands {
+            patch.extend_from_slice(command);
+        }
+        return with_footer(patch, source, target);
+    }
+
+    fn command(kind: usize, length: usize) -> Vec<u8> {
+        return varint((length - 1) << 2 | kind);
+    }
+
+    #[test]
+    fn ips_writes_records_and_runs() {
+        let mut patch = IPS_SIGNATURE.to_vec();
+        patch.extend_from_slice(&[0x00, 0x00, 0x02, 0x00, 0x02, b'x', b'y']);
+        // A run past the end grows the ROM.
+        patch.extend_from_slice(&[0x00, 0x00, 0x12, 0x00, 0x00, 0x00, 0x03, b'z']);
+        patch.extend_from_slice(IPS_END);
+        assert_eq!(
+            apply(&patch, ROM).unwrap(),
+            b"01xy456789ABCDEF\0\0zzz".to_vec()
+        );
+    }
+
+    #[test]
+    fn ips_truncates_to_the_size_after_the_end() {
+        let mut patch = IPS_SIGNATURE.to_vec();
+        patch.extend_from_slice(IPS_END);
+        patch.extend_from_slice(&[0x00, 0x00, 0x04]);
+        assert_eq!(apply(&patch, ROM).unwrap(), b"0123".to_vec());
+    }
+
+    #[test]
+    fn ips_without_end_is_truncated() {
+        let mut patch = IPS_SIGNATURE.to_vec();
+        patch.extend_from_slice(&[0x00, 0x00, 0x02, 0x00, 0x02, b'x']);
+        assert!(matches!(apply(&patch, ROM), Err(PatchError::Truncated)));
+    }
+
+    #[test]
+    fn ups_changes_and_grows_the_rom() {
+        let target = b"0123x5678yzBCDEF!!";
+        assert_eq!(apply(&ups(ROM, target), ROM).unwrap(), target.to_vec());
+    }
+
+    #[test]
+    fn ups_shrinks_the_rom() {
+        let target = b"0123";
+        assert_eq!(apply(&ups(ROM, target), ROM).unwrap(), target.to_vec());
+    }
+
+    #[test]
+    fn bps_runs_every_command() {
+        let target = b"0123newnewnew6789ABC";
+        let commands = [
+            // 0123 from the same offset of the source.
+            command(0, 4),
+            // new from the patch.
+            [command(1, 3), b"new".to_vec()].concat(),
+            // newnew from the new written, overlapping what it writes.
+            [command(3, 6), varint(4 << 1)].concat(),
+            // 6789ABC from 6 bytes further in the source.
+            [command(2, 7), varint(6 << 1)].concat(),
+        ];
+        let patch = bps(ROM, target, &commands);
+        assert_eq!(apply(&patch, ROM).unwrap(), target.to_vec());
+    }
+
+    #[test]
+    fn rejects_unknown_formats() {
+        assert!(matches!(
+            apply(b"NOTAPATCH", ROM),
+            Err(PatchError::UnknownFormat)
+        ));
+    }
+
+    #[test]
+    fn rejects_footerless_patches() {
+        assert!(matches!(
+            apply(b"UPS1\x80", ROM),
+            Err(PatchError::Truncated)
+        ));
+    }
+
+    #[test]
+    fn rejects_damaged_patches() {
+        let mut patch = ups(ROM, b"0123x56789ABCDEF");
+        patch[6] ^= 0xFF;
+        assert!(matches!(
+            apply(&patch, ROM),
+            Err(PatchError::PatchChecksum { .. })
+        ));
+    }
+
+    #[test]
+    fn rejects_patches_for_other_roms() {
+        let patch = ups(ROM, b"0123x56789ABCDEF");
+        let expected = crc32fast::hash(ROM);
+        assert!(matches!(
+            apply(&patch, b"another ROM"),
+            Err(PatchError::SourceChecksum { expected: checksum, .. }) if checksum == expected
+        ));
+    }
+
+    #[test]
+    fn rejects_wrong_targets() {
+        // Claims a target the commands do not produce.
+        let patch = bps(ROM, b"0124", &[command(0, 4)]);
+        assert!(matches!(
+            apply(&patch, ROM),
+            Err(PatchError::TargetChecksum { .. })
+        ));
+    }
+
+    #[test]
+    fn rejects_copies_past_the_source() {
+        let target = b"0123456789ABCDEF!";
+        let patch = bps(ROM, target, &[command(0, 17)]);
+        assert!(matches!(apply(&patch, ROM), Err(PatchError::OutOfBounds)));
+    }
+
+    #[test]
+    fn rejects_offsets_far_past_the_source() {
+        let target = b"01";
+        let commands = [[command(2, 2), varint((usize::MAX >> 1) << 1)].concat()];
+        let patch = bps(ROM, target, &commands);
+        assert!(matches!(apply(&patch, ROM), Err(PatchError::OutOfBounds)));
+    }
+
+    #[test]
+    fn rejects_huge_targets() {
+        let mut patch = UPS_SIGNATURE.to_vec();
+        patch.extend(varint(ROM.len()));
+        patch.extend(varint(MAX_TARGET_SIZE + 1));
+        let patch = with_footer(patch, ROM, ROM);
+        assert!(matches!(apply(&patch, ROM), Err(PatchError::TooLarge)));
+    }
+}
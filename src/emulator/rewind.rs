@@ -0,0 +1,311 @@
+use std::collections::VecDeque;
+
+/// Snapshot older than the newest one, stored as the run-length encoded XOR
+/// of itself with the next newer snapshot. Consecutive states mostly match,
+/// so the XOR is mostly zeros.
+struct Delta {
+    frame: u64,
+    length: usize,
+    encoded: Vec<u8>,
+}
+
+/// Joypad input applied from some point of a frame on, a bit per button in
+/// `Button::ALL` order.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InputChange {
+    pub frame: u64,
+    /// Dots already run in the frame.
+    pub dots: u32,
+    pub input: u8,
+}
+
+impl InputChange {
+    fn get_position(&self) -> (u64, u32) {
+        return (self.frame, self.dots);
+    }
+}
+
+/// Ring buffer of machine states for rewinding. Only the newest state is
+/// kept whole, going back one snapshot rebuilds the previous one from its
+/// delta. The oldest snapshots are dropped once over the memory budget.
+/// The input changes in between are kept too, to replay the frames after
+/// a snapshot as they ran.
+pub struct RewindBuffer {
+    newest: Option<(u64, Vec<u8>)>,
+    /// Oldest first.
+    deltas: VecDeque<Delta>,
+    /// Oldest first, none before the oldest snapshot.
+    inputs: VecDeque<InputChange>,
+    /// Bytes the snapshots may take, 0 disabling the buffer.
+    budget: usize,
+    used: usize,
+}
+
+impl RewindBuffer {
+    pub fn new(budget: usize) -> Self {
+        Self {
+            newest: None,
+            deltas: VecDeque::new(),
+            inputs: VecDeque::new(),
+            budget,
+            used: 0,
+        }
+    }
+
+    pub fn get_budget(&self) -> usize {
+        return self.budget;
+    }
+
+    pub fn set_budget(&mut self, budget: usize) {
+        self.budget = budget;
+        self.trim();
+    }
+
+    /// Bytes taken by the snapshots.
+    pub fn get_used(&self) -> usize {
+        return self.used;
+    }
+
+    /// Frame of the oldest snapshot, the furthest one can rewind to.
+    pub fn get_oldest_frame(&self) -> Option<u64> {
+        return match self.deltas.front() {
+            Some(delta) => Some(delta.frame),
+            None => self.newest.as_ref().map(|(frame, _)| *frame),
+        };
+    }
+
+    pub fn clear(&mut self) {
+        self.newest = None;
+        self.deltas.clear();
+        self.inputs.clear();
+        self.used = 0;
+    }
+
+    /// Adds the state of the machine at the start of `frame`.
+    pub fn push(&mut self, frame: u64, state: Vec<u8>) {
+        if self.budget == 0 {
+            return;
+        }
+        if let Some((previous_frame, previous)) = self.newest.take() {
+            let encoded = encode(&xor(&previous, &state));
+            self.used = self.used - previous.len() + encoded.len();
+            self.deltas.push_back(Delta {
+                frame: previous_frame,
+                length: previous.len(),
+                encoded,
+            });
+        }
+        self.used += state.len();
+        self.newest = Some((frame, state));
+        self.trim();
+    }
+
+    /// Newest snapshot, along with its frame.
+    pub fn get_newest(&self) -> Option<(u64, &[u8])> {
+        return self
+            .newest
+            .as_ref()
+            .map(|(frame, state)| (*frame, state.as_slice()));
+    }
+
+    /// Notes that `input` applies from `dots` into `frame` on.
+    pub fn push_input(&mut self, frame: u64, dots: u32, input: u8) {
+        if self.newest.is_some() {
+            self.inputs.push_back(InputChange { frame, dots, input });
+        }
+    }
+
+    /// Input changes made from `dots` into `frame` on, oldest first.
+    pub fn get_inputs_since(&self, frame: u64, dots: u32) -> VecDeque<InputChange> {
+        return self
+            .inputs
+            .iter()
+            .filter(|change| change.get_position() >= (frame, dots))
+            .copied()
+            .collect();
+    }
+
+    /// Forgets the input changes made after `dots` into `frame`, which did
+    /// not happen once rewound there.
+    pub fn truncate_inputs(&mut self, frame: u64, dots: u32) {
+        while self
+            .inputs
+            .back()
+            .is_some_and(|change| change.get_position() > (frame, dots))
+        {
+            self.inputs.pop_back();
+        }
+    }
+
+    /// Drops the newest snapshot, the previous one becoming the newest.
+    pub fn pop(&mut self) {
+        let Some((_, newest)) = self.newest.take() else {
+            return;
+        };
+        self.used -= newest.len();
+        if let Some(delta) = self.deltas.pop_back() {
+            let mut state = decode(&delta.encoded, delta.length);
+            for (byte, newer) in state.iter_mut().zip(&newest) {
+                *byte ^= newer;
+            }
+            self.used = self.used - delta.encoded.len() + state.len();
+            self.newest = Some((delta.frame, state));
+        }
+    }
+
+    fn trim(&mut self) {
+        if self.budget == 0 {
+            self.clear();
+            return;
+        }
+        while self.used > self.budget
+            && let Some(delta) = self.deltas.pop_front()
+        {
+            self.used -= delta.encoded.len();
+        }
+        if let Some(oldest) = self.get_oldest_frame() {
+            while self
+                .inputs
+                .front()
+                .is_some_and(|change| change.frame < oldest)
+            {
+                self.inputs.pop_front();
+            }
+        }
+    }
+}
+
+/// XOR of `older` with `newer`, as long as `older`. Missing bytes of
+/// `newer` count as zeros.
+fn xor(older: &[u8], newer: &[u8]) -> Vec<u8> {
+    return older
+        .iter()
+        .enumerate()
+        .map(|(index, byte)| byte ^ newer.get(index).unwrap_or(&0))
+        .collect();
+}
+
+/// Encodes `bytes` as pairs of a run of zeros and a run of literal bytes,
+/// both lengths written as LEB128 varints and followed by the literals.
+fn encode(bytes: &[u8]) -> Vec<u8> {
+    let mut encoded = vec![];
+    let mut index = 0;
+    while index < bytes.len() {
+        let zeros = bytes[index..].iter().take_while(|&&byte| byte == 0).count();
+        index += zeros;
+        let literals = bytes[index..].iter().take_while(|&&byte| byte != 0).count();
+        write_varint(&mut encoded, zeros);
+        write_varint(&mut encoded, literals);
+        encoded.extend_from_slice(&bytes[index..index + literals]);
+        index += literals;
+    }
+    return encoded;
+}
+
+fn decode(encoded: &[u8], length: usize) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(length);
+    let mut index = 0;
+    while index < encoded.len() {
+        let zeros = read_varint(encoded, &mut index);
+        let literals = read_varint(encoded, &mut index);
+        bytes.resize(bytes.len() + zeros, 0);
+        bytes.extend_from_slice(&encoded[index..index + literals]);
+        index += literals;
+    }
+    bytes.resize(length, 0);
+    return bytes;
+}
+
+fn write_varint(output: &mut Vec<u8>, value: usize) {
+    let mut value = value;
+    while value >= 0x80 {
+        output.push((value as u8 & 0x7F) | 0x80);
+        value >>= 7;
+    }
+    output.push(value as u8);
+}
+
+fn read_varint(input: &[u8], index: &mut usize) -> usize {
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        let byte = input[*index];
+        *index += 1;
+        value |= ((byte & 0x7F) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return value;
+        }
+        shift += 7;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// State of frame `frame`, mostly the same from one frame to the next.
+    fn state(frame: u64) -> Vec<u8> {
+        let mut state = vec![0x55; 300];
+        state[frame as usize % 300] = frame as u8;
+        state.extend(std::iter::repeat_n(0, frame as usize % 5));
+        return state;
+    }
+
+    #[test]
+    fn encodes_runs() {
+        let mut bytes = vec![0; 200];
+        bytes.extend_from_slice(&[1, 2, 3]);
+        bytes.extend(vec![0; 5]);
+        bytes.push(4);
+        let encoded = encode(&bytes);
+        assert!(encoded.len() < 12);
+        assert_eq!(decode(&encoded, bytes.len()), bytes);
+        assert_eq!(decode(&encode(&[]), 0), Vec::<u8>::new());
+    }
+
+    #[test]
+    fn pops_back_to_each_snapshot() {
+        let mut buffer = RewindBuffer::new(1 << 20);
+        for frame in 0..20 {
+            buffer.push(frame, state(frame));
+        }
+        assert_eq!(buffer.get_oldest_frame(), Some(0));
+        for frame in (0..20).rev() {
+            assert_eq!(buffer.get_newest(), Some((frame, state(frame).as_slice())));
+            buffer.pop();
+        }
+        assert_eq!(buffer.get_newest(), None);
+        assert_eq!(buffer.get_used(), 0);
+    }
+
+    #[test]
+    fn drops_the_oldest_snapshots_over_budget() {
+        let mut buffer = RewindBuffer::new(400);
+        for frame in 0..100 {
+            buffer.push(frame, state(frame));
+            assert!(buffer.get_used() <= 400);
+        }
+        assert!(buffer.get_oldest_frame().unwrap() > 0);
+        assert_eq!(buffer.get_newest(), Some((99, state(99).as_slice())));
+    }
+
+    #[test]
+    fn keeps_the_input_changes_after_the_snapshots() {
+        let mut buffer = RewindBuffer::new(1 << 20);
+        // Nothing to replay them from yet.
+        buffer.push_input(0, 10, 1);
+        buffer.push(4, state(4));
+        buffer.push_input(4, 20, 2);
+        buffer.push_input(5, 0, 3);
+        buffer.push_input(6, 30, 4);
+        let inputs: Vec<u8> = buffer
+            .get_inputs_since(4, 20)
+            .iter()
+            .map(|change| change.input)
+            .collect();
+        assert_eq!(inputs, [2, 3, 4]);
+        buffer.truncate_inputs(5, 0);
+        assert_eq!(buffer.get_inputs_since(5, 0).len(), 1);
+        assert_eq!(buffer.get_inputs_since(0, 0).len(), 2);
+    }
+}
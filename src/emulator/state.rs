@@ -0,0 +1,145 @@
+use crate::hardware::ppu::{Color, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+pub const SLOT_COUNT: usize = 10;
+pub const THUMBNAIL_WIDTH: usize = SCREEN_WIDTH / 2;
+pub const THUMBNAIL_HEIGHT: usize = SCREEN_HEIGHT / 2;
+const SIGNATURE: &[u8; 8] = b"GBSTATE\0";
+/// Format of the state files, to bump whenever the serialized machine
+/// changes shape since older files can no longer be read then.
+const VERSION: u32 = 1;
+/// Signature, version and thumbnail, followed by the machine.
+const HEADER_SIZE: usize = SIGNATURE.len() + 4 + THUMBNAIL_WIDTH * THUMBNAIL_HEIGHT * 3;
+
+#[derive(Debug)]
+pub enum StateError {
+    Io(std::io::Error),
+    NoRom,
+    InvalidSignature,
+    UnsupportedVersion(u32),
+    Truncated,
+    Corrupted(bincode::Error),
+}
+
+impl std::fmt::Display for StateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        return match self {
+            StateError::Io(error) => write!(f, "{}", error),
+            StateError::NoRom => write!(f, "no ROM loaded"),
+            StateError::InvalidSignature => write!(f, "not a save state"),
+            StateError::UnsupportedVersion(version) => write!(
+                f,
+                "saved in format version {}, this build only reads version {}",
+                version, VERSION
+            ),
+            StateError::Truncated => write!(f, "truncated save state"),
+            StateError::Corrupted(error) => write!(f, "corrupted save state: {}", error),
+        };
+    }
+}
+
+impl From<std::io::Error> for StateError {
+    fn from(error: std::io::Error) -> Self {
+        return StateError::Io(error);
+    }
+}
+
+/// Contents of a quick-save slot, as shown in the slot browser.
+#[derive(Clone)]
+pub enum Slot {
+    Empty,
+    Saved {
+        thumbnail: Vec<Color>,
+        modified: SystemTime,
+    },
+    Unreadable(String),
+}
+
+impl Slot {
+    /// Reads the header of the state file at `path`.
+    pub fn read(path: &Path) -> Self {
+        let bytes = match std::fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Slot::Empty,
+            Err(error) => return Slot::Unreadable(error.to_string()),
+        };
+        let modified = std::fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .unwrap_or(SystemTime::UNIX_EPOCH);
+        return match parse(&bytes) {
+            Ok((thumbnail, _)) => Slot::Saved {
+                thumbnail,
+                modified,
+            },
+            Err(error) => Slot::Unreadable(error.to_string()),
+        };
+    }
+}
+
+/// File of quick-save `slot`, next to the ROM at `rom` and numbered from 1
+/// like in the UI.
+pub fn slot_path(rom: &Path, slot: usize) -> PathBuf {
+    return rom.with_extension(format!("ss{}", slot + 1));
+}
+
+/// Halves the screen in both directions, averaging each 2x2 block.
+pub fn thumbnail(framebuffer: &[Color]) -> Vec<Color> {
+    let mut thumbnail = Vec::with_capacity(THUMBNAIL_WIDTH * THUMBNAIL_HEIGHT);
+    for y in 0..THUMBNAIL_HEIGHT {
+        for x in 0..THUMBNAIL_WIDTH {
+            let mut sum = [0u32; 3];
+            for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                let pixel = framebuffer[(y * 2 + dy) * SCREEN_WIDTH + x * 2 + dx];
+                for channel in 0..3 {
+                    sum[channel] += pixel[channel] as u32;
+                }
+            }
+            thumbnail.push(sum.map(|channel| (channel / 4) as u8));
+        }
+    }
+    return thumbnail;
+}
+
+/// Writes a state file made of the header, with `thumbnail`, and `machine`.
+/// The file is replaced only once completely written.
+pub fn write(path: &Path, thumbnail: &[Color], machine: &[u8]) -> Result<(), StateError> {
+    let mut bytes = Vec::with_capacity(HEADER_SIZE + machine.len());
+    bytes.extend_from_slice(SIGNATURE);
+    bytes.extend_from_slice(&VERSION.to_le_bytes());
+    bytes.extend(thumbnail.iter().flatten());
+    bytes.extend_from_slice(machine);
+    let partial = path.with_extension("tmp");
+    std::fs::write(&partial, bytes)?;
+    std::fs::rename(&partial, path)?;
+    return Ok(());
+}
+
+/// Reads a state file, returning its machine.
+pub fn read(path: &Path) -> Result<Vec<u8>, StateError> {
+    let bytes = std::fs::read(path)?;
+    let (_, machine) = parse(&bytes)?;
+    return Ok(machine.to_vec());
+}
+
+fn parse(bytes: &[u8]) -> Result<(Vec<Color>, &[u8]), StateError> {
+    if bytes.len() < SIGNATURE.len() + 4 {
+        return Err(StateError::Truncated);
+    }
+    if &bytes[0..SIGNATURE.len()] != SIGNATURE {
+        return Err(StateError::InvalidSignature);
+    }
+    let version = u32::from_le_bytes(bytes[8..12].try_into().unwrap());
+    if version != VERSION {
+        return Err(StateError::UnsupportedVersion(version));
+    }
+    if bytes.len() < HEADER_SIZE {
+        return Err(StateError::Truncated);
+    }
+    let thumbnail = bytes[12..HEADER_SIZE]
+        .chunks_exact(3)
+        .map(|pixel| [pixel[0], pixel[1], pixel[2]])
+        .collect();
+    return Ok((thumbnail, &bytes[HEADER_SIZE..]));
+}
@@ -0,0 +1,105 @@
+use super::{Command, Emulator, Snapshot};
+
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Instant;
+
+/// Runs an `Emulator` on its own thread so that a slow repaint does not
+/// slow the emulation down. Commands reach it through a channel, and the
+/// latest snapshot is left in a mailbox for the UI to pick up.
+pub struct EmulatorThread {
+    commands: Sender<Command>,
+    mailbox: Arc<Mutex<Option<Snapshot>>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl EmulatorThread {
+    /// Starts the thread, building the emulator on it with `build` since the
+    /// host audio output cannot move between threads. Returns along with
+    /// the initial snapshot. `on_snapshot` is called after each published
+    /// snapshot, to wake the UI up.
+    pub fn spawn(
+        build: impl FnOnce() -> Emulator + Send + 'static,
+        on_snapshot: impl Fn() + Send + 'static,
+    ) -> (Self, Snapshot) {
+        let (commands, receiver) = mpsc::channel();
+        let (ready, initial) = mpsc::channel();
+        let mailbox = Arc::new(Mutex::new(None));
+        let shared = mailbox.clone();
+        let handle = std::thread::spawn(move || {
+            let mut emulator = build();
+            ready.send(emulator.snapshot()).unwrap();
+            run(&mut emulator, &receiver, &shared, &on_snapshot);
+            emulator.stop_movie();
+            emulator.stop_recording();
+        });
+        let snapshot = initial
+            .recv()
+            .expect("the emulation thread failed to start");
+        let thread = Self {
+            commands,
+            mailbox,
+            handle: Some(handle),
+        };
+        return (thread, snapshot);
+    }
+
+    pub fn send(&self, command: Command) {
+        // The thread only stops once told to, or after a panic that is
+        // reported when joining it.
+        let _ = self.commands.send(command);
+    }
+
+    /// Returns the snapshot published since the last call, if any.
+    pub fn take_snapshot(&self) -> Option<Snapshot> {
+        return self.mailbox.lock().unwrap().take();
+    }
+
+    /// Stops the thread and waits for it, saving any movie being recorded
+    /// and finishing any recording.
+    pub fn stop(&mut self) {
+        self.send(Command::Quit);
+        if let Some(handle) = self.handle.take()
+            && handle.join().is_err()
+        {
+            eprintln!("The emulation thread panicked");
+        }
+    }
+}
+
+impl Drop for EmulatorThread {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Handles commands and runs the frames when due, sleeping in between.
+fn run(
+    emulator: &mut Emulator,
+    commands: &Receiver<Command>,
+    mailbox: &Mutex<Option<Snapshot>>,
+    on_snapshot: &dyn Fn(),
+) {
+    loop {
+        let command = match emulator.get_next_frame() {
+            Some(deadline) => {
+                commands.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+            }
+            None => commands.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+        match command {
+            Ok(command) => {
+                if !emulator.handle(command) {
+                    return;
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => (),
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+        emulator.run_frames();
+        let snapshot = emulator.snapshot();
+        *mailbox.lock().unwrap() = Some(snapshot);
+        on_snapshot();
+    }
+}
@@ -0,0 +1,327 @@
+use crate::hardware::apu::{NR50, NR51, NR52};
+use crate::hardware::cpu::{CPU, Register};
+use crate::hardware::memory::{KEY1, MemoryMap, Model};
+use crate::hardware::ppu::DOTS_PER_FRAME;
+use crate::interpreter::{self, ExecutionError};
+use serde::{Deserialize, Serialize};
+
+pub const HEADER_SIZE: usize = 0x70;
+/// Return address pushed before calling the driver. The CPU is never run
+/// there, reaching it means the routine returned.
+pub const RETURN_ADDRESS: u16 = 0xF000;
+/// Only the first two ROM banks are mapped, banked rips are truncated.
+const ROM_END: usize = 0x8000;
+const TMA: usize = 0xFF06;
+const TAC: usize = 0xFF07;
+const TAC_ENABLE: u8 = 0b100;
+/// GBS specific TAC bit asking for CGB double speed.
+const TAC_DOUBLE_SPEED: u8 = 0x80;
+/// Timer input clock periods in dots, indexed by the TAC clock select bits.
+const TIMER_PERIODS: [u32; 4] = [1024, 16, 64, 256];
+
+#[derive(Debug)]
+pub enum GbsError {
+    InvalidSignature,
+    UnsupportedVersion(u8),
+    Truncated,
+}
+
+impl std::fmt::Display for GbsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        return match self {
+            GbsError::InvalidSignature => write!(f, "not a GBS file"),
+            GbsError::UnsupportedVersion(version) => {
+                write!(f, "unsupported GBS version {}", version)
+            }
+            GbsError::Truncated => write!(f, "truncated GBS file"),
+        };
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GbsHeader {
+    pub song_count: u8,
+    /// First song to play, from 1.
+    pub first_song: u8,
+    pub load_address: u16,
+    pub init_address: u16,
+    pub play_address: u16,
+    pub stack_pointer: u16,
+    pub timer_modulo: u8,
+    pub timer_control: u8,
+    pub title: String,
+    pub author: String,
+    pub copyright: String,
+}
+
+impl GbsHeader {
+    pub fn parse(bytes: &[u8]) -> Result<Self, GbsError> {
+        if bytes.len() < HEADER_SIZE {
+            return Err(GbsError::Truncated);
+        }
+        if &bytes[0..3] != b"GBS" {
+            return Err(GbsError::InvalidSignature);
+        }
+        if bytes[3] != 1 {
+            return Err(GbsError::UnsupportedVersion(bytes[3]));
+        }
+        let word = |offset: usize| u16::from_le_bytes([bytes[offset], bytes[offset + 1]]);
+        return Ok(Self {
+            song_count: bytes[4],
+            first_song: bytes[5],
+            load_address: word(0x06),
+            init_address: word(0x08),
+            play_address: word(0x0A),
+            stack_pointer: word(0x0C),
+            timer_modulo: bytes[0x0E],
+            timer_control: bytes[0x0F],
+            title: header_string(&bytes[0x10..0x30]),
+            author: header_string(&bytes[0x30..0x50]),
+            copyright: header_string(&bytes[0x50..0x70]),
+        });
+    }
+
+    /// The play routine is called on every timer interrupt when the timer
+    /// is enabled, on every VBlank otherwise.
+    pub fn uses_timer(&self) -> bool {
+        return self.timer_control & TAC_ENABLE != 0;
+    }
+
+    pub fn uses_double_speed(&self) -> bool {
+        return self.timer_control & TAC_DOUBLE_SPEED != 0;
+    }
+
+    /// Hardware the rip expects to run on.
+    pub fn get_model(&self) -> Model {
+        return if self.uses_double_speed() {
+            Model::CGB
+        } else {
+            Model::DMG
+        };
+    }
+
+    /// Dots between two calls of the play routine, with the timer set up
+    /// as in the header.
+    pub fn play_period(&self) -> u32 {
+        if !self.uses_timer() {
+            return DOTS_PER_FRAME;
+        }
+        return timer_period(
+            self.timer_modulo,
+            self.timer_control,
+            self.uses_double_speed(),
+        );
+    }
+}
+
+/// Dots between two timer interrupts for the given TMA and TAC.
+fn timer_period(modulo: u8, control: u8, double_speed: bool) -> u32 {
+    let ticks = 256 - modulo as u32;
+    let period = TIMER_PERIODS[(control & 0b11) as usize] * ticks;
+    // The timer runs twice as fast in double speed.
+    if double_speed {
+        return period / 2;
+    }
+    return period;
+}
+
+fn header_string(bytes: &[u8]) -> String {
+    let end = bytes
+        .iter()
+        .position(|&byte| byte == 0)
+        .unwrap_or(bytes.len());
+    return String::from_utf8_lossy(&bytes[..end]).trim().to_string();
+}
+
+/// Plays a Game Boy Sound System rip by calling its driver routines, the
+/// CPU staying idle at `RETURN_ADDRESS` in between.
+#[derive(Serialize, Deserialize)]
+pub struct GbsPlayer {
+    header: GbsHeader,
+    data: Vec<u8>,
+    /// Current song, from 0.
+    track: u8,
+    /// Dots left before the next call of the play routine.
+    countdown: u32,
+}
+
+impl GbsPlayer {
+    pub fn load(bytes: &[u8]) -> Result<Self, GbsError> {
+        let header = GbsHeader::parse(bytes)?;
+        let track = header.first_song.saturating_sub(1);
+        return Ok(Self {
+            countdown: header.play_period(),
+            header,
+            data: bytes[HEADER_SIZE..].to_vec(),
+            track,
+        });
+    }
+
+    pub fn get_header(&self) -> &GbsHeader {
+        return &self.header;
+    }
+
+    pub fn get_track(&self) -> u8 {
+        return self.track;
+    }
+
+    /// Whether all the data fits in the mapped ROM.
+    pub fn is_truncated(&self) -> bool {
+        return self.header.load_address as usize + self.data.len() > ROM_END;
+    }
+
+    /// Maps the data in a freshly reset machine and calls the init routine
+    /// with `track`, counted from 0, in A.
+    pub fn start_track(
+        &mut self,
+        mem_map: &mut MemoryMap,
+        cpu: &mut CPU,
+        track: u8,
+    ) -> Result<(), ExecutionError> {
+        self.track = track.min(self.header.song_count.saturating_sub(1));
+        self.countdown = self.header.play_period();
+
+        let load = self.header.load_address as usize;
+        let size = self.data.len().min(ROM_END.saturating_sub(load));
+        for (offset, byte) in self.data[..size].iter().enumerate() {
+            mem_map.poke_byte(load + offset, *byte)?;
+        }
+        if self.header.uses_double_speed() {
+            mem_map.write_byte(KEY1, 1)?;
+            mem_map.switch_speed();
+        }
+        mem_map.write_byte(TMA, self.header.timer_modulo)?;
+        mem_map.write_byte(TAC, self.header.timer_control & 0b111)?;
+        // Sound is on with every channel panned to both sides, as after
+        // the boot ROM.
+        mem_map.write_byte(NR52, 0x80)?;
+        mem_map.write_byte(NR51, 0xFF)?;
+        mem_map.write_byte(NR50, 0x77)?;
+
+        cpu.write_word(&Register::SP, self.header.stack_pointer);
+        cpu.write_word(&Register::PC, RETURN_ADDRESS);
+        cpu.write_byte(&Register::A, self.track);
+        interpreter::call(mem_map, cpu, self.header.init_address)?;
+        return Ok(());
+    }
+
+    /// Whether the last routine called returned.
+    pub fn is_idle(&self, cpu: &CPU) -> bool {
+        return cpu.read_word(&Register::PC) == RETURN_ADDRESS;
+    }
+
+    /// Dots the machine can idle for before the next play call.
+    pub fn get_countdown(&self) -> u32 {
+        return self.countdown;
+    }
+
+    /// Whether the play routine is called on the timer interrupt. Drivers
+    /// stopping the timer through TAC are called on every VBlank instead.
+    pub fn is_on_timer(&self, mem_map: &MemoryMap) -> Result<bool, ExecutionError> {
+        return Ok(self.header.uses_timer() && mem_map.read_byte(TAC)? & TAC_ENABLE != 0);
+    }
+
+    /// Dots between two calls of the play routine. Drivers on the timer
+    /// may change its speed by writing TMA and TAC, which are read back
+    /// instead of the header.
+    pub fn get_period(&self, mem_map: &MemoryMap) -> Result<u32, ExecutionError> {
+        if !self.is_on_timer(mem_map)? {
+            return Ok(DOTS_PER_FRAME);
+        }
+        return Ok(timer_period(
+            mem_map.read_byte(TMA)?,
+            mem_map.read_byte(TAC)?,
+            self.header.uses_double_speed(),
+        ));
+    }
+
+    /// Advances the play timer by `dots`, calling the play routine when it
+    /// is due. A call is skipped when the previous one has not returned yet.
+    pub fn tick(
+        &mut self,
+        mem_map: &mut MemoryMap,
+        cpu: &mut CPU,
+        dots: u32,
+    ) -> Result<(), ExecutionError> {
+        let period = self.get_period(mem_map)?;
+        // A faster timer interrupts before the end of the slower period.
+        self.countdown = self.countdown.min(period);
+        if dots < self.countdown {
+            self.countdown -= dots;
+            return Ok(());
+        }
+        self.countdown = period - (dots - self.countdown) % period;
+        if self.is_idle(cpu) {
+            interpreter::call(mem_map, cpu, self.header.play_address)?;
+        }
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A rip whose init and play routines return at once.
+    fn rip(timer_modulo: u8, timer_control: u8) -> Vec<u8> {
+        let mut bytes = vec![0; HEADER_SIZE];
+        bytes[0..4].copy_from_slice(b"GBS\x01");
+        bytes[4] = 1;
+        bytes[5] = 1;
+        bytes[0x06..0x08].copy_from_slice(&0x0400u16.to_le_bytes());
+        bytes[0x08..0x0A].copy_from_slice(&0x0400u16.to_le_bytes());
+        bytes[0x0A..0x0C].copy_from_slice(&0x0400u16.to_le_bytes());
+        bytes[0x0C..0x0E].copy_from_slice(&0xFFFEu16.to_le_bytes());
+        bytes[0x0E] = timer_modulo;
+        bytes[0x0F] = timer_control;
+        bytes.push(0xC9);
+        return bytes;
+    }
+
+    fn start(bytes: &[u8]) -> (GbsPlayer, MemoryMap, CPU) {
+        let mut player = GbsPlayer::load(bytes).unwrap();
+        let mut mem_map = MemoryMap::new(player.get_header().get_model());
+        let mut cpu = CPU::new();
+        player.start_track(&mut mem_map, &mut cpu, 0).unwrap();
+        return (player, mem_map, cpu);
+    }
+
+    #[test]
+    fn computes_the_play_period_from_the_header() {
+        let header = |modulo, control| GbsHeader::parse(&rip(modulo, control)).unwrap();
+        assert_eq!(header(0x00, 0x00).play_period(), DOTS_PER_FRAME);
+        assert_eq!(header(0x00, 0x04).play_period(), 1024 * 256);
+        assert_eq!(header(0xC0, 0x05).play_period(), 16 * 64);
+        assert_eq!(header(0xFF, 0x07).play_period(), 256);
+        // Double speed halves it.
+        assert_eq!(header(0x00, 0x86).play_period(), 64 * 256 / 2);
+    }
+
+    #[test]
+    fn follows_the_timer_registers() {
+        let (mut player, mut mem_map, mut cpu) = start(&rip(0x00, 0x04));
+        assert_eq!(player.get_period(&mem_map).unwrap(), 1024 * 256);
+        player.tick(&mut mem_map, &mut cpu, 10).unwrap();
+        assert_eq!(player.get_countdown(), 1024 * 256 - 10);
+        mem_map.write_byte(TMA, 0x80).unwrap();
+        // The shorter period applies at once.
+        player.tick(&mut mem_map, &mut cpu, 10).unwrap();
+        assert_eq!(player.get_countdown(), 1024 * 128 - 10);
+        mem_map.write_byte(TAC, 0x05).unwrap();
+        assert_eq!(player.get_period(&mem_map).unwrap(), 16 * 128);
+    }
+
+    #[test]
+    fn plays_on_vblank_once_the_timer_is_stopped() {
+        let (player, mut mem_map, _) = start(&rip(0x00, 0x04));
+        assert!(player.is_on_timer(&mem_map).unwrap());
+        mem_map.write_byte(TAC, 0x00).unwrap();
+        assert!(!player.is_on_timer(&mem_map).unwrap());
+        assert_eq!(player.get_period(&mem_map).unwrap(), DOTS_PER_FRAME);
+
+        // Rips on VBlank ignore TAC.
+        let (player, mut mem_map, _) = start(&rip(0x00, 0x00));
+        mem_map.write_byte(TAC, 0x04).unwrap();
+        assert!(!player.is_on_timer(&mem_map).unwrap());
+    }
+}
@@ -0,0 +1,683 @@
+mod noise;
+pub mod resampler;
+mod square;
+mod wave;
+
+use noise::NoiseChannel;
+use resampler::Resampler;
+use square::SquareChannel;
+use wave::WaveChannel;
+
+use crate::hardware::memory::Model;
+use serde::{Deserialize, Serialize};
+
+pub const NR10: usize = 0xFF10;
+pub const NR11: usize = 0xFF11;
+pub const NR12: usize = 0xFF12;
+pub const NR13: usize = 0xFF13;
+pub const NR14: usize = 0xFF14;
+pub const NR21: usize = 0xFF16;
+pub const NR22: usize = 0xFF17;
+pub const NR23: usize = 0xFF18;
+pub const NR24: usize = 0xFF19;
+pub const NR30: usize = 0xFF1A;
+pub const NR31: usize = 0xFF1B;
+pub const NR32: usize = 0xFF1C;
+pub const NR33: usize = 0xFF1D;
+pub const NR34: usize = 0xFF1E;
+pub const NR41: usize = 0xFF20;
+pub const NR42: usize = 0xFF21;
+pub const NR43: usize = 0xFF22;
+pub const NR44: usize = 0xFF23;
+pub const NR50: usize = 0xFF24;
+pub const NR51: usize = 0xFF25;
+pub const NR52: usize = 0xFF26;
+pub const WAVE_RAM: usize = 0xFF30;
+pub const APU_START: usize = NR10;
+pub const APU_END: usize = 0xFF3F;
+
+/// Frequency of the clock driving the channels, which does not change in
+/// CGB double speed.
+pub const CLOCK_RATE: u32 = 4_194_304;
+pub const DEFAULT_SAMPLE_RATE: u32 = 48_000;
+pub const CHANNEL_COUNT: usize = 4;
+/// Dots between two evaluations of the channel outputs, which makes the
+/// mixer run at about 1 MiHz before resampling.
+const MIX_DOTS: u32 = 4;
+
+/// Bits always read as set, for the registers from NR10 to 0xFF2F.
+const READ_MASKS: [u8; 0x20] = [
+    0x80, 0x3F, 0x00, 0xFF, 0xBF, // NR10-NR14
+    0xFF, 0x3F, 0x00, 0xFF, 0xBF, // NR20-NR24
+    0x7F, 0xFF, 0x9F, 0xFF, 0xBF, // NR30-NR34
+    0xFF, 0xFF, 0x00, 0x00, 0xBF, // NR40-NR44
+    0x00, 0x00, 0x70, // NR50-NR52
+    0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+];
+
+/// Points kept for each channel by the oscilloscope.
+pub const SCOPE_LENGTH: usize = 512;
+/// Dots between two oscilloscope points, the buffer spanning about a frame.
+const SCOPE_DOTS: u32 = 128;
+
+/// Samples kept when nobody takes them, over a second of audio.
+const MAX_BUFFERED_SAMPLES: usize = 65536;
+
+/// Charge factor of the high-pass filter removing the DC offset of the DACs,
+/// per dot. The filter runs on the resampled output.
+const HIGH_PASS_CHARGE: f64 = 0.999958;
+
+/// Decoded state of a channel, for the debugger.
+#[derive(Debug, Clone, Copy)]
+pub struct ChannelState {
+    pub enabled: bool,
+    pub dac_enabled: bool,
+    /// Tone frequency in Hz, or the LFSR clock for the noise channel.
+    pub frequency: f64,
+    /// Duty cycle in eighths, for the square channels.
+    pub duty: Option<u8>,
+    /// Highest digital output the channel currently reaches, from 0 to 15.
+    pub volume: u8,
+    /// Envelope direction (true when increasing) and period.
+    pub envelope: Option<(bool, u8)>,
+    pub length: u16,
+    pub length_enabled: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct LengthCounter {
+    enabled: bool,
+    counter: u16,
+    max: u16,
+}
+
+impl LengthCounter {
+    fn new(max: u16) -> Self {
+        Self {
+            enabled: false,
+            counter: 0,
+            max,
+        }
+    }
+
+    fn load(&mut self, value: u16) {
+        self.counter = self.max - value;
+    }
+
+    /// Returns true when the counter expires, disabling its channel.
+    fn clock(&mut self) -> bool {
+        if !self.enabled || self.counter == 0 {
+            return false;
+        }
+        self.counter -= 1;
+        return self.counter == 0;
+    }
+
+    /// Enabling the counter while the next frame sequencer step does not
+    /// clock it gives it an extra clock. Returns true when that expires it.
+    fn write_enable(&mut self, enabled: bool, extra_clock: bool) -> bool {
+        let was_enabled = self.enabled;
+        self.enabled = enabled;
+        if extra_clock && !was_enabled && enabled && self.counter > 0 {
+            self.counter -= 1;
+            return self.counter == 0;
+        }
+        return false;
+    }
+
+    fn trigger(&mut self, extra_clock: bool) {
+        if self.counter == 0 {
+            self.counter = self.max;
+            if self.enabled && extra_clock {
+                self.counter -= 1;
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Envelope {
+    initial: u8,
+    increase: bool,
+    period: u8,
+    timer: u8,
+    volume: u8,
+}
+
+impl Envelope {
+    fn new() -> Self {
+        Self {
+            initial: 0,
+            increase: false,
+            period: 0,
+            timer: 0,
+            volume: 0,
+        }
+    }
+
+    fn write(&mut self, byte: u8) {
+        self.initial = byte >> 4;
+        self.increase = byte & 0b1000 != 0;
+        self.period = byte & 0b111;
+    }
+
+    /// The DAC of a channel with an envelope is powered by the upper five
+    /// bits of its NRx2 register.
+    fn dac_enabled(&self) -> bool {
+        return self.initial != 0 || self.increase;
+    }
+
+    fn trigger(&mut self) {
+        self.volume = self.initial;
+        self.timer = if self.period == 0 { 8 } else { self.period };
+    }
+
+    fn clock(&mut self) {
+        if self.period == 0 {
+            return;
+        }
+        self.timer = self.timer.saturating_sub(1);
+        if self.timer > 0 {
+            return;
+        }
+        self.timer = self.period;
+        if self.increase && self.volume < 15 {
+            self.volume += 1;
+        } else if !self.increase && self.volume > 0 {
+            self.volume -= 1;
+        }
+    }
+}
+
+/// Resampled output of the APU, either the full mix or a single channel.
+#[derive(Debug, Clone)]
+struct Output {
+    resampler: Resampler,
+    /// Charge of the high-pass filter capacitor, for each side.
+    capacitor: [f64; 2],
+}
+
+impl Output {
+    fn new(sample_rate: u32) -> Self {
+        Self {
+            resampler: Resampler::new(CLOCK_RATE as f64, sample_rate as f64),
+            capacitor: [0.0; 2],
+        }
+    }
+
+    fn read(&mut self, charge: f64) -> Vec<[f32; 2]> {
+        let mut samples = self.resampler.read();
+        for sample in samples.iter_mut() {
+            for (side, value) in sample.iter_mut().enumerate() {
+                let output = *value as f64 - self.capacitor[side];
+                self.capacitor[side] = *value as f64 - output * charge;
+                *value = output as f32;
+            }
+        }
+        return samples;
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct APU {
+    model: Model,
+    enabled: bool,
+    /// Last values written to NR10-0xFF2F, read back through `READ_MASKS`.
+    registers: [u8; 0x20],
+    square1: SquareChannel,
+    square2: SquareChannel,
+    wave: WaveChannel,
+    noise: NoiseChannel,
+    /// Next step of the frame sequencer, from 0 to 7.
+    frame_step: u8,
+    /// Dots elapsed since the channels were last mixed.
+    mix_dots: u32,
+    // The output side is not part of the machine state, see `keep_output`.
+    #[serde(skip, default = "default_sample_rate")]
+    sample_rate: u32,
+    #[serde(skip, default = "default_output")]
+    output: Output,
+    /// Separate outputs for each channel, only produced when requested.
+    #[serde(skip)]
+    stems: Option<Vec<Output>>,
+    /// Channels heard in the mix, to mute them while debugging.
+    #[serde(skip, default = "all_audible")]
+    audible: [bool; CHANNEL_COUNT],
+    /// Recent output of each channel, as a ring buffer starting at
+    /// `scope_index`.
+    #[serde(skip, default = "empty_scope")]
+    scope: [[f32; SCOPE_LENGTH]; CHANNEL_COUNT],
+    #[serde(skip)]
+    scope_index: usize,
+    #[serde(skip)]
+    scope_dots: u32,
+    #[serde(skip, default = "default_high_pass_charge")]
+    high_pass_charge: f64,
+}
+
+impl APU {
+    pub fn new(model: Model, sample_rate: u32) -> Self {
+        Self {
+            model,
+            enabled: false,
+            registers: [0; 0x20],
+            square1: SquareChannel::new(true),
+            square2: SquareChannel::new(false),
+            wave: WaveChannel::new(),
+            noise: NoiseChannel::new(),
+            frame_step: 0,
+            sample_rate,
+            mix_dots: 0,
+            output: Output::new(sample_rate),
+            stems: None,
+            audible: [true; CHANNEL_COUNT],
+            scope: [[0.0; SCOPE_LENGTH]; CHANNEL_COUNT],
+            scope_index: 0,
+            scope_dots: 0,
+            high_pass_charge: high_pass_charge(sample_rate),
+        }
+    }
+
+    pub fn get_sample_rate(&self) -> u32 {
+        return self.sample_rate;
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+        self.high_pass_charge = high_pass_charge(sample_rate);
+        self.output = Output::new(sample_rate);
+        if self.stems.is_some() {
+            self.set_stems_enabled(true);
+        }
+    }
+
+    /// Scales the resampling ratio by `adjust`, close to 1, so that the
+    /// frontend can produce slightly more or fewer samples to keep its
+    /// audio buffer level stable.
+    pub fn set_rate_adjust(&mut self, adjust: f64) {
+        let sample_rate = self.sample_rate as f64 * adjust;
+        self.output
+            .resampler
+            .set_rates(CLOCK_RATE as f64, sample_rate);
+        for stem in self.stems.iter_mut().flatten() {
+            stem.resampler.set_rates(CLOCK_RATE as f64, sample_rate);
+        }
+    }
+
+    /// Starts or stops producing the output of each channel separately, in
+    /// sync with the mix.
+    pub fn set_stems_enabled(&mut self, enabled: bool) {
+        self.stems = enabled.then(|| {
+            let mut resampler = self.output.resampler.clone();
+            resampler.reset();
+            let stem = Output {
+                resampler,
+                capacitor: [0.0; 2],
+            };
+            vec![stem; CHANNEL_COUNT]
+        });
+    }
+
+    /// Takes over the output of `previous`, along with its settings, so that
+    /// a restored state keeps playing through the same output.
+    pub fn keep_output(&mut self, previous: &mut APU) {
+        std::mem::swap(&mut self.output, &mut previous.output);
+        std::mem::swap(&mut self.stems, &mut previous.stems);
+        std::mem::swap(&mut self.scope, &mut previous.scope);
+        self.scope_index = previous.scope_index;
+        self.scope_dots = previous.scope_dots;
+        self.audible = previous.audible;
+        self.sample_rate = previous.sample_rate;
+        self.high_pass_charge = previous.high_pass_charge;
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        return self.enabled;
+    }
+
+    /// Selects the channels heard in the mix. Stems and the oscilloscope
+    /// still get every channel.
+    pub fn set_audible_channels(&mut self, audible: [bool; CHANNEL_COUNT]) {
+        self.audible = audible;
+    }
+
+    pub fn get_channel_state(&self, channel: usize) -> ChannelState {
+        return match channel {
+            0 => self.square1.get_state(),
+            1 => self.square2.get_state(),
+            2 => self.wave.get_state(),
+            _ => self.noise.get_state(),
+        };
+    }
+
+    /// Returns the recent output of a channel, oldest first, between -1 and
+    /// 1.
+    pub fn get_scope(&self, channel: usize) -> Vec<f32> {
+        let scope = &self.scope[channel];
+        return scope[self.scope_index..]
+            .iter()
+            .chain(&scope[..self.scope_index])
+            .copied()
+            .collect();
+    }
+
+    /// Returns the stereo samples produced since the last call.
+    pub fn take_samples(&mut self) -> Vec<[f32; 2]> {
+        return self.output.read(self.high_pass_charge);
+    }
+
+    /// Returns the samples of each channel produced since the last call,
+    /// when stems are enabled. They match the samples of `take_samples`.
+    pub fn take_stems(&mut self) -> Option<Vec<Vec<[f32; 2]>>> {
+        let charge = self.high_pass_charge;
+        return self
+            .stems
+            .as_mut()
+            .map(|stems| stems.iter_mut().map(|stem| stem.read(charge)).collect());
+    }
+
+    pub fn read_register(&self, address: usize) -> u8 {
+        return match address {
+            WAVE_RAM..=APU_END => self.wave.wave_ram[address - WAVE_RAM],
+            NR52 => {
+                let status = self.square1.enabled as u8
+                    | (self.square2.enabled as u8) << 1
+                    | (self.wave.enabled as u8) << 2
+                    | (self.noise.enabled as u8) << 3;
+                (self.enabled as u8) << 7 | READ_MASKS[NR52 - APU_START] | status
+            }
+            _ => self.registers[address - APU_START] | READ_MASKS[address - APU_START],
+        };
+    }
+
+    /// Last value written to a register, without the bits reading as 1.
+    /// NR52 and the wave RAM read as usual.
+    pub fn peek_register(&self, address: usize) -> u8 {
+        return match address {
+            NR52 | WAVE_RAM..=APU_END => self.read_register(address),
+            _ => self.registers[address - APU_START],
+        };
+    }
+
+    pub fn write_register(&mut self, address: usize, byte: u8) {
+        if (WAVE_RAM..=APU_END).contains(&address) {
+            self.wave.wave_ram[address - WAVE_RAM] = byte;
+            return;
+        }
+        if address == NR52 {
+            self.write_power(byte & 0x80 != 0);
+            return;
+        }
+        if !self.enabled {
+            // On DMG the length counters stay writable while powered off.
+            if self.model == Model::DMG {
+                match address {
+                    NR11 => self.square1.length.load((byte & 0x3F) as u16),
+                    NR21 => self.square2.length.load((byte & 0x3F) as u16),
+                    NR31 => self.wave.write_length(byte),
+                    NR41 => self.noise.write_length(byte),
+                    _ => (),
+                }
+            }
+            return;
+        }
+        self.registers[address - APU_START] = byte;
+        // Enabling a length counter gets an extra clock when the next step
+        // of the frame sequencer does not clock the length counters.
+        let extra_length_clock = self.frame_step % 2 == 1;
+        match address {
+            NR10 => self.square1.write_sweep(byte),
+            NR11 => self.square1.write_length_duty(byte),
+            NR12 => self.square1.write_envelope(byte),
+            NR13 => self.square1.write_frequency_low(byte),
+            NR14 => self.square1.write_frequency_high(byte, extra_length_clock),
+            NR21 => self.square2.write_length_duty(byte),
+            NR22 => self.square2.write_envelope(byte),
+            NR23 => self.square2.write_frequency_low(byte),
+            NR24 => self.square2.write_frequency_high(byte, extra_length_clock),
+            NR30 => self.wave.write_dac(byte),
+            NR31 => self.wave.write_length(byte),
+            NR32 => self.wave.write_volume(byte),
+            NR33 => self.wave.write_frequency_low(byte),
+            NR34 => self.wave.write_frequency_high(byte, extra_length_clock),
+            NR41 => self.noise.write_length(byte),
+            NR42 => self.noise.write_envelope(byte),
+            NR43 => self.noise.write_polynomial(byte),
+            NR44 => self.noise.write_control(byte, extra_length_clock),
+            _ => (),
+        }
+    }
+
+    fn write_power(&mut self, enabled: bool) {
+        if enabled && !self.enabled {
+            self.frame_step = 0;
+        }
+        if !enabled && self.enabled {
+            // The DMG does not reset the length counters, which clearing
+            // the length registers would reload.
+            let counters = [
+                self.square1.length.counter,
+                self.square2.length.counter,
+                self.wave.length.counter,
+                self.noise.length.counter,
+            ];
+            // Powering off clears every register but the wave RAM, which
+            // also resets the channels.
+            for address in NR10..NR52 {
+                self.write_register(address, 0);
+            }
+            let mut square1 = SquareChannel::new(true);
+            let mut square2 = SquareChannel::new(false);
+            let mut wave = WaveChannel::new();
+            let mut noise = NoiseChannel::new();
+            wave.wave_ram = self.wave.wave_ram;
+            if self.model == Model::DMG {
+                [
+                    square1.length.counter,
+                    square2.length.counter,
+                    wave.length.counter,
+                    noise.length.counter,
+                ] = counters;
+            }
+            (self.square1, self.square2, self.wave, self.noise) = (square1, square2, wave, noise);
+        }
+        self.enabled = enabled;
+    }
+
+    /// Called on the falling edge of the DIV bit driving the 512 Hz frame
+    /// sequencer.
+    pub fn clock_frame_sequencer(&mut self) {
+        if !self.enabled {
+            return;
+        }
+        if self.frame_step.is_multiple_of(2) {
+            self.square1.clock_length();
+            self.square2.clock_length();
+            self.wave.clock_length();
+            self.noise.clock_length();
+        }
+        if self.frame_step == 2 || self.frame_step == 6 {
+            self.square1.clock_sweep();
+        }
+        if self.frame_step == 7 {
+            self.square1.envelope.clock();
+            self.square2.envelope.clock();
+            self.noise.envelope.clock();
+        }
+        self.frame_step = (self.frame_step + 1) % 8;
+    }
+
+    /// Advances the channels by `dots`, feeding their mix to the resampler.
+    pub fn tick(&mut self, dots: u32) {
+        let mut dots = dots;
+        while dots > 0 {
+            let step = dots.min(MIX_DOTS - self.mix_dots);
+            self.square1.tick(step);
+            self.square2.tick(step);
+            self.wave.tick(step);
+            self.noise.tick(step);
+            dots -= step;
+            self.mix_dots += step;
+            if self.mix_dots == MIX_DOTS {
+                self.mix_dots = 0;
+                let channels = self.mix_channels();
+                let amplitude = channels
+                    .iter()
+                    .zip(self.audible)
+                    .filter(|(_, audible)| *audible)
+                    .fold([0.0; 2], |mix, (channel, _)| {
+                        [mix[0] + channel[0], mix[1] + channel[1]]
+                    });
+                self.output.resampler.add(MIX_DOTS, amplitude);
+                if let Some(stems) = self.stems.as_mut() {
+                    for (stem, channel) in stems.iter_mut().zip(channels) {
+                        stem.resampler.add(MIX_DOTS, channel);
+                    }
+                }
+                self.scope_dots += MIX_DOTS;
+                if self.scope_dots >= SCOPE_DOTS {
+                    self.scope_dots = 0;
+                    for channel in 0..CHANNEL_COUNT {
+                        self.scope[channel][self.scope_index] = self.channel_output(channel);
+                    }
+                    self.scope_index = (self.scope_index + 1) % SCOPE_LENGTH;
+                }
+            }
+        }
+        if self.output.resampler.available() > MAX_BUFFERED_SAMPLES {
+            // Nobody is listening, drop the oldest samples.
+            self.take_samples();
+            self.take_stems();
+        }
+    }
+
+    /// Analog output of a channel DAC, between -1 and 1, before panning.
+    pub fn channel_output(&self, channel: usize) -> f32 {
+        let (dac_enabled, digital) = match channel {
+            0 => (self.square1.dac_enabled(), self.square1.output()),
+            1 => (self.square2.dac_enabled(), self.square2.output()),
+            2 => (self.wave.dac_enabled(), self.wave.output()),
+            _ => (self.noise.dac_enabled(), self.noise.output()),
+        };
+        if !dac_enabled {
+            return 0.0;
+        }
+        return 1.0 - digital as f32 / 7.5;
+    }
+
+    /// Contribution of each channel to the left and right outputs, after
+    /// panning and master volume. Their sum is the mix.
+    fn mix_channels(&self) -> [[f32; 2]; CHANNEL_COUNT] {
+        let mut channels = [[0.0; 2]; CHANNEL_COUNT];
+        if !self.enabled {
+            return channels;
+        }
+        let panning = self.registers[NR51 - APU_START];
+        let volume = self.registers[NR50 - APU_START];
+        let left_volume = (((volume >> 4) & 0b111) + 1) as f32 / 8.0 / CHANNEL_COUNT as f32;
+        let right_volume = ((volume & 0b111) + 1) as f32 / 8.0 / CHANNEL_COUNT as f32;
+        for (channel, sides) in channels.iter_mut().enumerate() {
+            let output = self.channel_output(channel);
+            if panning & (0x10 << channel) != 0 {
+                sides[0] = output * left_volume;
+            }
+            if panning & (0x01 << channel) != 0 {
+                sides[1] = output * right_volume;
+            }
+        }
+        return channels;
+    }
+}
+
+fn default_output() -> Output {
+    return Output::new(DEFAULT_SAMPLE_RATE);
+}
+
+fn default_sample_rate() -> u32 {
+    return DEFAULT_SAMPLE_RATE;
+}
+
+fn default_high_pass_charge() -> f64 {
+    return high_pass_charge(DEFAULT_SAMPLE_RATE);
+}
+
+fn all_audible() -> [bool; CHANNEL_COUNT] {
+    return [true; CHANNEL_COUNT];
+}
+
+fn empty_scope() -> [[f32; SCOPE_LENGTH]; CHANNEL_COUNT] {
+    return [[0.0; SCOPE_LENGTH]; CHANNEL_COUNT];
+}
+
+fn high_pass_charge(sample_rate: u32) -> f64 {
+    return HIGH_PASS_CHARGE.powf(CLOCK_RATE as f64 / sample_rate as f64);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hardware::memory::{MemoryMap, Speed};
+
+    /// Starts square 1 with its length counter one clock from expiring.
+    fn start_expiring_square(write: &mut impl FnMut(usize, u8)) {
+        write(NR52, 0x80);
+        write(NR11, 0x3F);
+        write(NR12, 0xF0);
+        write(NR14, 0xC0);
+    }
+
+    fn steps_on_falling_edges(speed: Speed, cycles: u32) {
+        let mut mem_map = MemoryMap::new(Model::CGB);
+        mem_map.set_speed(speed);
+        start_expiring_square(&mut |address, byte| mem_map.write_byte(address, byte).unwrap());
+        mem_map.tick(cycles - 1).unwrap();
+        assert_eq!(mem_map.read_byte(NR52).unwrap() & 1, 1);
+        mem_map.tick(1).unwrap();
+        assert_eq!(mem_map.read_byte(NR52).unwrap() & 1, 0);
+    }
+
+    #[test]
+    fn steps_the_frame_sequencer_when_div_bit_12_falls() {
+        steps_on_falling_edges(Speed::Normal, 2048);
+    }
+
+    #[test]
+    fn steps_the_frame_sequencer_when_div_bit_13_falls_in_double_speed() {
+        steps_on_falling_edges(Speed::Double, 4096);
+    }
+
+    #[test]
+    fn disables_channels_when_their_length_expires() {
+        let mut apu = APU::new(Model::DMG, DEFAULT_SAMPLE_RATE);
+        apu.write_register(NR52, 0x80);
+        apu.write_register(NR42, 0xF0);
+        apu.write_register(NR41, 0x3E);
+        apu.write_register(NR44, 0xC0);
+        assert_eq!(apu.read_register(NR52), 0xF8);
+        // Only the even steps clock the length counters.
+        apu.clock_frame_sequencer();
+        apu.clock_frame_sequencer();
+        assert_eq!(apu.read_register(NR52), 0xF8);
+        apu.clock_frame_sequencer();
+        assert_eq!(apu.read_register(NR52), 0xF0);
+    }
+
+    #[test]
+    fn clears_the_registers_when_powered_off() {
+        for model in [Model::DMG, Model::CGB] {
+            let mut apu = APU::new(model, DEFAULT_SAMPLE_RATE);
+            start_expiring_square(&mut |address, byte| apu.write_register(address, byte));
+            apu.write_register(NR50, 0x77);
+            apu.write_register(WAVE_RAM, 0x12);
+            apu.write_register(NR52, 0x00);
+            assert_eq!(apu.read_register(NR52), 0x70);
+            assert_eq!(apu.read_register(NR50), 0x00);
+            assert_eq!(apu.read_register(NR12), 0x00);
+            assert_eq!(apu.read_register(WAVE_RAM), 0x12);
+            // Only the DMG keeps its length counters.
+            let length = apu.get_channel_state(0).length;
+            assert_eq!(length, if model == Model::DMG { 1 } else { 0 });
+            assert!(!apu.get_channel_state(0).length_enabled);
+        }
+    }
+}
@@ -0,0 +1,151 @@
+use super::{CLOCK_RATE, ChannelState, Envelope, LengthCounter};
+use serde::{Deserialize, Serialize};
+
+const DIVISORS: [u32; 8] = [8, 16, 32, 48, 64, 80, 96, 112];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(super) struct NoiseChannel {
+    pub(super) enabled: bool,
+    clock_shift: u8,
+    /// Whether the LFSR is shortened to 7 bits, giving a more tonal noise.
+    short_mode: bool,
+    divisor_code: u8,
+    timer: u32,
+    lfsr: u16,
+    pub(super) length: LengthCounter,
+    pub(super) envelope: Envelope,
+}
+
+impl NoiseChannel {
+    pub(super) fn new() -> Self {
+        Self {
+            enabled: false,
+            clock_shift: 0,
+            short_mode: false,
+            divisor_code: 0,
+            timer: 0,
+            lfsr: 0x7FFF,
+            length: LengthCounter::new(64),
+            envelope: Envelope::new(),
+        }
+    }
+
+    fn period(&self) -> u32 {
+        return DIVISORS[self.divisor_code as usize] << self.clock_shift;
+    }
+
+    pub(super) fn write_length(&mut self, byte: u8) {
+        self.length.load((byte & 0x3F) as u16);
+    }
+
+    pub(super) fn write_envelope(&mut self, byte: u8) {
+        self.envelope.write(byte);
+        if !self.envelope.dac_enabled() {
+            self.enabled = false;
+        }
+    }
+
+    pub(super) fn write_polynomial(&mut self, byte: u8) {
+        self.clock_shift = byte >> 4;
+        self.short_mode = byte & 0b1000 != 0;
+        self.divisor_code = byte & 0b111;
+    }
+
+    pub(super) fn write_control(&mut self, byte: u8, extra_length_clock: bool) {
+        let trigger = byte & 0x80 != 0;
+        if self
+            .length
+            .write_enable(byte & 0x40 != 0, extra_length_clock)
+            && !trigger
+        {
+            self.enabled = false;
+        }
+        if trigger {
+            self.enabled = self.envelope.dac_enabled();
+            self.length.trigger(extra_length_clock);
+            self.envelope.trigger();
+            self.timer = self.period();
+            self.lfsr = 0x7FFF;
+        }
+    }
+
+    pub(super) fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    pub(super) fn tick(&mut self, cycles: u32) {
+        let mut cycles = cycles;
+        while cycles >= self.timer {
+            cycles -= self.timer;
+            self.timer = self.period();
+            let feedback = (self.lfsr ^ (self.lfsr >> 1)) & 1;
+            self.lfsr = (self.lfsr >> 1) | (feedback << 14);
+            if self.short_mode {
+                self.lfsr = (self.lfsr & !(1 << 6)) | (feedback << 6);
+            }
+        }
+        self.timer -= cycles;
+    }
+
+    pub(super) fn get_state(&self) -> ChannelState {
+        return ChannelState {
+            enabled: self.enabled,
+            dac_enabled: self.dac_enabled(),
+            frequency: CLOCK_RATE as f64 / self.period() as f64,
+            duty: None,
+            volume: self.envelope.volume,
+            envelope: Some((self.envelope.increase, self.envelope.period)),
+            length: self.length.counter,
+            length_enabled: self.length.enabled,
+        };
+    }
+
+    pub(super) fn dac_enabled(&self) -> bool {
+        return self.envelope.dac_enabled();
+    }
+
+    /// Digital output of the channel, between 0 and 15.
+    pub(super) fn output(&self) -> u8 {
+        if !self.enabled || self.lfsr & 1 != 0 {
+            return 0;
+        }
+        return self.envelope.volume;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Low bits of the LFSR after each of `count` shifts.
+    fn lfsr_sequence(polynomial: u8, count: usize) -> Vec<u16> {
+        let mut noise = NoiseChannel::new();
+        noise.write_envelope(0xF0);
+        noise.write_polynomial(polynomial);
+        noise.write_control(0x80, false);
+        return (0..count)
+            .map(|_| {
+                noise.tick(noise.period());
+                noise.lfsr & 0x7F
+            })
+            .collect();
+    }
+
+    #[test]
+    fn repeats_every_127_shifts_in_7_bit_mode() {
+        let sequence = lfsr_sequence(0x08, 254);
+        assert_eq!(sequence[..127], sequence[127..]);
+        let mut states = sequence[..127].to_vec();
+        states.sort();
+        states.dedup();
+        assert_eq!(states.len(), 127);
+    }
+
+    #[test]
+    fn does_not_repeat_as_soon_in_15_bit_mode() {
+        let sequence = lfsr_sequence(0x00, 254);
+        assert_ne!(sequence[..127], sequence[127..]);
+    }
+}
@@ -0,0 +1,121 @@
+use std::f64::consts::PI;
+
+/// Output samples covered by the band-limited step added for each change of
+/// the input signal.
+const KERNEL_WIDTH: usize = 16;
+/// Sub-sample positions the kernel is precomputed for.
+const KERNEL_PHASES: usize = 64;
+/// Cutoff of the low-pass filter relative to the output Nyquist frequency,
+/// slightly below it so that the kernel roll-off does not alias.
+const CUTOFF: f64 = 0.9;
+
+/// Converts a stepped signal clocked at `clock_rate` to `sample_rate` by
+/// adding a band-limited step to the output for each change of the input,
+/// which avoids the aliasing of plain decimation.
+#[derive(Debug, Clone)]
+pub struct Resampler {
+    /// Input clocks elapsed for each output sample.
+    clocks_per_sample: f64,
+    /// Position of the next input clock, in output samples from the start
+    /// of `deltas`.
+    time: f64,
+    /// Amplitude changes spread by the kernel, integrated when read.
+    deltas: Vec<[f32; 2]>,
+    level: [f32; 2],
+    integrator: [f32; 2],
+    kernel: Vec<[f32; KERNEL_WIDTH]>,
+}
+
+impl Resampler {
+    pub fn new(clock_rate: f64, sample_rate: f64) -> Self {
+        Self {
+            clocks_per_sample: clock_rate / sample_rate,
+            time: 0.0,
+            deltas: vec![[0.0; 2]; KERNEL_WIDTH],
+            level: [0.0; 2],
+            integrator: [0.0; 2],
+            kernel: build_kernel(),
+        }
+    }
+
+    /// Changes the conversion ratio, keeping the samples already produced.
+    pub fn set_rates(&mut self, clock_rate: f64, sample_rate: f64) {
+        self.clocks_per_sample = clock_rate / sample_rate;
+    }
+
+    /// Silences the pending output, keeping the conversion ratio and the
+    /// position, so that a copy stays in sync with the original.
+    pub fn reset(&mut self) {
+        self.deltas = vec![[0.0; 2]; self.time as usize + KERNEL_WIDTH];
+        self.level = [0.0; 2];
+        self.integrator = [0.0; 2];
+    }
+
+    /// Adds `clocks` input clocks during which the signal holds `amplitude`.
+    pub fn add(&mut self, clocks: u32, amplitude: [f32; 2]) {
+        if amplitude != self.level {
+            let position = self.time.floor();
+            let phase = ((self.time - position) * KERNEL_PHASES as f64) as usize;
+            let start = position as usize;
+            let delta = [amplitude[0] - self.level[0], amplitude[1] - self.level[1]];
+            let taps = &self.kernel[phase.min(KERNEL_PHASES - 1)];
+            for (offset, tap) in taps.iter().enumerate() {
+                let slot = &mut self.deltas[start + offset];
+                slot[0] += delta[0] * tap;
+                slot[1] += delta[1] * tap;
+            }
+            self.level = amplitude;
+        }
+        self.time += clocks as f64 / self.clocks_per_sample;
+        let needed = self.time as usize + KERNEL_WIDTH;
+        if self.deltas.len() < needed {
+            self.deltas.resize(needed, [0.0; 2]);
+        }
+    }
+
+    /// Output samples that no later input can change anymore.
+    pub fn available(&self) -> usize {
+        return self.time as usize;
+    }
+
+    /// Returns the available output samples.
+    pub fn read(&mut self) -> Vec<[f32; 2]> {
+        let count = self.available();
+        let mut samples = Vec::with_capacity(count);
+        for delta in self.deltas.drain(..count) {
+            self.integrator[0] += delta[0];
+            self.integrator[1] += delta[1];
+            samples.push(self.integrator);
+        }
+        self.deltas
+            .resize(self.deltas.len().max(KERNEL_WIDTH), [0.0; 2]);
+        self.time -= count as f64;
+        return samples;
+    }
+}
+
+/// Builds a Blackman windowed sinc impulse for each phase, each summing to
+/// one so that integrating it gives a unit step.
+fn build_kernel() -> Vec<[f32; KERNEL_WIDTH]> {
+    let half = KERNEL_WIDTH as f64 / 2.0;
+    return (0..KERNEL_PHASES)
+        .map(|phase| {
+            let fraction = phase as f64 / KERNEL_PHASES as f64;
+            let mut taps = [0.0; KERNEL_WIDTH];
+            for (offset, tap) in taps.iter_mut().enumerate() {
+                let x = offset as f64 - half - fraction + 1.0;
+                let sinc = if x == 0.0 {
+                    1.0
+                } else {
+                    (PI * CUTOFF * x).sin() / (PI * CUTOFF * x)
+                };
+                let window_position = (x + half) / KERNEL_WIDTH as f64;
+                let window = 0.42 - 0.5 * (2.0 * PI * window_position).cos()
+                    + 0.08 * (4.0 * PI * window_position).cos();
+                *tap = sinc * window.max(0.0);
+            }
+            let sum: f64 = taps.iter().sum();
+            return taps.map(|tap| (tap / sum) as f32);
+        })
+        .collect();
+}
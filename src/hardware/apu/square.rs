@@ -0,0 +1,208 @@
+use super::{CLOCK_RATE, ChannelState, Envelope, LengthCounter};
+use serde::{Deserialize, Serialize};
+
+const DUTY_PATTERNS: [[u8; 8]; 4] = [
+    [0, 0, 0, 0, 0, 0, 0, 1],
+    [1, 0, 0, 0, 0, 0, 0, 1],
+    [1, 0, 0, 0, 0, 1, 1, 1],
+    [0, 1, 1, 1, 1, 1, 1, 0],
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(super) struct Sweep {
+    period: u8,
+    negate: bool,
+    shift: u8,
+    timer: u8,
+    enabled: bool,
+    shadow: u16,
+    /// Set once a calculation was done in negate mode, switching back to
+    /// addition afterwards disables the channel.
+    negate_used: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(super) struct SquareChannel {
+    pub(super) enabled: bool,
+    duty: u8,
+    duty_step: u8,
+    frequency: u16,
+    timer: u32,
+    pub(super) length: LengthCounter,
+    pub(super) envelope: Envelope,
+    sweep: Option<Sweep>,
+}
+
+impl SquareChannel {
+    pub(super) fn new(with_sweep: bool) -> Self {
+        let sweep = with_sweep.then_some(Sweep {
+            period: 0,
+            negate: false,
+            shift: 0,
+            timer: 0,
+            enabled: false,
+            shadow: 0,
+            negate_used: false,
+        });
+        Self {
+            enabled: false,
+            duty: 0,
+            duty_step: 0,
+            frequency: 0,
+            timer: 0,
+            length: LengthCounter::new(64),
+            envelope: Envelope::new(),
+            sweep,
+        }
+    }
+
+    fn period(&self) -> u32 {
+        return (2048 - self.frequency as u32) * 4;
+    }
+
+    pub(super) fn write_sweep(&mut self, byte: u8) {
+        let Some(sweep) = self.sweep.as_mut() else {
+            return;
+        };
+        sweep.period = (byte >> 4) & 0b111;
+        sweep.negate = byte & 0b1000 != 0;
+        sweep.shift = byte & 0b111;
+        if sweep.negate_used && !sweep.negate {
+            self.enabled = false;
+        }
+    }
+
+    pub(super) fn write_length_duty(&mut self, byte: u8) {
+        self.duty = byte >> 6;
+        self.length.load((byte & 0x3F) as u16);
+    }
+
+    pub(super) fn write_envelope(&mut self, byte: u8) {
+        self.envelope.write(byte);
+        if !self.envelope.dac_enabled() {
+            self.enabled = false;
+        }
+    }
+
+    pub(super) fn write_frequency_low(&mut self, byte: u8) {
+        self.frequency = (self.frequency & 0x700) | byte as u16;
+    }
+
+    /// `extra_length_clock` tells whether the next frame sequencer step will
+    /// not clock the length counter, see `LengthCounter::write_enable`.
+    pub(super) fn write_frequency_high(&mut self, byte: u8, extra_length_clock: bool) {
+        self.frequency = (self.frequency & 0xFF) | (((byte & 0b111) as u16) << 8);
+        let trigger = byte & 0x80 != 0;
+        if self
+            .length
+            .write_enable(byte & 0x40 != 0, extra_length_clock)
+            && !trigger
+        {
+            self.enabled = false;
+        }
+        if trigger {
+            self.trigger(extra_length_clock);
+        }
+    }
+
+    fn trigger(&mut self, extra_length_clock: bool) {
+        self.enabled = self.envelope.dac_enabled();
+        self.length.trigger(extra_length_clock);
+        self.envelope.trigger();
+        self.timer = self.period();
+        let frequency = self.frequency;
+        if let Some(sweep) = self.sweep.as_mut() {
+            sweep.shadow = frequency;
+            sweep.timer = if sweep.period == 0 { 8 } else { sweep.period };
+            sweep.enabled = sweep.period != 0 || sweep.shift != 0;
+            sweep.negate_used = false;
+            if sweep.shift != 0 {
+                self.calculate_sweep();
+            }
+        }
+    }
+
+    /// Computes the next sweep frequency, disabling the channel when it
+    /// overflows 11 bits.
+    fn calculate_sweep(&mut self) -> u16 {
+        let Some(sweep) = self.sweep.as_mut() else {
+            return self.frequency;
+        };
+        let delta = sweep.shadow >> sweep.shift;
+        let frequency = if sweep.negate {
+            sweep.negate_used = true;
+            sweep.shadow - delta
+        } else {
+            sweep.shadow + delta
+        };
+        if frequency > 2047 {
+            self.enabled = false;
+        }
+        return frequency;
+    }
+
+    pub(super) fn clock_sweep(&mut self) {
+        let Some(sweep) = self.sweep.as_mut() else {
+            return;
+        };
+        sweep.timer = sweep.timer.saturating_sub(1);
+        if sweep.timer > 0 {
+            return;
+        }
+        sweep.timer = if sweep.period == 0 { 8 } else { sweep.period };
+        if !sweep.enabled || sweep.period == 0 {
+            return;
+        }
+        let shift = sweep.shift;
+        let frequency = self.calculate_sweep();
+        if frequency <= 2047 && shift != 0 {
+            self.frequency = frequency;
+            if let Some(sweep) = self.sweep.as_mut() {
+                sweep.shadow = frequency;
+            }
+            self.calculate_sweep();
+        }
+    }
+
+    pub(super) fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    pub(super) fn tick(&mut self, cycles: u32) {
+        let mut cycles = cycles;
+        while cycles >= self.timer {
+            cycles -= self.timer;
+            self.timer = self.period();
+            self.duty_step = (self.duty_step + 1) % 8;
+        }
+        self.timer -= cycles;
+    }
+
+    pub(super) fn get_state(&self) -> ChannelState {
+        const DUTY_EIGHTHS: [u8; 4] = [1, 2, 4, 6];
+        return ChannelState {
+            enabled: self.enabled,
+            dac_enabled: self.dac_enabled(),
+            frequency: CLOCK_RATE as f64 / (self.period() * 8) as f64,
+            duty: Some(DUTY_EIGHTHS[self.duty as usize]),
+            volume: self.envelope.volume,
+            envelope: Some((self.envelope.increase, self.envelope.period)),
+            length: self.length.counter,
+            length_enabled: self.length.enabled,
+        };
+    }
+
+    pub(super) fn dac_enabled(&self) -> bool {
+        return self.envelope.dac_enabled();
+    }
+
+    /// Digital output of the channel, between 0 and 15.
+    pub(super) fn output(&self) -> u8 {
+        if !self.enabled {
+            return 0;
+        }
+        return DUTY_PATTERNS[self.duty as usize][self.duty_step as usize] * self.envelope.volume;
+    }
+}
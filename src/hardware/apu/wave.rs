@@ -0,0 +1,132 @@
+use super::{CLOCK_RATE, ChannelState, LengthCounter};
+use serde::{Deserialize, Serialize};
+
+pub const WAVE_RAM_SIZE: usize = 16;
+
+/// Dots between a trigger and the first sample being read.
+const TRIGGER_DELAY: u32 = 6;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(super) struct WaveChannel {
+    pub(super) enabled: bool,
+    dac_enabled: bool,
+    volume_code: u8,
+    frequency: u16,
+    timer: u32,
+    position: u8,
+    sample: u8,
+    pub(super) length: LengthCounter,
+    pub(super) wave_ram: [u8; WAVE_RAM_SIZE],
+}
+
+impl WaveChannel {
+    pub(super) fn new() -> Self {
+        Self {
+            enabled: false,
+            dac_enabled: false,
+            volume_code: 0,
+            frequency: 0,
+            timer: 0,
+            position: 0,
+            sample: 0,
+            length: LengthCounter::new(256),
+            wave_ram: [0; WAVE_RAM_SIZE],
+        }
+    }
+
+    fn period(&self) -> u32 {
+        return (2048 - self.frequency as u32) * 2;
+    }
+
+    pub(super) fn write_dac(&mut self, byte: u8) {
+        self.dac_enabled = byte & 0x80 != 0;
+        if !self.dac_enabled {
+            self.enabled = false;
+        }
+    }
+
+    pub(super) fn write_length(&mut self, byte: u8) {
+        self.length.load(byte as u16);
+    }
+
+    pub(super) fn write_volume(&mut self, byte: u8) {
+        self.volume_code = (byte >> 5) & 0b11;
+    }
+
+    pub(super) fn write_frequency_low(&mut self, byte: u8) {
+        self.frequency = (self.frequency & 0x700) | byte as u16;
+    }
+
+    pub(super) fn write_frequency_high(&mut self, byte: u8, extra_length_clock: bool) {
+        self.frequency = (self.frequency & 0xFF) | (((byte & 0b111) as u16) << 8);
+        let trigger = byte & 0x80 != 0;
+        if self
+            .length
+            .write_enable(byte & 0x40 != 0, extra_length_clock)
+            && !trigger
+        {
+            self.enabled = false;
+        }
+        if trigger {
+            self.enabled = self.dac_enabled;
+            self.length.trigger(extra_length_clock);
+            self.timer = self.period() + TRIGGER_DELAY;
+            // The sample buffer is not refilled, the last sample played is
+            // output until the first step.
+            self.position = 0;
+        }
+    }
+
+    pub(super) fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    pub(super) fn tick(&mut self, cycles: u32) {
+        let mut cycles = cycles;
+        while cycles >= self.timer {
+            cycles -= self.timer;
+            self.timer = self.period();
+            self.position = (self.position + 1) % 32;
+            let byte = self.wave_ram[self.position as usize / 2];
+            self.sample = if self.position.is_multiple_of(2) {
+                byte >> 4
+            } else {
+                byte & 0x0F
+            };
+        }
+        self.timer -= cycles;
+    }
+
+    pub(super) fn get_state(&self) -> ChannelState {
+        return ChannelState {
+            enabled: self.enabled,
+            dac_enabled: self.dac_enabled,
+            frequency: CLOCK_RATE as f64 / (self.period() * 32) as f64,
+            duty: None,
+            volume: match self.volume_code {
+                0 => 0,
+                code => 15 >> (code - 1),
+            },
+            envelope: None,
+            length: self.length.counter,
+            length_enabled: self.length.enabled,
+        };
+    }
+
+    pub(super) fn dac_enabled(&self) -> bool {
+        return self.dac_enabled;
+    }
+
+    /// Digital output of the channel, between 0 and 15.
+    pub(super) fn output(&self) -> u8 {
+        if !self.enabled {
+            return 0;
+        }
+        return match self.volume_code {
+            0 => 0,
+            code => self.sample >> (code - 1),
+        };
+    }
+}
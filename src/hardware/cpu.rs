@@ -2,6 +2,7 @@ use crate::utils::{
     get_bit_of_byte, get_word_left_byte, get_word_right_byte, set_bit_of_byte, set_word_left_byte,
     set_word_right_byte,
 };
+use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Copy)]
 pub enum Register {
@@ -59,7 +60,7 @@ impl Register {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CPU {
     af: u16,
     bc: u16,
@@ -95,6 +96,16 @@ impl CPU {
         self.ime_delay = None;
     }
 
+    pub fn get_ime(&self) -> bool {
+        return self.ime;
+    }
+
+    /// Sets IME right away, unlike EI.
+    pub fn set_ime(&mut self, ime: bool) {
+        self.ime = ime;
+        self.ime_delay = None;
+    }
+
     pub fn refresh_interupt_flag(&mut self) {
         match self.ime_delay {
             Some(0) => {
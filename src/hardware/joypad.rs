@@ -0,0 +1,107 @@
+use serde::{Deserialize, Serialize};
+
+pub const P1: usize = 0xFF00;
+
+pub const INTERRUPT_JOYPAD: u8 = 0b00010000;
+const SELECT_DIRECTIONS: u8 = 0b00010000;
+const SELECT_ACTIONS: u8 = 0b00100000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Button {
+    Right,
+    Left,
+    Up,
+    Down,
+    A,
+    B,
+    Select,
+    Start,
+}
+
+impl Button {
+    pub const ALL: [Button; 8] = [
+        Button::Right,
+        Button::Left,
+        Button::Up,
+        Button::Down,
+        Button::A,
+        Button::B,
+        Button::Select,
+        Button::Start,
+    ];
+
+    /// Bit of the button in `Joypad::pressed`, the directions in the lower
+    /// nibble and the actions in the upper one, in P1 order.
+    fn mask(&self) -> u8 {
+        use Button::*;
+        return match self {
+            Right => 0x01,
+            Left => 0x02,
+            Up => 0x04,
+            Down => 0x08,
+            A => 0x10,
+            B => 0x20,
+            Select => 0x40,
+            Start => 0x80,
+        };
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Joypad {
+    /// P1 bits 4 and 5, a cleared bit selecting its button group.
+    select: u8,
+    pressed: u8,
+}
+
+impl Joypad {
+    pub fn new() -> Self {
+        Self {
+            select: SELECT_DIRECTIONS | SELECT_ACTIONS,
+            pressed: 0,
+        }
+    }
+
+    /// P1 as read by the CPU, a pressed button of a selected group reading
+    /// as a cleared bit.
+    pub fn read(&self) -> u8 {
+        let mut lines = 0;
+        if self.select & SELECT_DIRECTIONS == 0 {
+            lines |= self.pressed & 0x0F;
+        }
+        if self.select & SELECT_ACTIONS == 0 {
+            lines |= self.pressed >> 4;
+        }
+        return 0xC0 | self.select | (!lines & 0x0F);
+    }
+
+    pub fn write(&mut self, byte: u8) {
+        self.select = byte & (SELECT_DIRECTIONS | SELECT_ACTIONS);
+    }
+
+    pub fn is_pressed(&self, button: Button) -> bool {
+        return self.pressed & button.mask() != 0;
+    }
+
+    /// Releases every button, without requesting any interrupt.
+    pub fn release_all(&mut self) {
+        self.pressed = 0;
+    }
+
+    /// Holds the same buttons as `other`.
+    pub fn keep_pressed(&mut self, other: &Joypad) {
+        self.pressed = other.pressed;
+    }
+
+    /// Returns whether one of the P1 input lines went low, which requests
+    /// the joypad interrupt.
+    pub fn set_button(&mut self, button: Button, pressed: bool) -> bool {
+        let previous = self.read();
+        if pressed {
+            self.pressed |= button.mask();
+        } else {
+            self.pressed &= !button.mask();
+        }
+        return previous & !self.read() & 0x0F != 0;
+    }
+}
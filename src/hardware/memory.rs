@@ -1,17 +1,151 @@
-use std::usize;
-
+use crate::hardware::apu::{self, APU, APU_END, APU_START};
+use crate::hardware::joypad::{self, Button, Joypad, P1};
+use crate::hardware::ppu::IF;
 use crate::interpreter::ExecutionError;
 use crate::interpreter::ExecutionError::MemoryOutOfBoundsError;
 use crate::utils::{bytes_to_word_little_endian, word_to_bytes_little_endian};
+use serde::{Deserialize, Serialize};
+
+pub const DIV: usize = 0xFF04;
+pub const KEY1: usize = 0xFF4D;
+pub const VBK: usize = 0xFF4F;
+pub const DMA: usize = 0xFF46;
+pub const HDMA1: usize = 0xFF51;
+pub const HDMA2: usize = 0xFF52;
+pub const HDMA3: usize = 0xFF53;
+pub const HDMA4: usize = 0xFF54;
+pub const HDMA5: usize = 0xFF55;
+pub const BCPS: usize = 0xFF68;
+pub const BCPD: usize = 0xFF69;
+pub const OCPS: usize = 0xFF6A;
+pub const OCPD: usize = 0xFF6B;
+pub const SVBK: usize = 0xFF70;
+/// Unmaps the boot ROM once written to.
+pub const BOOT: usize = 0xFF50;
+pub const DMG_BOOT_ROM_SIZE: usize = 0x100;
+pub const CGB_BOOT_ROM_SIZE: usize = 0x900;
+
+/// End of the cartridge ROM, where VRAM starts.
+pub const ROM_END: usize = 0x8000;
+const VRAM_START: usize = 0x8000;
+const VRAM_BANK_SIZE: usize = 0x2000;
+const WRAM_START: usize = 0xC000;
+const WRAM_BANK_SIZE: usize = 0x1000;
+const ECHO_START: usize = 0xE000;
+const OAM_START: usize = 0xFE00;
+const OAM_SIZE: usize = 0xA0;
+const IO_START: usize = 0xFF00;
+const PALETTE_RAM_SIZE: usize = 64;
+
+/// M-cycles the CPU is stalled for each 16 bytes block copied by HDMA, in
+/// normal speed.
+const HDMA_BLOCK_CYCLES: u32 = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Model {
+    DMG,
+    CGB,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Speed {
+    Normal,
+    Double,
+}
+
+impl Speed {
+    /// PPU dots elapsed during one CPU M-cycle.
+    pub fn dots_per_cycle(&self) -> u32 {
+        return match self {
+            Speed::Normal => 4,
+            Speed::Double => 2,
+        };
+    }
+}
 
+/// Substitutes a byte read by the CPU from the cartridge ROM, the way a
+/// Game Genie does.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RomPatch {
+    pub address: u16,
+    pub value: u8,
+    /// Byte the ROM must hold for the substitution to happen, which tells
+    /// the right bank apart when several are mapped there in turn.
+    pub compare: Option<u8>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct OamDma {
+    source: usize,
+    index: usize,
+    delay: u8,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct Hdma {
+    source: usize,
+    destination: usize,
+    blocks: usize,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct MemoryMap {
+    model: Model,
     data: Vec<u8>,
+    vram: Vec<u8>,
+    wram: Vec<u8>,
+    vram_bank: usize,
+    wram_bank: usize,
+    speed: Speed,
+    speed_switch_armed: bool,
+    dma: Option<OamDma>,
+    dma_starting: Option<OamDma>,
+    hdma: Option<Hdma>,
+    hdma5: u8,
+    stall_cycles: u32,
+    dmg_compatibility: bool,
+    bg_palettes: Vec<u8>,
+    obj_palettes: Vec<u8>,
+    bcps: u8,
+    ocps: u8,
+    /// Internal 16 bits counter whose upper byte is DIV.
+    div_counter: u16,
+    apu: APU,
+    joypad: Joypad,
+    /// Mapped over the cartridge until BOOT is written, except for the
+    /// cartridge header at 0x100-0x1FF.
+    boot_rom: Option<Vec<u8>>,
+    /// Cheats of the host rather than part of the machine.
+    #[serde(skip)]
+    rom_patches: Vec<RomPatch>,
 }
 
 impl MemoryMap {
-    pub fn new() -> Self {
+    pub fn new(model: Model) -> Self {
         Self {
+            model,
             data: vec![0; 65536],
+            vram: vec![0; VRAM_BANK_SIZE * 2],
+            wram: vec![0; WRAM_BANK_SIZE * 8],
+            vram_bank: 0,
+            wram_bank: 1,
+            speed: Speed::Normal,
+            speed_switch_armed: false,
+            dma: None,
+            dma_starting: None,
+            hdma: None,
+            hdma5: 0xFF,
+            stall_cycles: 0,
+            dmg_compatibility: false,
+            bg_palettes: vec![0xFF; PALETTE_RAM_SIZE],
+            obj_palettes: vec![0xFF; PALETTE_RAM_SIZE],
+            bcps: 0,
+            ocps: 0,
+            div_counter: 0,
+            apu: APU::new(model, apu::DEFAULT_SAMPLE_RATE),
+            joypad: Joypad::new(),
+            boot_rom: None,
+            rom_patches: vec![],
         }
     }
 
@@ -19,11 +153,260 @@ impl MemoryMap {
         return self.data.len();
     }
 
+    pub fn get_model(&self) -> Model {
+        return self.model;
+    }
+
+    /// Whether CGB only features are in use, which is not the case when a
+    /// DMG cartridge runs on a CGB.
+    pub fn is_cgb_mode(&self) -> bool {
+        return self.model == Model::CGB && !self.dmg_compatibility;
+    }
+
+    pub fn set_dmg_compatibility(&mut self, dmg_compatibility: bool) {
+        self.dmg_compatibility = dmg_compatibility;
+    }
+
+    /// Reads an RGB555 color from the CGB background or object palette RAM.
+    pub fn get_palette_color(&self, obj: bool, palette: u8, index: u8) -> u16 {
+        let ram = if obj {
+            &self.obj_palettes
+        } else {
+            &self.bg_palettes
+        };
+        let offset = palette as usize * 8 + index as usize * 2;
+        return bytes_to_word_little_endian(ram[offset], ram[offset + 1]);
+    }
+
+    pub fn set_palette_color(&mut self, obj: bool, palette: u8, index: u8, color: u16) {
+        let ram = if obj {
+            &mut self.obj_palettes
+        } else {
+            &mut self.bg_palettes
+        };
+        let offset = palette as usize * 8 + index as usize * 2;
+        (ram[offset], ram[offset + 1]) = word_to_bytes_little_endian(color);
+    }
+
+    pub fn get_speed(&self) -> Speed {
+        return self.speed;
+    }
+
+    pub fn set_speed(&mut self, speed: Speed) {
+        self.speed = speed;
+    }
+
+    /// Sets the visible part of DIV, clearing the hidden lower bits.
+    pub fn set_div(&mut self, div: u8) {
+        self.div_counter = (div as u16) << 8;
+    }
+
+    /// All eight WRAM banks, whatever bank is selected by SVBK.
+    pub fn get_wram(&self) -> &[u8] {
+        return &self.wram;
+    }
+
+    pub fn get_wram_mut(&mut self) -> &mut [u8] {
+        return &mut self.wram;
+    }
+
+    /// Both VRAM banks, whatever bank is selected by VBK.
+    pub fn get_vram(&self) -> &[u8] {
+        return &self.vram;
+    }
+
+    pub fn get_vram_mut(&mut self) -> &mut [u8] {
+        return &mut self.vram;
+    }
+
+    /// The CGB background or object palette RAM.
+    pub fn get_palette_ram(&self, obj: bool) -> &[u8] {
+        return if obj {
+            &self.obj_palettes
+        } else {
+            &self.bg_palettes
+        };
+    }
+
+    pub fn get_palette_ram_mut(&mut self, obj: bool) -> &mut [u8] {
+        return if obj {
+            &mut self.obj_palettes
+        } else {
+            &mut self.bg_palettes
+        };
+    }
+
+    /// Called by STOP, switches the CPU speed if it was requested through
+    /// KEY1 beforehand. Returns whether the speed changed.
+    pub fn switch_speed(&mut self) -> bool {
+        if self.model != Model::CGB || !self.speed_switch_armed {
+            return false;
+        }
+        self.speed_switch_armed = false;
+        self.speed = match self.speed {
+            Speed::Normal => Speed::Double,
+            Speed::Double => Speed::Normal,
+        };
+        return true;
+    }
+
+    pub fn get_apu(&self) -> &APU {
+        return &self.apu;
+    }
+
+    pub fn get_apu_mut(&mut self) -> &mut APU {
+        return &mut self.apu;
+    }
+
+    /// Takes over what comes from the host rather than from the machine, the
+    /// audio output, the buttons held and the ROM patches, from the memory
+    /// map this one replaces when restoring a state.
+    pub fn keep_host_state(&mut self, previous: &mut MemoryMap) {
+        self.apu.keep_output(&mut previous.apu);
+        self.joypad.keep_pressed(&previous.joypad);
+        self.rom_patches = std::mem::take(&mut previous.rom_patches);
+    }
+
+    pub fn set_rom_patches(&mut self, rom_patches: Vec<RomPatch>) {
+        self.rom_patches = rom_patches;
+    }
+
+    /// Reads a byte of WRAM bank `bank` at `address`, see `poke_wram`.
+    pub fn peek_wram(&self, bank: usize, address: usize) -> u8 {
+        return self.wram[self.wram_index(bank, address)];
+    }
+
+    /// Writes a byte to WRAM bank `bank` at `address`, in 0xD000-0xDFFF,
+    /// whatever bank is selected by SVBK. A DMG only has bank 1 there.
+    pub fn poke_wram(&mut self, bank: usize, address: usize, byte: u8) {
+        let index = self.wram_index(bank, address);
+        self.wram[index] = byte;
+    }
+
+    fn wram_index(&self, bank: usize, address: usize) -> usize {
+        let bank = match self.model {
+            Model::CGB => bank.clamp(1, 7),
+            Model::DMG => 1,
+        };
+        return bank * WRAM_BANK_SIZE + address - 0xD000;
+    }
+
+    pub fn set_boot_rom(&mut self, boot_rom: Vec<u8>) {
+        self.boot_rom = Some(boot_rom);
+    }
+
+    pub fn get_joypad(&self) -> &Joypad {
+        return &self.joypad;
+    }
+
+    /// Presses or releases a button, requesting the joypad interrupt when
+    /// the game is polling its group.
+    pub fn set_button(&mut self, button: Button, pressed: bool) {
+        if self.joypad.set_button(button, pressed) {
+            self.data[IF] |= joypad::INTERRUPT_JOYPAD;
+        }
+    }
+
+    /// Holds the same buttons as `joypad`, without requesting any interrupt.
+    pub fn keep_pressed(&mut self, joypad: &Joypad) {
+        self.joypad.keep_pressed(joypad);
+    }
+
+    /// Releases every button, as if none had been held.
+    pub fn release_buttons(&mut self) {
+        self.joypad.release_all();
+    }
+
+    /// Returns the M-cycles the CPU has to wait for since the last call,
+    /// because of HDMA transfers.
+    pub fn take_stall_cycles(&mut self) -> u32 {
+        let cycles = self.stall_cycles;
+        self.stall_cycles = 0;
+        return cycles;
+    }
+
+    /// Advances the memory side hardware (DIV, OAM DMA and the APU) by
+    /// `cycles` M-cycles.
+    pub fn tick(&mut self, cycles: u32) -> Result<(), ExecutionError> {
+        for _ in 0..cycles {
+            let previous = self.div_counter;
+            self.div_counter = self.div_counter.wrapping_add(4);
+            self.check_frame_sequencer(previous);
+            if let Some(mut dma) = self.dma_starting.take() {
+                if dma.delay == 0 {
+                    // A restarted transfer replaces the running one only
+                    // once its own startup cycle is over.
+                    self.dma = Some(dma);
+                } else {
+                    dma.delay -= 1;
+                    self.dma_starting = Some(dma);
+                }
+            }
+            if let Some(mut dma) = self.dma.take() {
+                let byte = self.peek_byte(dma.source + dma.index)?;
+                self.poke_byte(OAM_START + dma.index, byte)?;
+                dma.index += 1;
+                if dma.index < OAM_SIZE {
+                    self.dma = Some(dma);
+                }
+            }
+        }
+        self.apu.tick(cycles * self.speed.dots_per_cycle());
+        return Ok(());
+    }
+
+    /// Called by the PPU when entering HBlank, copies the next block of a
+    /// running HBlank DMA.
+    pub fn hblank(&mut self) -> Result<(), ExecutionError> {
+        let Some(mut hdma) = self.hdma.take() else {
+            return Ok(());
+        };
+        self.copy_hdma_block(&mut hdma)?;
+        if hdma.blocks == 0 {
+            self.hdma5 = 0xFF;
+        } else {
+            self.hdma5 = (hdma.blocks - 1) as u8;
+            self.hdma = Some(hdma);
+        }
+        return Ok(());
+    }
+
+    pub fn is_dma_active(&self) -> bool {
+        return self.dma.is_some();
+    }
+
+    /// Reads a byte as seen by the CPU. While an OAM DMA is running only
+    /// HRAM and the IO registers can be accessed, the bus used by the
+    /// transfer returns the byte being copied and everything else 0xFF.
     pub fn read_byte(&self, address: usize) -> Result<u8, ExecutionError> {
         if !self.is_inbound_byte(address) {
             return Err(MemoryOutOfBoundsError(address));
         }
-        return Ok(self.data[address]);
+        if let Some(dma) = self.dma
+            && address < IO_START
+        {
+            if is_vram_bus(address) == is_vram_bus(dma.source) && !is_oam(address) {
+                return Ok(*self.cell(dma.source + dma.index));
+            }
+            return Ok(0xFF);
+        }
+        let byte = self.read_register(address);
+        if address < ROM_END && self.boot_rom_byte(address).is_none() {
+            return Ok(self.patch_rom_byte(address, byte));
+        }
+        return Ok(byte);
+    }
+
+    /// Applies the first ROM patch for `address` expecting `byte`.
+    fn patch_rom_byte(&self, address: usize, byte: u8) -> u8 {
+        return self
+            .rom_patches
+            .iter()
+            .find(|patch| {
+                patch.address as usize == address
+                    && patch.compare.is_none_or(|compare| compare == byte)
+            })
+            .map_or(byte, |patch| patch.value);
     }
 
     pub fn read_bytes(&self, address: usize, n: usize) -> Result<Vec<u8>, ExecutionError> {
@@ -37,16 +420,43 @@ impl MemoryMap {
             return Err(MemoryOutOfBoundsError(address));
         }
         return Ok(bytes_to_word_little_endian(
-            self.data[address],
-            self.data[address + 1],
+            self.read_byte(address)?,
+            self.read_byte(address + 1)?,
         ));
     }
 
+    /// Writes a byte as the CPU would, see `read_byte` for the accesses
+    /// blocked during an OAM DMA.
     pub fn write_byte(&mut self, address: usize, byte: u8) -> Result<(), ExecutionError> {
         if !self.is_inbound_byte(address) {
             return Err(MemoryOutOfBoundsError(address));
         }
-        self.data[address] = byte;
+        if self.dma.is_some() && address < IO_START {
+            return Ok(());
+        }
+        match address {
+            P1 => {
+                self.joypad.write(byte);
+                return Ok(());
+            }
+            DIV => {
+                let previous = self.div_counter;
+                self.div_counter = 0;
+                self.check_frame_sequencer(previous);
+                return Ok(());
+            }
+            APU_START..=APU_END => {
+                self.apu.write_register(address, byte);
+                return Ok(());
+            }
+            DMA => self.start_dma(byte),
+            BOOT if byte != 0 => self.boot_rom = None,
+            KEY1 | VBK | SVBK | HDMA5 | BCPS..=OCPD if self.model == Model::CGB => {
+                return self.write_cgb_register(address, byte);
+            }
+            _ => (),
+        }
+        *self.cell_mut(address) = byte;
         return Ok(());
     }
 
@@ -55,8 +465,8 @@ impl MemoryMap {
             return Err(MemoryOutOfBoundsError(address));
         }
         let (fst, snd) = word_to_bytes_little_endian(word);
-        self.data[address] = fst;
-        self.data[address + 1] = snd;
+        self.write_byte(address, fst)?;
+        self.write_byte(address + 1, snd)?;
         return Ok(());
     }
 
@@ -67,6 +477,30 @@ impl MemoryMap {
         return Ok(());
     }
 
+    /// Reads a byte without any of the CPU access restrictions, for use by
+    /// the other hardware components and the debugger.
+    pub fn peek_byte(&self, address: usize) -> Result<u8, ExecutionError> {
+        if !self.is_inbound_byte(address) {
+            return Err(MemoryOutOfBoundsError(address));
+        }
+        return Ok(self.read_register(address));
+    }
+
+    /// Writes a byte without any of the CPU access restrictions or side
+    /// effects, for use by the other hardware components.
+    pub fn poke_byte(&mut self, address: usize, byte: u8) -> Result<(), ExecutionError> {
+        if !self.is_inbound_byte(address) {
+            return Err(MemoryOutOfBoundsError(address));
+        }
+        *self.cell_mut(address) = byte;
+        return Ok(());
+    }
+
+    /// Reads VRAM from the given bank, whatever bank is selected by VBK.
+    pub fn peek_vram(&self, bank: usize, address: usize) -> u8 {
+        return self.vram[bank * VRAM_BANK_SIZE + address - VRAM_START];
+    }
+
     pub fn add_byte(&mut self, address: usize, n: u8) -> Result<(), ExecutionError> {
         self.write_byte(address, self.read_byte(address)?.wrapping_add(n))?;
         return Ok(());
@@ -77,6 +511,156 @@ impl MemoryMap {
         return Ok(());
     }
 
+    /// Byte of the boot ROM mapped at `address`, if any.
+    fn boot_rom_byte(&self, address: usize) -> Option<u8> {
+        let boot_rom = self.boot_rom.as_ref()?;
+        if address < DMG_BOOT_ROM_SIZE || (0x200..boot_rom.len()).contains(&address) {
+            return Some(boot_rom[address]);
+        }
+        return None;
+    }
+
+    fn read_register(&self, address: usize) -> u8 {
+        if let Some(byte) = self.boot_rom_byte(address) {
+            return byte;
+        }
+        match address {
+            P1 => return self.joypad.read(),
+            DIV => return (self.div_counter >> 8) as u8,
+            APU_START..=APU_END => return self.apu.read_register(address),
+            _ => (),
+        }
+        if self.model != Model::CGB {
+            return *self.cell(address);
+        }
+        return match address {
+            KEY1 => {
+                let current = if self.speed == Speed::Double { 0x80 } else { 0 };
+                current | 0x7E | self.speed_switch_armed as u8
+            }
+            VBK => 0xFE | self.vram_bank as u8,
+            SVBK => 0xF8 | self.wram_bank as u8,
+            HDMA1..=HDMA4 => 0xFF,
+            HDMA5 => self.hdma5,
+            BCPS => 0x40 | self.bcps,
+            BCPD => self.bg_palettes[(self.bcps & 0x3F) as usize],
+            OCPS => 0x40 | self.ocps,
+            OCPD => self.obj_palettes[(self.ocps & 0x3F) as usize],
+            _ => *self.cell(address),
+        };
+    }
+
+    fn write_cgb_register(&mut self, address: usize, byte: u8) -> Result<(), ExecutionError> {
+        match address {
+            KEY1 => self.speed_switch_armed = byte & 1 != 0,
+            VBK => self.vram_bank = (byte & 1) as usize,
+            // Selecting bank 0 selects bank 1 instead.
+            SVBK => self.wram_bank = ((byte & 0b111) as usize).max(1),
+            HDMA5 => self.start_hdma(byte)?,
+            BCPS => self.bcps = byte & 0xBF,
+            BCPD => write_palette_data(&mut self.bg_palettes, &mut self.bcps, byte),
+            OCPS => self.ocps = byte & 0xBF,
+            OCPD => write_palette_data(&mut self.obj_palettes, &mut self.ocps, byte),
+            _ => (),
+        }
+        return Ok(());
+    }
+
+    /// Clocks the APU frame sequencer on the falling edge of DIV bit 4, or
+    /// bit 5 in double speed so that it keeps running at 512 Hz.
+    fn check_frame_sequencer(&mut self, previous: u16) {
+        let bit = match self.speed {
+            Speed::Normal => 1 << 12,
+            Speed::Double => 1 << 13,
+        };
+        if previous & bit != 0 && self.div_counter & bit == 0 {
+            self.apu.clock_frame_sequencer();
+        }
+    }
+
+    fn start_hdma(&mut self, byte: u8) -> Result<(), ExecutionError> {
+        if byte & 0x80 == 0
+            && let Some(hdma) = self.hdma.take()
+        {
+            // Writing with bit 7 cleared during an HBlank DMA cancels it.
+            self.hdma5 = 0x80 | (hdma.blocks - 1) as u8;
+            return Ok(());
+        }
+        let source = ((self.data[HDMA1] as usize) << 8 | self.data[HDMA2] as usize) & 0xFFF0;
+        let destination =
+            VRAM_START + (((self.data[HDMA3] as usize) << 8 | self.data[HDMA4] as usize) & 0x1FF0);
+        let mut hdma = Hdma {
+            source,
+            destination,
+            blocks: (byte & 0x7F) as usize + 1,
+        };
+        if byte & 0x80 != 0 {
+            self.hdma5 = byte & 0x7F;
+            self.hdma = Some(hdma);
+            return Ok(());
+        }
+        // General purpose DMA copies everything at once.
+        while hdma.blocks > 0 {
+            self.copy_hdma_block(&mut hdma)?;
+        }
+        self.hdma5 = 0xFF;
+        return Ok(());
+    }
+
+    fn copy_hdma_block(&mut self, hdma: &mut Hdma) -> Result<(), ExecutionError> {
+        for _ in 0..16 {
+            let byte = self.peek_byte(hdma.source)?;
+            self.poke_byte(hdma.destination, byte)?;
+            hdma.source += 1;
+            hdma.destination = VRAM_START + (hdma.destination + 1 - VRAM_START) % VRAM_BANK_SIZE;
+        }
+        hdma.blocks -= 1;
+        // The copy takes the same time in dots at both speeds, so twice as
+        // many CPU cycles in double speed.
+        self.stall_cycles += HDMA_BLOCK_CYCLES * (4 / self.speed.dots_per_cycle());
+        return Ok(());
+    }
+
+    fn start_dma(&mut self, page: u8) {
+        let mut source = (page as usize) << 8;
+        // Sources above 0xDFFF read from WRAM through its echo, including
+        // the 0xFE00 and 0xFF00 pages.
+        if source >= ECHO_START {
+            source -= 0x2000;
+        }
+        self.dma_starting = Some(OamDma {
+            source,
+            index: 0,
+            delay: 1,
+        });
+    }
+
+    fn cell(&self, address: usize) -> &u8 {
+        return match address {
+            VRAM_START..0xA000 => {
+                &self.vram[self.vram_bank * VRAM_BANK_SIZE + address - VRAM_START]
+            }
+            WRAM_START..0xD000 => &self.wram[address - WRAM_START],
+            0xD000..ECHO_START => &self.wram[self.wram_bank * WRAM_BANK_SIZE + address - 0xD000],
+            ECHO_START..OAM_START => self.cell(address - 0x2000),
+            _ => &self.data[address],
+        };
+    }
+
+    fn cell_mut(&mut self, address: usize) -> &mut u8 {
+        return match address {
+            VRAM_START..0xA000 => {
+                &mut self.vram[self.vram_bank * VRAM_BANK_SIZE + address - VRAM_START]
+            }
+            WRAM_START..0xD000 => &mut self.wram[address - WRAM_START],
+            0xD000..ECHO_START => {
+                &mut self.wram[self.wram_bank * WRAM_BANK_SIZE + address - 0xD000]
+            }
+            ECHO_START..OAM_START => self.cell_mut(address - 0x2000),
+            _ => &mut self.data[address],
+        };
+    }
+
     fn is_inbound_byte(&self, address: usize) -> bool {
         return self.size() > address;
     }
@@ -85,3 +669,184 @@ impl MemoryMap {
         return self.size() > (address + 1);
     }
 }
+
+/// Writes through a BCPD/OCPD register, `specification` being the matching
+/// BCPS/OCPS value whose index auto-increments when its bit 7 is set.
+fn write_palette_data(ram: &mut [u8], specification: &mut u8, byte: u8) {
+    let index = *specification & 0x3F;
+    ram[index as usize] = byte;
+    if *specification & 0x80 != 0 {
+        *specification = 0x80 | ((index + 1) & 0x3F);
+    }
+}
+
+fn is_vram_bus(address: usize) -> bool {
+    return (VRAM_START..0xA000).contains(&address);
+}
+
+fn is_oam(address: usize) -> bool {
+    return (OAM_START..IO_START).contains(&address);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Fills the page at `page` with bytes counting from `first`.
+    fn fill_page(mem_map: &mut MemoryMap, page: usize, first: u8) {
+        for offset in 0..0x100 {
+            mem_map
+                .poke_byte(page + offset, first.wrapping_add(offset as u8))
+                .unwrap();
+        }
+    }
+
+    fn oam(mem_map: &MemoryMap) -> Vec<u8> {
+        return (OAM_START..OAM_START + OAM_SIZE)
+            .map(|address| mem_map.peek_byte(address).unwrap())
+            .collect();
+    }
+
+    fn page(first: u8) -> Vec<u8> {
+        return (0..OAM_SIZE)
+            .map(|offset| first.wrapping_add(offset as u8))
+            .collect();
+    }
+
+    #[test]
+    fn copies_oam_in_160_cycles_after_a_startup_cycle() {
+        let mut mem_map = MemoryMap::new(Model::DMG);
+        fill_page(&mut mem_map, 0xC000, 0x10);
+        mem_map.write_byte(DMA, 0xC0).unwrap();
+        mem_map.tick(1).unwrap();
+        assert!(!mem_map.is_dma_active());
+        mem_map.tick(OAM_SIZE as u32 - 1).unwrap();
+        assert!(mem_map.is_dma_active());
+        assert_eq!(mem_map.peek_byte(OAM_START + OAM_SIZE - 1).unwrap(), 0);
+        mem_map.tick(1).unwrap();
+        assert!(!mem_map.is_dma_active());
+        assert_eq!(oam(&mem_map), page(0x10));
+    }
+
+    #[test]
+    fn restarts_after_the_startup_cycle_of_the_new_transfer() {
+        let mut mem_map = MemoryMap::new(Model::DMG);
+        fill_page(&mut mem_map, 0xC000, 0x10);
+        fill_page(&mut mem_map, 0xC100, 0x80);
+        mem_map.write_byte(DMA, 0xC0).unwrap();
+        mem_map.tick(11).unwrap();
+        mem_map.write_byte(DMA, 0xC1).unwrap();
+        // The running transfer goes on during the startup cycle.
+        mem_map.tick(1).unwrap();
+        assert_eq!(mem_map.peek_byte(OAM_START + 10).unwrap(), 0x10 + 10);
+        mem_map.tick(1).unwrap();
+        assert_eq!(mem_map.peek_byte(OAM_START).unwrap(), 0x80);
+        assert_eq!(mem_map.peek_byte(OAM_START + 11).unwrap(), 0);
+        mem_map.tick(OAM_SIZE as u32 - 1).unwrap();
+        assert!(!mem_map.is_dma_active());
+        assert_eq!(oam(&mem_map), page(0x80));
+    }
+
+    #[test]
+    fn reads_sources_above_0xdfff_from_wram() {
+        let mut mem_map = MemoryMap::new(Model::DMG);
+        fill_page(&mut mem_map, 0xC000, 0x10);
+        mem_map.write_byte(DMA, 0xE0).unwrap();
+        mem_map.tick(OAM_SIZE as u32 + 1).unwrap();
+        assert_eq!(oam(&mem_map), page(0x10));
+
+        fill_page(&mut mem_map, 0xDE00, 0x40);
+        mem_map.write_byte(DMA, 0xFE).unwrap();
+        mem_map.tick(OAM_SIZE as u32 + 1).unwrap();
+        assert_eq!(oam(&mem_map), page(0x40));
+    }
+
+    #[test]
+    fn limits_the_cpu_to_hram_during_a_transfer() {
+        let mut mem_map = MemoryMap::new(Model::DMG);
+        fill_page(&mut mem_map, 0xC000, 0x10);
+        mem_map.poke_byte(0x8000, 0x55).unwrap();
+        mem_map.poke_byte(0xFF80, 0x66).unwrap();
+        mem_map.write_byte(DMA, 0xC0).unwrap();
+        mem_map.tick(3).unwrap();
+        assert_eq!(mem_map.read_byte(0x8000).unwrap(), 0xFF);
+        assert_eq!(mem_map.read_byte(OAM_START).unwrap(), 0xFF);
+        assert_eq!(mem_map.read_byte(0xFF80).unwrap(), 0x66);
+        // The bus used by the transfer returns the byte being copied.
+        assert_eq!(mem_map.read_byte(0x0150).unwrap(), 0x12);
+        mem_map.write_byte(0xC000, 0).unwrap();
+        mem_map.write_byte(0xFF80, 0x77).unwrap();
+        assert_eq!(mem_map.peek_byte(0xC000).unwrap(), 0x10);
+        assert_eq!(mem_map.peek_byte(0xFF80).unwrap(), 0x77);
+    }
+
+    /// Sets the HDMA source and VRAM destination registers.
+    fn set_hdma(mem_map: &mut MemoryMap, source: u16, destination: u16) {
+        mem_map.write_byte(HDMA1, (source >> 8) as u8).unwrap();
+        mem_map.write_byte(HDMA2, source as u8).unwrap();
+        mem_map.write_byte(HDMA3, (destination >> 8) as u8).unwrap();
+        mem_map.write_byte(HDMA4, destination as u8).unwrap();
+    }
+
+    #[test]
+    fn copies_general_purpose_hdma_at_once() {
+        let mut mem_map = MemoryMap::new(Model::CGB);
+        fill_page(&mut mem_map, 0xC000, 0x10);
+        set_hdma(&mut mem_map, 0xC000, 0x0100);
+        mem_map.write_byte(HDMA5, 0x01).unwrap();
+        assert_eq!(mem_map.peek_vram(0, 0x8100), 0x10);
+        assert_eq!(mem_map.peek_vram(0, 0x811F), 0x2F);
+        assert_eq!(mem_map.peek_vram(0, 0x8120), 0);
+        assert_eq!(mem_map.read_byte(HDMA5).unwrap(), 0xFF);
+        assert_eq!(mem_map.take_stall_cycles(), 2 * HDMA_BLOCK_CYCLES);
+    }
+
+    #[test]
+    fn copies_hblank_hdma_a_block_per_hblank_until_cancelled() {
+        let mut mem_map = MemoryMap::new(Model::CGB);
+        fill_page(&mut mem_map, 0xC000, 0x10);
+        set_hdma(&mut mem_map, 0xC000, 0x0000);
+        mem_map.write_byte(HDMA5, 0x82).unwrap();
+        assert_eq!(mem_map.read_byte(HDMA5).unwrap(), 0x02);
+        assert_eq!(mem_map.peek_vram(0, 0x8000), 0);
+        mem_map.hblank().unwrap();
+        assert_eq!(mem_map.peek_vram(0, 0x800F), 0x1F);
+        assert_eq!(mem_map.peek_vram(0, 0x8010), 0);
+        assert_eq!(mem_map.read_byte(HDMA5).unwrap(), 0x01);
+        // Writing with bit 7 cleared stops it, with the blocks left.
+        mem_map.write_byte(HDMA5, 0x00).unwrap();
+        assert_eq!(mem_map.read_byte(HDMA5).unwrap(), 0x81);
+        mem_map.hblank().unwrap();
+        assert_eq!(mem_map.peek_vram(0, 0x8010), 0);
+    }
+
+    #[test]
+    fn maps_wram_bank_1_when_selecting_bank_0() {
+        let mut mem_map = MemoryMap::new(Model::CGB);
+        mem_map.write_byte(SVBK, 2).unwrap();
+        mem_map.write_byte(0xD000, 0x22).unwrap();
+        mem_map.write_byte(SVBK, 0).unwrap();
+        assert_eq!(mem_map.read_byte(SVBK).unwrap(), 0xF9);
+        mem_map.write_byte(0xD000, 0x11).unwrap();
+        assert_eq!(mem_map.peek_wram(1, 0xD000), 0x11);
+        assert_eq!(mem_map.peek_wram(2, 0xD000), 0x22);
+    }
+
+    #[test]
+    fn switches_speed_on_stop_once_armed() {
+        let mut mem_map = MemoryMap::new(Model::CGB);
+        assert!(!mem_map.switch_speed());
+        mem_map.write_byte(KEY1, 1).unwrap();
+        assert_eq!(mem_map.read_byte(KEY1).unwrap(), 0x7F);
+        assert!(mem_map.switch_speed());
+        assert_eq!(mem_map.get_speed(), Speed::Double);
+        assert_eq!(mem_map.read_byte(KEY1).unwrap(), 0xFE);
+        mem_map.write_byte(KEY1, 1).unwrap();
+        assert!(mem_map.switch_speed());
+        assert_eq!(mem_map.get_speed(), Speed::Normal);
+
+        let mut mem_map = MemoryMap::new(Model::DMG);
+        mem_map.write_byte(KEY1, 1).unwrap();
+        assert!(!mem_map.switch_speed());
+    }
+}
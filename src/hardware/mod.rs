@@ -1,2 +1,5 @@
-pub mod memory;
+pub mod apu;
 pub mod cpu;
+pub mod joypad;
+pub mod memory;
+pub mod ppu;
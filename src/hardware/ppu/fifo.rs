@@ -0,0 +1,255 @@
+use std::collections::VecDeque;
+
+use super::{
+    BgPixel, LCDC, LCDC_BG_ENABLE, LCDC_BG_MAP, LCDC_OBJ_ENABLE, LCDC_WINDOW_ENABLE,
+    LCDC_WINDOW_MAP, ObjPixel, PPU, SCREEN_WIDTH, SCX, SCY, TRANSPARENT, WX, bg_pixel, bg_tile_row,
+    sprite_row, tile_map_entry, tile_row_pixel,
+};
+use crate::hardware::memory::MemoryMap;
+use crate::interpreter::ExecutionError;
+use serde::{Deserialize, Serialize};
+
+/// Each fetcher step but the push takes two dots.
+const FETCH_STEP_DOTS: u8 = 2;
+/// Dots spent fetching a sprite once the background fetcher is ready.
+const SPRITE_FETCH_DOTS: u8 = 6;
+/// Dots spent at the beginning of mode 3 before the fetcher starts.
+const STARTUP_DOTS: u8 = 6;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+enum FetcherStep {
+    Tile,
+    DataLow,
+    DataHigh,
+    Push,
+}
+
+#[derive(Serialize, Deserialize)]
+pub(super) struct PixelFifo {
+    bg: VecDeque<BgPixel>,
+    obj: VecDeque<ObjPixel>,
+    step: FetcherStep,
+    step_dots: u8,
+    fetcher_x: u8,
+    tile: u8,
+    attributes: u8,
+    tile_row: u8,
+    low: u8,
+    high: u8,
+    /// Next screen column to be pushed to the LCD.
+    x: u8,
+    /// Pixels left to drop at the start of the line for SCX fine scrolling.
+    discard: u8,
+    /// Dots left during which the pixel output is stalled.
+    stall: u8,
+    sprite_fetch: Option<usize>,
+    next_sprite: usize,
+    in_window: bool,
+    window_drawn: bool,
+}
+
+impl PixelFifo {
+    pub(super) fn new() -> Self {
+        Self {
+            bg: VecDeque::with_capacity(16),
+            obj: VecDeque::with_capacity(8),
+            step: FetcherStep::Tile,
+            step_dots: 0,
+            fetcher_x: 0,
+            tile: 0,
+            attributes: 0,
+            tile_row: 0,
+            low: 0,
+            high: 0,
+            x: 0,
+            discard: 0,
+            stall: 0,
+            sprite_fetch: None,
+            next_sprite: 0,
+            in_window: false,
+            window_drawn: false,
+        }
+    }
+}
+
+impl PPU {
+    pub(super) fn start_fifo_line(&mut self, mem_map: &MemoryMap) -> Result<(), ExecutionError> {
+        self.fifo = PixelFifo::new();
+        // The fine scroll is latched when mode 3 starts, the coarse scroll
+        // is read again by the fetcher for each tile.
+        self.fifo.discard = mem_map.peek_byte(SCX)? % 8;
+        self.fifo.stall = STARTUP_DOTS;
+        return Ok(());
+    }
+
+    /// Advances mode 3 by one dot, returns true once the line is complete.
+    pub(super) fn tick_fifo(&mut self, mem_map: &MemoryMap) -> Result<bool, ExecutionError> {
+        let lcdc = mem_map.peek_byte(LCDC)?;
+        let cgb_mode = mem_map.is_cgb_mode();
+        if self.fifo.stall > 0 {
+            self.fifo.stall -= 1;
+            if self.fifo.stall == 0
+                && let Some(index) = self.fifo.sprite_fetch.take()
+            {
+                self.merge_sprite(mem_map, lcdc, index);
+            }
+            return Ok(false);
+        }
+
+        if !self.fifo.in_window
+            && self.fifo.discard == 0
+            && lcdc & LCDC_WINDOW_ENABLE != 0
+            && (lcdc & LCDC_BG_ENABLE != 0 || cgb_mode)
+            && self.wy_triggered
+            && self.fifo.x as i16 + 7 >= mem_map.peek_byte(WX)? as i16
+        {
+            // The window restarts the fetcher from its first tile and throws
+            // away what was already fetched for the background.
+            self.fifo.bg.clear();
+            self.fifo.step = FetcherStep::Tile;
+            self.fifo.step_dots = 0;
+            self.fifo.fetcher_x = 0;
+            self.fifo.in_window = true;
+            self.fifo.window_drawn = true;
+        }
+
+        if lcdc & LCDC_OBJ_ENABLE != 0
+            && let Some(sprite) = self.line_sprites.get(self.fifo.next_sprite)
+            && sprite.x as i16 - 8 <= self.fifo.x as i16
+        {
+            // A sprite fetch has to wait for the background fetcher to have
+            // pixels ready, which makes the penalty depend on its progress.
+            if !self.fifo.bg.is_empty() {
+                self.fifo.sprite_fetch = Some(self.fifo.next_sprite);
+                self.fifo.next_sprite += 1;
+                self.fifo.stall = SPRITE_FETCH_DOTS;
+                return Ok(false);
+            }
+            self.tick_fetcher(mem_map, lcdc)?;
+            return Ok(false);
+        }
+
+        self.tick_fetcher(mem_map, lcdc)?;
+        let Some(mut bg) = self.fifo.bg.pop_front() else {
+            return Ok(false);
+        };
+        if self.fifo.discard > 0 {
+            self.fifo.discard -= 1;
+            return Ok(false);
+        }
+        if lcdc & LCDC_BG_ENABLE == 0 && !cgb_mode {
+            bg.index = 0;
+        }
+        let obj = self.fifo.obj.pop_front().unwrap_or(TRANSPARENT);
+
+        // Palettes are read when the pixel is output, so writes done in the
+        // middle of the line affect the following pixels only.
+        self.framebuffer[self.ly as usize * SCREEN_WIDTH + self.fifo.x as usize] =
+            self.mix_pixel(mem_map, lcdc, bg, obj)?;
+        self.fifo.x += 1;
+
+        if self.fifo.x as usize == SCREEN_WIDTH {
+            if self.fifo.window_drawn {
+                self.window_line += 1;
+            }
+            return Ok(true);
+        }
+        return Ok(false);
+    }
+
+    fn tick_fetcher(&mut self, mem_map: &MemoryMap, lcdc: u8) -> Result<(), ExecutionError> {
+        if self.fifo.step == FetcherStep::Push {
+            if self.fifo.bg.is_empty() {
+                for column in 0..8 {
+                    self.fifo.bg.push_back(bg_pixel(
+                        self.fifo.low,
+                        self.fifo.high,
+                        self.fifo.attributes,
+                        column,
+                    ));
+                }
+                self.fifo.fetcher_x = self.fifo.fetcher_x.wrapping_add(1);
+                self.fifo.step = FetcherStep::Tile;
+            }
+            return Ok(());
+        }
+
+        self.fifo.step_dots += 1;
+        if self.fifo.step_dots < FETCH_STEP_DOTS {
+            return Ok(());
+        }
+        self.fifo.step_dots = 0;
+        match self.fifo.step {
+            FetcherStep::Tile => {
+                let (map, x, y) = if self.fifo.in_window {
+                    let map = if lcdc & LCDC_WINDOW_MAP != 0 {
+                        0x9C00
+                    } else {
+                        0x9800
+                    };
+                    (map, self.fifo.fetcher_x, self.window_line)
+                } else {
+                    let map = if lcdc & LCDC_BG_MAP != 0 {
+                        0x9C00
+                    } else {
+                        0x9800
+                    };
+                    let x = (mem_map.peek_byte(SCX)? / 8).wrapping_add(self.fifo.fetcher_x);
+                    (map, x, self.ly.wrapping_add(mem_map.peek_byte(SCY)?))
+                };
+                let address = map + (y as usize / 8) * 32 + (x as usize % 32);
+                (self.fifo.tile, self.fifo.attributes) = tile_map_entry(mem_map, address);
+                self.fifo.tile_row = y % 8;
+                self.fifo.step = FetcherStep::DataLow;
+            }
+            FetcherStep::DataLow => {
+                let (low, _) = bg_tile_row(
+                    mem_map,
+                    lcdc,
+                    self.fifo.tile,
+                    self.fifo.attributes,
+                    self.fifo.tile_row,
+                );
+                self.fifo.low = low;
+                self.fifo.step = FetcherStep::DataHigh;
+            }
+            FetcherStep::DataHigh => {
+                let (_, high) = bg_tile_row(
+                    mem_map,
+                    lcdc,
+                    self.fifo.tile,
+                    self.fifo.attributes,
+                    self.fifo.tile_row,
+                );
+                self.fifo.high = high;
+                self.fifo.step = FetcherStep::Push;
+            }
+            FetcherStep::Push => (),
+        }
+        return Ok(());
+    }
+
+    /// Mixes a fetched sprite into the sprite FIFO. On DMG pixels already
+    /// holding an opaque sprite pixel are kept, which gives priority to the
+    /// sprites fetched first (lowest X, then lowest OAM index). On CGB the
+    /// lowest OAM index wins.
+    fn merge_sprite(&mut self, mem_map: &MemoryMap, lcdc: u8, index: usize) {
+        let sprite = self.line_sprites[index];
+        let cgb_mode = mem_map.is_cgb_mode();
+        let (low, high) = sprite_row(mem_map, &sprite, self.ly, Self::sprite_height(lcdc));
+        // Sprites partially off the left edge lose their first columns.
+        let skip = (self.fifo.x as i16 - (sprite.x as i16 - 8)).max(0) as u8;
+        while self.fifo.obj.len() < 8 {
+            self.fifo.obj.push_back(TRANSPARENT);
+        }
+        for column in skip..8 {
+            let pixel = ObjPixel::new(&sprite, tile_row_pixel(low, high, column), cgb_mode);
+            let slot = &mut self.fifo.obj[(column - skip) as usize];
+            let replace = slot.index == 0
+                || (cgb_mode && pixel.index != 0 && pixel.oam_index < slot.oam_index);
+            if replace {
+                *slot = pixel;
+            }
+        }
+    }
+}
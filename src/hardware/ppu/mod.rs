@@ -0,0 +1,466 @@
+mod fifo;
+pub mod palette;
+mod scanline;
+
+use crate::hardware::memory::{MemoryMap, Model};
+use crate::interpreter::ExecutionError;
+use serde::{Deserialize, Serialize};
+
+pub const SCREEN_WIDTH: usize = 160;
+pub const SCREEN_HEIGHT: usize = 144;
+
+pub const LCDC: usize = 0xFF40;
+pub const STAT: usize = 0xFF41;
+pub const SCY: usize = 0xFF42;
+pub const SCX: usize = 0xFF43;
+pub const LY: usize = 0xFF44;
+pub const LYC: usize = 0xFF45;
+pub const BGP: usize = 0xFF47;
+pub const OBP0: usize = 0xFF48;
+pub const OBP1: usize = 0xFF49;
+pub const WY: usize = 0xFF4A;
+pub const WX: usize = 0xFF4B;
+pub const IF: usize = 0xFF0F;
+pub const OAM: usize = 0xFE00;
+
+const LCDC_ENABLE: u8 = 0b10000000;
+const LCDC_WINDOW_MAP: u8 = 0b01000000;
+const LCDC_WINDOW_ENABLE: u8 = 0b00100000;
+const LCDC_TILE_DATA: u8 = 0b00010000;
+const LCDC_BG_MAP: u8 = 0b00001000;
+const LCDC_OBJ_SIZE: u8 = 0b00000100;
+const LCDC_OBJ_ENABLE: u8 = 0b00000010;
+const LCDC_BG_ENABLE: u8 = 0b00000001;
+
+const OBJ_BG_PRIORITY: u8 = 0b10000000;
+const OBJ_Y_FLIP: u8 = 0b01000000;
+const OBJ_X_FLIP: u8 = 0b00100000;
+const OBJ_PALETTE: u8 = 0b00010000;
+const OBJ_VRAM_BANK: u8 = 0b00001000;
+const OBJ_CGB_PALETTE: u8 = 0b00000111;
+
+const ATTR_PRIORITY: u8 = 0b10000000;
+const ATTR_Y_FLIP: u8 = 0b01000000;
+const ATTR_X_FLIP: u8 = 0b00100000;
+const ATTR_VRAM_BANK: u8 = 0b00001000;
+const ATTR_PALETTE: u8 = 0b00000111;
+
+const INTERRUPT_VBLANK: u8 = 0b00000001;
+const INTERRUPT_STAT: u8 = 0b00000010;
+
+const DOTS_PER_LINE: u32 = 456;
+const OAM_SCAN_DOTS: u32 = 80;
+const DRAWING_DOTS: u32 = 172;
+const LINES_PER_FRAME: u8 = 154;
+/// Dots between two VBlanks.
+pub const DOTS_PER_FRAME: u32 = DOTS_PER_LINE * LINES_PER_FRAME as u32;
+const OAM_ENTRIES: usize = 40;
+const MAX_SPRITES_PER_LINE: usize = 10;
+
+pub type Color = [u8; 3];
+
+/// Shades of the original DMG screen, from lightest (0) to darkest (3).
+pub const DMG_PALETTE: [Color; 4] = [
+    [0xE0, 0xF8, 0xD0],
+    [0x88, 0xC0, 0x70],
+    [0x34, 0x68, 0x56],
+    [0x08, 0x18, 0x20],
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Mode {
+    HBlank = 0,
+    VBlank = 1,
+    OamScan = 2,
+    Drawing = 3,
+}
+
+/// How mode 3 is emulated. The scanline renderer draws a whole line at
+/// once, the pixel FIFO renderer emulates the fetchers dot by dot so that
+/// register writes in the middle of a line are taken into account.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Renderer {
+    Scanline,
+    PixelFifo,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct Sprite {
+    y: u8,
+    x: u8,
+    tile: u8,
+    flags: u8,
+    oam_index: u8,
+}
+
+/// A background or window pixel, `palette` and `priority` coming from the
+/// CGB BG map attributes (always 0 and false on DMG).
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct BgPixel {
+    index: u8,
+    palette: u8,
+    priority: bool,
+}
+
+/// A sprite pixel, `palette` being 0 or 1 for OBP0/OBP1 on DMG or the
+/// palette number on CGB.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct ObjPixel {
+    index: u8,
+    palette: u8,
+    bg_priority: bool,
+    oam_index: u8,
+}
+
+const TRANSPARENT: ObjPixel = ObjPixel {
+    index: 0,
+    palette: 0,
+    bg_priority: false,
+    oam_index: u8::MAX,
+};
+
+impl ObjPixel {
+    fn new(sprite: &Sprite, index: u8, cgb_mode: bool) -> Self {
+        let palette = if cgb_mode {
+            sprite.flags & OBJ_CGB_PALETTE
+        } else {
+            (sprite.flags & OBJ_PALETTE != 0) as u8
+        };
+        return Self {
+            index,
+            palette,
+            bg_priority: sprite.flags & OBJ_BG_PRIORITY != 0,
+            oam_index: sprite.oam_index,
+        };
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PPU {
+    mode: Mode,
+    dots: u32,
+    ly: u8,
+    window_line: u8,
+    wy_triggered: bool,
+    stat_line: bool,
+    line_sprites: Vec<Sprite>,
+    framebuffer: Vec<Color>,
+    frame_ready: bool,
+    palette: [Color; 4],
+    renderer: Renderer,
+    line_renderer: Renderer,
+    fifo: fifo::PixelFifo,
+}
+
+impl PPU {
+    pub fn new() -> Self {
+        Self {
+            mode: Mode::OamScan,
+            dots: 0,
+            ly: 0,
+            window_line: 0,
+            wy_triggered: false,
+            stat_line: false,
+            line_sprites: vec![],
+            framebuffer: vec![DMG_PALETTE[0]; SCREEN_WIDTH * SCREEN_HEIGHT],
+            frame_ready: false,
+            palette: DMG_PALETTE,
+            renderer: Renderer::Scanline,
+            line_renderer: Renderer::Scanline,
+            fifo: fifo::PixelFifo::new(),
+        }
+    }
+
+    pub fn get_renderer(&self) -> Renderer {
+        return self.renderer;
+    }
+
+    /// The new renderer is used from the next line on.
+    pub fn set_renderer(&mut self, renderer: Renderer) {
+        self.renderer = renderer;
+    }
+
+    /// Shades of the DMG screen, from lightest to darkest.
+    pub fn set_palette(&mut self, palette: [Color; 4]) {
+        self.palette = palette;
+    }
+
+    /// Takes over what the host picked rather than the machine, the
+    /// renderer and the DMG palette, from the PPU this one replaces when
+    /// restoring a state.
+    pub fn keep_host_state(&mut self, previous: &PPU) {
+        self.renderer = previous.renderer;
+        self.palette = previous.palette;
+    }
+
+    pub fn get_mode(&self) -> Mode {
+        return self.mode;
+    }
+
+    pub fn get_framebuffer(&self) -> &[Color] {
+        return &self.framebuffer;
+    }
+
+    /// Returns true once per completed frame, when the PPU enters VBlank.
+    pub fn take_frame_ready(&mut self) -> bool {
+        let ready = self.frame_ready;
+        self.frame_ready = false;
+        return ready;
+    }
+
+    pub fn tick(&mut self, mem_map: &mut MemoryMap, dots: u32) -> Result<(), ExecutionError> {
+        if mem_map.peek_byte(LCDC)? & LCDC_ENABLE == 0 {
+            return self.disable(mem_map);
+        }
+        for _ in 0..dots {
+            self.tick_dot(mem_map)?;
+        }
+        return Ok(());
+    }
+
+    fn disable(&mut self, mem_map: &mut MemoryMap) -> Result<(), ExecutionError> {
+        // While the LCD is off LY stays at 0 and the PPU restarts from
+        // the top of the screen once it is switched back on.
+        self.mode = Mode::OamScan;
+        self.dots = 0;
+        self.ly = 0;
+        self.window_line = 0;
+        self.wy_triggered = false;
+        self.stat_line = false;
+        mem_map.poke_byte(LY, 0)?;
+        let stat = mem_map.peek_byte(STAT)?;
+        mem_map.poke_byte(STAT, (stat & 0b11111100) | 0b10000000)?;
+        return Ok(());
+    }
+
+    fn tick_dot(&mut self, mem_map: &mut MemoryMap) -> Result<(), ExecutionError> {
+        if self.dots == 0 && self.ly == mem_map.peek_byte(WY)? {
+            self.wy_triggered = true;
+        }
+        self.dots += 1;
+        match self.mode {
+            Mode::OamScan if self.dots == OAM_SCAN_DOTS => {
+                self.line_sprites = self.scan_oam(mem_map)?;
+                self.mode = Mode::Drawing;
+                self.line_renderer = self.renderer;
+                if self.line_renderer == Renderer::PixelFifo {
+                    self.start_fifo_line(mem_map)?;
+                }
+            }
+            Mode::Drawing => {
+                let done = match self.line_renderer {
+                    Renderer::Scanline if self.dots == OAM_SCAN_DOTS + DRAWING_DOTS => {
+                        self.render_scanline(mem_map)?;
+                        true
+                    }
+                    Renderer::Scanline => false,
+                    Renderer::PixelFifo => self.tick_fifo(mem_map)?,
+                };
+                if done {
+                    self.mode = Mode::HBlank;
+                    mem_map.hblank()?;
+                }
+            }
+            _ => (),
+        }
+        if self.dots == DOTS_PER_LINE {
+            self.dots = 0;
+            self.ly += 1;
+            if self.ly == LINES_PER_FRAME {
+                self.ly = 0;
+                self.window_line = 0;
+                self.wy_triggered = false;
+            }
+            if self.ly as usize == SCREEN_HEIGHT {
+                self.mode = Mode::VBlank;
+                self.frame_ready = true;
+                request_interrupt(mem_map, INTERRUPT_VBLANK)?;
+            } else if (self.ly as usize) < SCREEN_HEIGHT {
+                self.mode = Mode::OamScan;
+            }
+            mem_map.poke_byte(LY, self.ly)?;
+        }
+        return self.update_stat(mem_map);
+    }
+
+    fn update_stat(&mut self, mem_map: &mut MemoryMap) -> Result<(), ExecutionError> {
+        let stat = mem_map.peek_byte(STAT)?;
+        let coincidence = self.ly == mem_map.peek_byte(LYC)?;
+        let line = (stat & 0b00001000 != 0 && self.mode == Mode::HBlank)
+            || (stat & 0b00010000 != 0 && self.mode == Mode::VBlank)
+            || (stat & 0b00100000 != 0 && self.mode == Mode::OamScan)
+            || (stat & 0b01000000 != 0 && coincidence);
+        // The STAT interrupt only fires on a rising edge of the combined
+        // interrupt line, so overlapping sources do not retrigger it.
+        if line && !self.stat_line {
+            request_interrupt(mem_map, INTERRUPT_STAT)?;
+        }
+        self.stat_line = line;
+        let new_stat =
+            0b10000000 | (stat & 0b01111000) | ((coincidence as u8) << 2) | self.mode as u8;
+        mem_map.poke_byte(STAT, new_stat)?;
+        return Ok(());
+    }
+
+    fn sprite_height(lcdc: u8) -> u8 {
+        return if lcdc & LCDC_OBJ_SIZE != 0 { 16 } else { 8 };
+    }
+
+    /// Selects the (at most 10) sprites overlapping the current line, in the
+    /// order they will be drawn: lowest X first, then lowest OAM index on
+    /// DMG, OAM order only on CGB.
+    fn scan_oam(&self, mem_map: &MemoryMap) -> Result<Vec<Sprite>, ExecutionError> {
+        let height = Self::sprite_height(mem_map.peek_byte(LCDC)?) as i16;
+        let line = self.ly as i16;
+        let mut sprites = vec![];
+        for index in 0..OAM_ENTRIES {
+            let address = OAM + index * 4;
+            let y = mem_map.peek_byte(address)?;
+            let top = y as i16 - 16;
+            if line >= top && line < top + height {
+                sprites.push(Sprite {
+                    y,
+                    x: mem_map.peek_byte(address + 1)?,
+                    tile: mem_map.peek_byte(address + 2)?,
+                    flags: mem_map.peek_byte(address + 3)?,
+                    oam_index: index as u8,
+                });
+                if sprites.len() == MAX_SPRITES_PER_LINE {
+                    break;
+                }
+            }
+        }
+        if !mem_map.is_cgb_mode() {
+            // Stable sort, so sprites sharing an X keep their OAM order.
+            sprites.sort_by_key(|sprite| sprite.x);
+        }
+        return Ok(sprites);
+    }
+
+    /// Resolves the color shown for a pixel from its background and sprite
+    /// layers, palettes being read at that moment.
+    fn mix_pixel(
+        &self,
+        mem_map: &MemoryMap,
+        lcdc: u8,
+        bg: BgPixel,
+        obj: ObjPixel,
+    ) -> Result<Color, ExecutionError> {
+        if mem_map.is_cgb_mode() {
+            // On CGB, LCDC bit 0 clear puts every sprite above the
+            // background instead of hiding the background.
+            let obj_visible = obj.index != 0
+                && (lcdc & LCDC_BG_ENABLE == 0
+                    || bg.index == 0
+                    || (!bg.priority && !obj.bg_priority));
+            if obj_visible {
+                let color = mem_map.get_palette_color(true, obj.palette, obj.index);
+                return Ok(palette::rgb555_to_color(color));
+            }
+            let color = mem_map.get_palette_color(false, bg.palette, bg.index);
+            return Ok(palette::rgb555_to_color(color));
+        }
+
+        let obj_visible = obj.index != 0 && !(obj.bg_priority && bg.index != 0);
+        let (register, index) = if obj_visible {
+            (if obj.palette == 0 { OBP0 } else { OBP1 }, obj.index)
+        } else {
+            (BGP, bg.index)
+        };
+        let shade = apply_palette(mem_map.peek_byte(register)?, index);
+        if mem_map.get_model() == Model::CGB {
+            // DMG games on CGB go through the palettes set up by the boot
+            // ROM, BGP using BG palette 0 and OBP0/OBP1 OBJ palettes 0/1.
+            let number = if obj_visible { obj.palette } else { 0 };
+            let color = mem_map.get_palette_color(obj_visible, number, shade);
+            return Ok(palette::rgb555_to_color(color));
+        }
+        return Ok(self.palette[shade as usize]);
+    }
+}
+
+fn request_interrupt(mem_map: &mut MemoryMap, interrupt: u8) -> Result<(), ExecutionError> {
+    let flags = mem_map.peek_byte(IF)?;
+    mem_map.poke_byte(IF, flags | interrupt)?;
+    return Ok(());
+}
+
+/// Maps a 2 bit color index to a shade through a BGP/OBP palette register.
+fn apply_palette(palette: u8, index: u8) -> u8 {
+    return (palette >> (index * 2)) & 0b11;
+}
+
+/// Reads the color index of pixel `x` (0 being the leftmost) of a tile row.
+fn tile_row_pixel(low: u8, high: u8, x: u8) -> u8 {
+    let bit = 7 - x;
+    return (((high >> bit) & 1) << 1) | ((low >> bit) & 1);
+}
+
+fn tile_address(lcdc: u8, tile: u8) -> usize {
+    if lcdc & LCDC_TILE_DATA != 0 {
+        return 0x8000 + tile as usize * 16;
+    }
+    return (0x9000 + (tile as i8) as isize * 16) as usize;
+}
+
+/// Reads the tile index of a BG/window map entry and, in CGB mode, its
+/// attributes stored at the same address in VRAM bank 1.
+fn tile_map_entry(mem_map: &MemoryMap, address: usize) -> (u8, u8) {
+    let attributes = if mem_map.is_cgb_mode() {
+        mem_map.peek_vram(1, address)
+    } else {
+        0
+    };
+    return (mem_map.peek_vram(0, address), attributes);
+}
+
+/// Fetches the two bytes of row `row` of a background tile, with the flips
+/// of its attributes applied.
+fn bg_tile_row(mem_map: &MemoryMap, lcdc: u8, tile: u8, attributes: u8, row: u8) -> (u8, u8) {
+    let row = if attributes & ATTR_Y_FLIP != 0 {
+        7 - row
+    } else {
+        row
+    };
+    let bank = (attributes & ATTR_VRAM_BANK != 0) as usize;
+    let address = tile_address(lcdc, tile) + row as usize * 2;
+    let low = mem_map.peek_vram(bank, address);
+    let high = mem_map.peek_vram(bank, address + 1);
+    if attributes & ATTR_X_FLIP != 0 {
+        return (low.reverse_bits(), high.reverse_bits());
+    }
+    return (low, high);
+}
+
+fn bg_pixel(low: u8, high: u8, attributes: u8, x: u8) -> BgPixel {
+    return BgPixel {
+        index: tile_row_pixel(low, high, x),
+        palette: attributes & ATTR_PALETTE,
+        priority: attributes & ATTR_PRIORITY != 0,
+    };
+}
+
+/// Fetches the two bytes of the sprite row drawn on line `ly`, with both
+/// flips already applied.
+fn sprite_row(mem_map: &MemoryMap, sprite: &Sprite, ly: u8, height: u8) -> (u8, u8) {
+    let mut row = (ly as i16 - (sprite.y as i16 - 16)) as u8;
+    if sprite.flags & OBJ_Y_FLIP != 0 {
+        row = height - 1 - row;
+    }
+    // In 8x16 mode the lowest bit of the tile index is ignored, the bottom
+    // half of the sprite being the following tile.
+    let tile = if height == 16 {
+        sprite.tile & 0xFE
+    } else {
+        sprite.tile
+    };
+    let bank = (mem_map.is_cgb_mode() && sprite.flags & OBJ_VRAM_BANK != 0) as usize;
+    let address = 0x8000 + tile as usize * 16 + row as usize * 2;
+    let mut low = mem_map.peek_vram(bank, address);
+    let mut high = mem_map.peek_vram(bank, address + 1);
+    if sprite.flags & OBJ_X_FLIP != 0 {
+        low = low.reverse_bits();
+        high = high.reverse_bits();
+    }
+    return (low, high);
+}
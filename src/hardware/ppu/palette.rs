@@ -0,0 +1,35 @@
+use super::Color;
+use crate::hardware::memory::MemoryMap;
+
+/// Colors picked by the CGB boot ROM for DMG games it has no entry for, as
+/// RGB555 values for BG palette 0, OBJ palette 0 and OBJ palette 1.
+const DMG_COMPATIBILITY_PALETTES: [[u16; 4]; 3] = [
+    [0x7FFF, 0x1BEF, 0x6180, 0x0000],
+    [0x7FFF, 0x421F, 0x1CF2, 0x0000],
+    [0x7FFF, 0x421F, 0x1CF2, 0x0000],
+];
+
+/// Converts a CGB RGB555 color (red in the low bits) to 8 bits per channel.
+pub fn rgb555_to_color(color: u16) -> Color {
+    let expand = |channel: u16| -> u8 {
+        let channel = (channel & 0x1F) as u8;
+        return (channel << 3) | (channel >> 2);
+    };
+    return [expand(color), expand(color >> 5), expand(color >> 10)];
+}
+
+/// Sets up a CGB to run a DMG cartridge the way its boot ROM does: the
+/// CGB features are disabled and the palette RAM is filled with colors
+/// that BGP/OBP0/OBP1 will then index into.
+pub fn load_dmg_compatibility(mem_map: &mut MemoryMap) {
+    mem_map.set_dmg_compatibility(true);
+    for (palette, colors) in DMG_COMPATIBILITY_PALETTES.iter().enumerate() {
+        for (index, color) in colors.iter().enumerate() {
+            let (obj, number) = match palette {
+                0 => (false, 0),
+                _ => (true, palette as u8 - 1),
+            };
+            mem_map.set_palette_color(obj, number, index as u8, *color);
+        }
+    }
+}
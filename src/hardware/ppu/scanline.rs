@@ -0,0 +1,124 @@
+use super::{
+    BgPixel, LCDC, LCDC_BG_ENABLE, LCDC_BG_MAP, LCDC_OBJ_ENABLE, LCDC_WINDOW_ENABLE,
+    LCDC_WINDOW_MAP, ObjPixel, PPU, SCREEN_WIDTH, SCX, SCY, Sprite, TRANSPARENT, WX, bg_pixel,
+    bg_tile_row, sprite_row, tile_map_entry, tile_row_pixel,
+};
+use crate::hardware::memory::MemoryMap;
+use crate::interpreter::ExecutionError;
+
+const BLANK: BgPixel = BgPixel {
+    index: 0,
+    palette: 0,
+    priority: false,
+};
+
+impl PPU {
+    pub(super) fn render_scanline(&mut self, mem_map: &MemoryMap) -> Result<(), ExecutionError> {
+        let lcdc = mem_map.peek_byte(LCDC)?;
+        let mut bg_line = [BLANK; SCREEN_WIDTH];
+        // In CGB mode LCDC bit 0 only affects priorities, the background
+        // and window are always drawn.
+        if lcdc & LCDC_BG_ENABLE != 0 || mem_map.is_cgb_mode() {
+            self.render_background(mem_map, lcdc, &mut bg_line)?;
+            self.render_window(mem_map, lcdc, &mut bg_line)?;
+        }
+
+        let mut obj_line = [TRANSPARENT; SCREEN_WIDTH];
+        if lcdc & LCDC_OBJ_ENABLE != 0 {
+            self.render_sprites(mem_map, lcdc, &mut obj_line);
+        }
+
+        let row = self.ly as usize * SCREEN_WIDTH;
+        for x in 0..SCREEN_WIDTH {
+            self.framebuffer[row + x] = self.mix_pixel(mem_map, lcdc, bg_line[x], obj_line[x])?;
+        }
+        return Ok(());
+    }
+
+    fn render_background(
+        &self,
+        mem_map: &MemoryMap,
+        lcdc: u8,
+        bg_line: &mut [BgPixel; SCREEN_WIDTH],
+    ) -> Result<(), ExecutionError> {
+        let map = if lcdc & LCDC_BG_MAP != 0 {
+            0x9C00
+        } else {
+            0x9800
+        };
+        let y = self.ly.wrapping_add(mem_map.peek_byte(SCY)?);
+        let scx = mem_map.peek_byte(SCX)?;
+        for (x, pixel) in bg_line.iter_mut().enumerate() {
+            *pixel = tile_map_pixel(mem_map, lcdc, map, (x as u8).wrapping_add(scx), y);
+        }
+        return Ok(());
+    }
+
+    fn render_window(
+        &mut self,
+        mem_map: &MemoryMap,
+        lcdc: u8,
+        bg_line: &mut [BgPixel; SCREEN_WIDTH],
+    ) -> Result<(), ExecutionError> {
+        let wx = mem_map.peek_byte(WX)? as i16 - 7;
+        // The window shows from the first line LY matched WY on, like in
+        // the pixel FIFO, whatever WY holds afterwards.
+        if lcdc & LCDC_WINDOW_ENABLE == 0 || !self.wy_triggered || wx >= SCREEN_WIDTH as i16 {
+            return Ok(());
+        }
+        let map = if lcdc & LCDC_WINDOW_MAP != 0 {
+            0x9C00
+        } else {
+            0x9800
+        };
+        for x in wx.max(0)..SCREEN_WIDTH as i16 {
+            bg_line[x as usize] =
+                tile_map_pixel(mem_map, lcdc, map, (x - wx) as u8, self.window_line);
+        }
+        // The window keeps its own line counter, which only advances on
+        // lines where it was actually drawn.
+        self.window_line += 1;
+        return Ok(());
+    }
+
+    fn render_sprites(
+        &mut self,
+        mem_map: &MemoryMap,
+        lcdc: u8,
+        obj_line: &mut [ObjPixel; SCREEN_WIDTH],
+    ) {
+        let height = Self::sprite_height(lcdc);
+        let cgb_mode = mem_map.is_cgb_mode();
+        for (x, pixel) in obj_line.iter_mut().enumerate() {
+            // The highest priority opaque sprite wins, even when it ends up
+            // hidden behind the background.
+            for sprite in self.line_sprites.iter() {
+                if let Some(index) = sprite_pixel(mem_map, sprite, self.ly, x, height) {
+                    *pixel = ObjPixel::new(sprite, index, cgb_mode);
+                    break;
+                }
+            }
+        }
+    }
+}
+
+fn tile_map_pixel(mem_map: &MemoryMap, lcdc: u8, map: usize, x: u8, y: u8) -> BgPixel {
+    let (tile, attributes) = tile_map_entry(mem_map, map + (y as usize / 8) * 32 + x as usize / 8);
+    let (low, high) = bg_tile_row(mem_map, lcdc, tile, attributes, y % 8);
+    return bg_pixel(low, high, attributes, x % 8);
+}
+
+/// Returns the color index of a sprite at screen column `x`, or `None` when
+/// the sprite does not cover that column or is transparent there.
+fn sprite_pixel(mem_map: &MemoryMap, sprite: &Sprite, ly: u8, x: usize, height: u8) -> Option<u8> {
+    let column = x as i16 - (sprite.x as i16 - 8);
+    if !(0..8).contains(&column) {
+        return None;
+    }
+    let (low, high) = sprite_row(mem_map, sprite, ly, height);
+    let index = tile_row_pixel(low, high, column as u8);
+    if index == 0 {
+        return None;
+    }
+    return Some(index);
+}
@@ -511,14 +511,3 @@ pub fn get_instruction(bytes: &[u8]) -> Result<Instruction, DisassemblyError> {
     }
     return Ok(Unkown(current));
 }
-
-pub fn disassemble_program(bytes: &[u8]) -> Result<Vec<Instruction>, DisassemblyError> {
-    let mut instructions = vec![];
-    let mut head = 0;
-    while head < bytes.len() {
-        let instruction = get_instruction(&bytes[head..bytes.len()])?;
-        head += instruction.get_size();
-        instructions.push(instruction);
-    }
-    return Ok(instructions);
-}
@@ -2,8 +2,8 @@ pub mod disassembler;
 use crate::hardware::cpu::{CPU, Register};
 use crate::hardware::memory::MemoryMap;
 use crate::utils::{
-    borrow_occurred_byte, borrow_occurred_word, endianess_conversion, get_bit_of_byte,
-    overflow_occured_byte, overflow_occured_word, set_bit_of_byte,
+    borrow_occurred_byte, endianess_conversion, get_bit_of_byte, overflow_occured_byte,
+    overflow_occured_word, set_bit_of_byte,
 };
 use disassembler::Cond;
 use disassembler::Instruction;
@@ -18,6 +18,19 @@ pub enum ExecutionError {
     MemoryOutOfBoundsError(usize),
 }
 
+impl std::fmt::Display for ExecutionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        return match self {
+            ExecutionError::IllegalInstructionError(instruction, reason) => {
+                write!(f, "{:X?}: {}", instruction, reason)
+            }
+            ExecutionError::MemoryOutOfBoundsError(address) => {
+                write!(f, "memory access out of bounds at {:04X}", address)
+            }
+        };
+    }
+}
+
 pub fn execute(
     mem_map: &mut MemoryMap,
     cpu: &mut CPU,
@@ -25,7 +38,7 @@ pub fn execute(
 ) -> Result<u32, ExecutionError> {
     use Instruction::*;
     return Ok(match instruction {
-        Unkown(_) => {
+        Unkown(_) | HALT | Reti => {
             return Err(ExecutionError::IllegalInstructionError(
                 instruction.clone(),
                 "Instruction is unkown or has not yet been implemented".to_string(),
@@ -40,8 +53,7 @@ pub fn execute(
         CPL => execute_cpl(cpu),
         SCF => execute_scf(cpu),
         CCF => execute_ccf(cpu),
-        STOP => execute_stop(),
-        HALT => todo!(),
+        STOP => execute_stop(mem_map),
         DI => execute_di(cpu),
         EI => execute_ei(cpu),
         LdR16Imm16(..) => execute_ld_r16_imm16(mem_map, cpu, instruction)?,
@@ -78,7 +90,6 @@ pub fn execute(
         OrAImm8(byte) => execute_or_a_imm8(cpu, *byte),
         CpAImm8(byte) => execute_cp_a_imm8(cpu, *byte),
         Ret => execute_ret(mem_map, cpu)?,
-        Reti => todo!(),
         RetCond(cond) => execute_ret_cond(mem_map, cpu, cond)?,
         JpImm16(word) => execute_jp_imm16(cpu, *word),
         JpCondImm16(cond, word) => execute_jp_cond_imm16(cpu, cond, *word),
@@ -90,6 +101,17 @@ pub fn execute(
     });
 }
 
+/// Pushes PC and jumps to `address` the way a CALL does, so that the routine
+/// comes back through `execute_ret`. Used to run code from outside the CPU.
+pub fn call(mem_map: &mut MemoryMap, cpu: &mut CPU, address: u16) -> Result<(), ExecutionError> {
+    use Register::*;
+    cpu.sub_word(&SP, 2);
+    let return_address = endianess_conversion(cpu.read_word(&PC));
+    mem_map.write_word(cpu.read_word(&SP) as usize, return_address)?;
+    cpu.write_word(&PC, address);
+    return Ok(());
+}
+
 fn execute_di(cpu: &mut CPU) -> u32 {
     cpu.disable_interupts();
     return 1;
@@ -501,8 +523,11 @@ fn execute_add_hl_r16(cpu: &mut CPU, r16: &R16) -> u32 {
     return 2;
 }
 
-fn execute_stop() -> u32 {
-    // todo
+fn execute_stop(mem_map: &mut MemoryMap) -> u32 {
+    if mem_map.switch_speed() {
+        return 1;
+    }
+    // todo: low power mode
     return 0;
 }
 
@@ -1,78 +1,378 @@
+mod audio;
+mod cli;
+mod config;
+mod emulator;
+mod gbs;
 pub mod hardware;
 mod interpreter;
 pub mod utils;
 mod vue;
 
+use audio::AudioSink;
+use cli::Options;
+use config::Config;
+use emulator::cartridge::{CartridgeError, Hardware};
+use emulator::loader::{self, LoadError, RomSource};
+use emulator::patch;
+use emulator::state::SLOT_COUNT;
+use emulator::thread::EmulatorThread;
+use emulator::{Command, Emulator, HeadlessError, Snapshot};
+use gbs::{GbsError, GbsPlayer};
+use hardware::apu::CHANNEL_COUNT;
 use hardware::cpu::CPU;
-use hardware::cpu::Register;
-use hardware::memory::MemoryMap;
+use hardware::memory::{MemoryMap, Model};
 use interpreter::disassembler;
-use interpreter::disassembler::Instruction;
 
+use clap::Parser;
 use eframe::egui;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::SystemTime;
+use vue::bindings::Action;
+use vue::files::FileBrowser;
+use vue::gamepad::Gamepads;
+use vue::recent::RecentRoms;
+use vue::search::RamSearch;
+use vue::settings::Preferences;
 
 pub struct EmulatorApp {
-    mem_map: MemoryMap,
-    cpu: CPU,
-    step_flag: bool,
-    pause_flag: bool,
+    emulator: EmulatorThread,
+    /// Latest state published by the emulation thread.
+    snapshot: Snapshot,
+    screen_texture: Option<egui::TextureHandle>,
+    /// Settings saved between runs, as configured even when overridden on
+    /// the command line.
+    config: Config,
+    /// Size of a Game Boy pixel on screen.
+    scale: f32,
+    /// Inner size of the window, saved on exit.
+    window_size: Option<egui::Vec2>,
+    record_stems: bool,
+    channel_muted: [bool; CHANNEL_COUNT],
+    channel_solo: [bool; CHANNEL_COUNT],
+    /// Address typed in the breakpoint field, in hexadecimal.
+    breakpoint_input: String,
+    gamepads: Gamepads,
+    /// Actions held on the keyboard or gamepads, as last sent to the
+    /// emulator.
+    held_actions: [bool; Action::ALL.len()],
+    /// Whether the fast-forward key is held.
+    fast_forward: bool,
+    rewinding: bool,
+    /// Thumbnails of the save state slots, along with when they were saved.
+    slot_textures: Vec<Option<(SystemTime, egui::TextureHandle)>>,
+    /// BESS file typed in to import or export.
+    bess_path: String,
+    /// Movie file typed in to record or play back.
+    movie_path: String,
+    /// Whether movies are recorded from the current state.
+    movie_from_state: bool,
+    /// Cheat being typed in, along with why it was refused.
+    cheat_name: String,
+    cheat_code: String,
+    cheat_error: Option<String>,
+    /// Cheat being renamed, with the name typed in so far.
+    cheat_rename: Option<(usize, String)>,
+    /// RAM search window, with the addresses watched.
+    ram_search: RamSearch,
+    preferences: Preferences,
+    /// Whether the controls window is open.
+    controls_open: bool,
+    /// Action waiting for a key or gamepad input to bind.
+    binding_action: Option<Action>,
+    /// Name typed in for a new input profile.
+    profile_name: String,
+    /// Open while picking a ROM to open.
+    browser: Option<FileBrowser>,
+    recent: RecentRoms,
 }
 
 impl EmulatorApp {
-    fn step(&mut self, instruction: Instruction) {
-        println!("{:X?}", instruction);
-        self.cpu
-            .add_word(&Register::PC, instruction.get_size() as u16);
-        interpreter::execute(&mut self.mem_map, &mut self.cpu, &instruction).unwrap();
-        self.cpu.refresh_interupt_flag();
+    fn new(
+        emulator: EmulatorThread,
+        snapshot: Snapshot,
+        config: Config,
+        record_stems: bool,
+        scale: f32,
+    ) -> Self {
+        let mut app = Self {
+            emulator,
+            snapshot,
+            screen_texture: None,
+            preferences: Preferences::new(&config),
+            config,
+            scale,
+            window_size: None,
+            record_stems,
+            channel_muted: [false; CHANNEL_COUNT],
+            channel_solo: [false; CHANNEL_COUNT],
+            breakpoint_input: String::new(),
+            gamepads: Gamepads::new(),
+            held_actions: [false; Action::ALL.len()],
+            fast_forward: false,
+            rewinding: false,
+            slot_textures: vec![None; SLOT_COUNT],
+            bess_path: String::new(),
+            movie_path: String::new(),
+            movie_from_state: false,
+            cheat_name: String::new(),
+            cheat_code: String::new(),
+            cheat_error: None,
+            cheat_rename: None,
+            ram_search: RamSearch::new(),
+            controls_open: false,
+            binding_action: None,
+            profile_name: String::new(),
+            browser: None,
+            recent: RecentRoms::load(),
+        };
+        app.rom_opened();
+        return app;
     }
 
-    fn next_instruction(&mut self) -> Instruction {
-        let next_bytes = self
-            .mem_map
-            .read_bytes(self.cpu.read_word(&Register::PC) as usize, 3)
-            .unwrap();
-        return disassembler::get_instruction(&next_bytes).unwrap();
+    /// Remembers the ROM just opened, and forgets the thumbnails of the
+    /// slots of the previous one and the cheat being renamed.
+    fn rom_opened(&mut self) {
+        self.slot_textures = vec![None; SLOT_COUNT];
+        self.cheat_rename = None;
+        if let Some(source) = &self.snapshot.rom_source {
+            self.recent.add(source);
+        }
+    }
+
+    /// Soloed channels are the only ones heard, otherwise every channel but
+    /// the muted ones.
+    fn audible_channels(&self) -> [bool; CHANNEL_COUNT] {
+        if self.channel_solo.contains(&true) {
+            return self.channel_solo;
+        }
+        return self.channel_muted.map(|muted| !muted);
     }
 }
 
 impl eframe::App for EmulatorApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        self.step_flag = false;
-        let instruction = self.next_instruction();
-        vue::debug::show(ctx, _frame, self, &instruction);
-        ctx.request_repaint();
-        if !self.pause_flag || self.step_flag {
-            self.step(instruction);
+        // The emulation thread requests a repaint whenever it publishes.
+        if let Some(snapshot) = self.emulator.take_snapshot() {
+            let opened = snapshot.rom_source != self.snapshot.rom_source;
+            self.snapshot = snapshot;
+            if opened {
+                self.rom_opened();
+            }
+        }
+        if let Some(rect) = ctx.input(|i| i.viewport().inner_rect) {
+            self.window_size = Some(rect.size());
+        }
+        vue::input::poll(ctx, self);
+        vue::files::show(ctx, self);
+        vue::debug::show(ctx, _frame, self);
+        vue::controls::show(ctx, self);
+        vue::settings::show(ctx, self);
+    }
+
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        let Some(size) = self.window_size else {
+            return;
+        };
+        let window = &mut self.config.window;
+        if (size.x, size.y) != (window.width, window.height) {
+            window.width = size.x;
+            window.height = size.y;
+            self.config.save();
         }
     }
 }
 
-fn read_rom(path: &str) -> Vec<u8> {
-    let rom = std::fs::read(path).unwrap();
-    return rom;
+/// Reasons the emulator could not start or finish a headless run, reported
+/// before exiting with a failure status.
+#[derive(Debug)]
+enum StartupError {
+    Read(PathBuf, std::io::Error),
+    Load(RomSource, LoadError),
+    Gbs(PathBuf, GbsError),
+    Cartridge(CartridgeError),
+    Record(PathBuf, std::io::Error),
+    Window(eframe::Error),
+    Headless(HeadlessError),
+}
+
+impl std::fmt::Display for StartupError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        return match self {
+            StartupError::Read(path, error) => {
+                write!(f, "could not read {}: {}", path.display(), error)
+            }
+            StartupError::Load(source, error) => {
+                write!(f, "could not load {}: {}", source, error)
+            }
+            StartupError::Gbs(path, error) => {
+                write!(f, "could not load {}: {}", path.display(), error)
+            }
+            StartupError::Cartridge(error) => write!(f, "{}", error),
+            StartupError::Record(path, error) => {
+                write!(f, "could not record to {}: {}", path.display(), error)
+            }
+            StartupError::Window(error) => write!(f, "could not open the window: {}", error),
+            StartupError::Headless(error) => write!(f, "{}", error),
+        };
+    }
+}
+
+fn read_file(path: &Path) -> Result<Vec<u8>, StartupError> {
+    return std::fs::read(path).map_err(|error| StartupError::Read(path.to_path_buf(), error));
+}
+
+fn main() -> ExitCode {
+    let options = Options::parse();
+    return match run(options) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("error: {}", error);
+            ExitCode::FAILURE
+        }
+    };
 }
 
-fn main() -> eframe::Result<()> {
-    let input = read_rom("roms/cpu_instrs/individual/04-op r,imm.gb");
-    let program = disassembler::disassemble_program(&input).unwrap();
-    println!("Full program:\n{:X?}\n", program);
-    let mut mem_map = MemoryMap::new();
-    let cpu = CPU::new();
+fn run(options: Options) -> Result<(), StartupError> {
+    let config = Config::load();
+    let gbs = match &options.gbs {
+        Some(path) => {
+            let bytes = read_file(path)?;
+            let player =
+                GbsPlayer::load(&bytes).map_err(|error| StartupError::Gbs(path.clone(), error))?;
+            if player.is_truncated() {
+                eprintln!("Banked GBS files are not supported, only the first 32 KiB are mapped");
+            }
+            Some(player)
+        }
+        None => None,
+    };
 
-    mem_map.write_bytes(0, input.to_vec()).unwrap();
+    let mut hardware = Hardware {
+        model: options.model.or(config.model).map(Into::into),
+        boot_roms: config.boot_roms.clone(),
+    };
+    // Clap makes sure there is a ROM unless playing a GBS.
+    let rom_path = options.gbs.clone().or(options.rom.clone()).unwrap();
+    let rom = if gbs.is_some() {
+        None
+    } else {
+        let source = RomSource {
+            path: rom_path.clone(),
+            entry: options.entry.clone(),
+        };
+        let load_error = |error| StartupError::Load(source.clone(), error);
+        let mut loaded = loader::load(&source).map_err(load_error)?;
+        let patch = match &options.patch {
+            Some(patch) => Some(patch.clone()),
+            None if options.no_patch => None,
+            None => patch::find(&loaded.save_path),
+        };
+        if let Some(patch) = patch {
+            loader::apply_patch(&mut loaded, &patch).map_err(load_error)?;
+            eprintln!("Patched with {}", patch.display());
+        }
+        let rom = loaded.rom;
+        if options.disassemble {
+            print_disassembly(&rom);
+            return Ok(());
+        }
+        // The boot ROM given replaces the one configured for the model
+        // picked, for the ROMs opened later on as well.
+        if let Some(path) = &options.boot_rom {
+            let model = hardware.get_model(&rom);
+            hardware.boot_roms.set(model, Some(path.clone()));
+        }
+        let cartridge = hardware.load(rom).map_err(StartupError::Cartridge)?;
+        if cartridge.is_truncated() {
+            eprintln!("Banked ROMs are not supported, only the first 32 KiB are mapped");
+        }
+        Some((cartridge, source, loaded.save_path))
+    };
+    let record_stems = options.stems;
+    let scale = options.scale.unwrap_or(config.window.scale);
+    let headless = options.headless;
+    let save_dir = config.save_dir.clone();
+    let palette = config.palette;
+    let gain = config.audio.get_gain();
+    let build = move |audio: Box<dyn AudioSink>| -> Result<Emulator, StartupError> {
+        // The machine is set up once the cartridge is loaded or the first
+        // track starts.
+        let mut emulator = Emulator::new(MemoryMap::new(Model::DMG), CPU::new(), gbs, audio);
+        emulator.handle(Command::SetTrace(options.trace));
+        emulator.handle(Command::SetSaveDir(save_dir));
+        emulator.handle(Command::SetPalette(palette));
+        emulator.handle(Command::SetVolume(gain));
+        emulator.handle(Command::SetPaused(options.paused));
+        emulator.set_hardware(hardware);
+        match rom {
+            Some((cartridge, source, save_path)) => {
+                emulator.load_cartridge(cartridge, source, save_path)
+            }
+            None => emulator.set_rom_path(rom_path),
+        }
+        if let Some(gbs) = emulator.get_gbs() {
+            let track = options
+                .track
+                .unwrap_or(gbs.get_header().first_song)
+                .saturating_sub(1);
+            emulator.handle(Command::StartGbsTrack(track));
+        }
+        if let Some(path) = options.record {
+            emulator
+                .start_recording(&path, options.stems)
+                .map_err(|error| StartupError::Record(path, error))?;
+        }
+        return Ok(emulator);
+    };
+    if let Some(seconds) = headless {
+        let sink = audio::NullSink::new(hardware::apu::DEFAULT_SAMPLE_RATE);
+        return build(Box::new(sink))?
+            .run_headless(seconds)
+            .map_err(StartupError::Headless);
+    }
 
-    let options = eframe::NativeOptions::default();
-    eframe::run_native(
+    let native_options = eframe::NativeOptions {
+        viewport: egui::ViewportBuilder::default()
+            .with_inner_size([config.window.width, config.window.height]),
+        ..Default::default()
+    };
+    return eframe::run_native(
         "Emulator",
-        options,
-        Box::new(|_cc| {
-            Ok(Box::new(EmulatorApp {
-                mem_map,
-                cpu,
-                step_flag: false,
-                pause_flag: false,
-            }))
+        native_options,
+        Box::new(move |cc| {
+            let ctx = cc.egui_ctx.clone();
+            let (emulator, snapshot) = EmulatorThread::spawn(
+                move || {
+                    build(audio::open_default()).unwrap_or_else(|error| {
+                        eprintln!("error: {}", error);
+                        std::process::exit(1);
+                    })
+                },
+                move || ctx.request_repaint(),
+            );
+            Ok(Box::new(EmulatorApp::new(
+                emulator,
+                snapshot,
+                config,
+                record_stems,
+                scale,
+            )))
         }),
     )
+    .map_err(StartupError::Window);
+}
+
+/// Prints the ROM decoded from its start, one instruction per line.
+fn print_disassembly(rom: &[u8]) {
+    let mut address = 0;
+    while address < rom.len() {
+        let Ok(instruction) = disassembler::get_instruction(&rom[address..]) else {
+            // The last instruction is cut by the end of the ROM.
+            println!("{:04X}  {:02X?}", address, &rom[address..]);
+            return;
+        };
+        println!("{:04X}  {:X?}", address, instruction);
+        address += instruction.get_size();
+    }
 }
@@ -0,0 +1,153 @@
+use crate::EmulatorApp;
+use crate::emulator::Command;
+use crate::hardware::apu::{CHANNEL_COUNT, ChannelState, NR10, WAVE_RAM};
+use crate::hardware::memory::MemoryMap;
+
+const CHANNEL_NAMES: [&str; CHANNEL_COUNT] = ["CH1 square", "CH2 square", "CH3 wave", "CH4 noise"];
+const NOTE_NAMES: [&str; 12] = [
+    "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+];
+/// Register names from NR10, five per channel then NR50-NR52.
+const REGISTER_NAMES: [&str; 23] = [
+    "NR10", "NR11", "NR12", "NR13", "NR14", "NR20", "NR21", "NR22", "NR23", "NR24", "NR30", "NR31",
+    "NR32", "NR33", "NR34", "NR40", "NR41", "NR42", "NR43", "NR44", "NR50", "NR51", "NR52",
+];
+const SCOPE_HEIGHT: f32 = 40.0;
+
+pub fn show(ui: &mut egui::Ui, app: &mut EmulatorApp) {
+    ui.heading("Audio");
+    for channel in 0..CHANNEL_COUNT {
+        show_channel(ui, app, channel);
+        ui.separator();
+    }
+    show_registers(ui, &app.snapshot.mem_map);
+    ui.separator();
+    show_wave_ram(ui, &app.snapshot.mem_map);
+}
+
+fn show_channel(ui: &mut egui::Ui, app: &mut EmulatorApp, channel: usize) {
+    let apu = app.snapshot.mem_map.get_apu();
+    let changed = ui
+        .horizontal(|ui| {
+            ui.strong(CHANNEL_NAMES[channel]);
+            let muted = ui.checkbox(&mut app.channel_muted[channel], "Mute");
+            let soloed = ui.checkbox(&mut app.channel_solo[channel], "Solo");
+            muted.changed() || soloed.changed()
+        })
+        .inner;
+    if changed {
+        app.emulator
+            .send(Command::SetAudibleChannels(app.audible_channels()));
+    }
+    ui.label(describe_channel(channel, &apu.get_channel_state(channel)));
+    show_scope(ui, &apu.get_scope(channel));
+}
+
+fn describe_channel(channel: usize, state: &ChannelState) -> String {
+    let status = if state.enabled {
+        "on"
+    } else if state.dac_enabled {
+        "off"
+    } else {
+        "DAC off"
+    };
+    let mut parts = vec![status.to_string()];
+    if channel == 3 {
+        parts.push(format!("clock {:.0} Hz", state.frequency));
+    } else {
+        parts.push(format!(
+            "{:.1} Hz ({})",
+            state.frequency,
+            note_name(state.frequency)
+        ));
+    }
+    if let Some(duty) = state.duty {
+        parts.push(format!("duty {}/8", duty));
+    }
+    parts.push(format!("volume {}", state.volume));
+    if let Some((increase, period)) = state.envelope {
+        let direction = if increase { "up" } else { "down" };
+        if period == 0 {
+            parts.push("envelope stopped".to_string());
+        } else {
+            parts.push(format!("envelope {} every {}", direction, period));
+        }
+    }
+    if state.length_enabled {
+        parts.push(format!("length {}", state.length));
+    } else {
+        parts.push("length off".to_string());
+    }
+    return parts.join(", ");
+}
+
+/// Nearest note of the equal temperament, such as A4 for 440 Hz.
+fn note_name(frequency: f64) -> String {
+    if frequency <= 0.0 {
+        return "-".to_string();
+    }
+    let midi = (69.0 + 12.0 * (frequency / 440.0).log2()).round() as i32;
+    return format!(
+        "{}{}",
+        NOTE_NAMES[midi.rem_euclid(12) as usize],
+        midi.div_euclid(12) - 1
+    );
+}
+
+fn show_scope(ui: &mut egui::Ui, points: &[f32]) {
+    let size = egui::vec2(ui.available_width(), SCOPE_HEIGHT);
+    let (response, painter) = ui.allocate_painter(size, egui::Sense::hover());
+    let rect = response.rect;
+    painter.rect_filled(rect, 0.0, ui.visuals().extreme_bg_color);
+    let step = rect.width() / (points.len().max(2) - 1) as f32;
+    let line = points
+        .iter()
+        .enumerate()
+        .map(|(index, value)| {
+            egui::pos2(
+                rect.left() + index as f32 * step,
+                rect.center().y - value * rect.height() / 2.0,
+            )
+        })
+        .collect();
+    painter.add(egui::Shape::line(
+        line,
+        egui::Stroke::new(1.0, ui.visuals().text_color()),
+    ));
+}
+
+fn show_registers(ui: &mut egui::Ui, mem_map: &MemoryMap) {
+    egui::Grid::new("apu_registers")
+        .striped(true)
+        .show(ui, |ui| {
+            for (index, name) in REGISTER_NAMES.iter().enumerate() {
+                let value = mem_map.peek_byte(NR10 + index).unwrap();
+                ui.label(format!("{} {:02X}", name, value));
+                if index % 5 == 4 {
+                    ui.end_row();
+                }
+            }
+        });
+}
+
+fn show_wave_ram(ui: &mut egui::Ui, mem_map: &MemoryMap) {
+    let bytes = (0..16)
+        .map(|offset| mem_map.peek_byte(WAVE_RAM + offset).unwrap())
+        .collect::<Vec<_>>();
+    ui.label(format!("Wave RAM {:02X?}", bytes));
+    let size = egui::vec2(ui.available_width(), SCOPE_HEIGHT);
+    let (response, painter) = ui.allocate_painter(size, egui::Sense::hover());
+    let rect = response.rect;
+    painter.rect_filled(rect, 0.0, ui.visuals().extreme_bg_color);
+    let width = rect.width() / 32.0;
+    let samples = bytes.iter().flat_map(|byte| [byte >> 4, byte & 0x0F]);
+    for (index, sample) in samples.enumerate() {
+        let height = rect.height() * sample as f32 / 15.0;
+        let left = rect.left() + index as f32 * width;
+        let bar = egui::Rect::from_min_max(
+            egui::pos2(left, rect.bottom() - height),
+            egui::pos2(left + width - 1.0, rect.bottom()),
+        );
+        painter.rect_filled(bar, 0.0, ui.visuals().text_color());
+    }
+}
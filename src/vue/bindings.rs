@@ -0,0 +1,342 @@
+use std::collections::BTreeMap;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::hardware::joypad::Button;
+
+const DEFAULT_PROFILE: &str = "Default";
+/// Presses per second of the turbo buttons, unless configured.
+pub const DEFAULT_TURBO_RATE: u32 = 10;
+pub const MAX_TURBO_RATE: u32 = 30;
+const BUTTON_PREFIX: &str = "pad:";
+const AXIS_PREFIX: &str = "stick:";
+
+/// What a binding does, a joypad button held or pressed repeatedly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Right,
+    Left,
+    Up,
+    Down,
+    A,
+    B,
+    Select,
+    Start,
+    TurboA,
+    TurboB,
+}
+
+impl Action {
+    pub const ALL: [Action; 10] = [
+        Action::Right,
+        Action::Left,
+        Action::Up,
+        Action::Down,
+        Action::A,
+        Action::B,
+        Action::Select,
+        Action::Start,
+        Action::TurboA,
+        Action::TurboB,
+    ];
+
+    pub fn get_button(&self) -> Button {
+        return match self {
+            Action::Right => Button::Right,
+            Action::Left => Button::Left,
+            Action::Up => Button::Up,
+            Action::Down => Button::Down,
+            Action::A | Action::TurboA => Button::A,
+            Action::B | Action::TurboB => Button::B,
+            Action::Select => Button::Select,
+            Action::Start => Button::Start,
+        };
+    }
+
+    pub fn is_turbo(&self) -> bool {
+        return matches!(self, Action::TurboA | Action::TurboB);
+    }
+
+    pub fn get_name(&self) -> &'static str {
+        return match self {
+            Action::TurboA => "Turbo A",
+            Action::TurboB => "Turbo B",
+            _ => match self.get_button() {
+                Button::Right => "Right",
+                Button::Left => "Left",
+                Button::Up => "Up",
+                Button::Down => "Down",
+                Button::A => "A",
+                Button::B => "B",
+                Button::Select => "Select",
+                Button::Start => "Start",
+            },
+        };
+    }
+}
+
+/// Gamepad button, named after its position on the pad as the labels vary
+/// between brands.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PadButton {
+    South,
+    East,
+    North,
+    West,
+    LeftBumper,
+    RightBumper,
+    LeftTrigger,
+    RightTrigger,
+    Select,
+    Start,
+    LeftStick,
+    RightStick,
+    DpadUp,
+    DpadDown,
+    DpadLeft,
+    DpadRight,
+}
+
+/// Gamepad stick pushed in a direction.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PadAxis {
+    LeftStickLeft,
+    LeftStickRight,
+    LeftStickUp,
+    LeftStickDown,
+    RightStickLeft,
+    RightStickRight,
+    RightStickUp,
+    RightStickDown,
+}
+
+/// Host input an action is bound to, saved as the name of the key or as
+/// the gamepad input after its prefix.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(into = "String", try_from = "String")]
+pub enum Binding {
+    Key(egui::Key),
+    Button(PadButton),
+    Axis(PadAxis),
+}
+
+impl std::fmt::Display for Binding {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        return match self {
+            Binding::Key(key) => write!(f, "{}", key.name()),
+            Binding::Button(button) => write!(f, "🎮 {:?}", button),
+            Binding::Axis(axis) => write!(f, "🎮 {:?}", axis),
+        };
+    }
+}
+
+impl From<Binding> for String {
+    fn from(binding: Binding) -> Self {
+        return match binding {
+            Binding::Key(key) => key.name().to_string(),
+            Binding::Button(button) => format!("{}{}", BUTTON_PREFIX, variant_name(button)),
+            Binding::Axis(axis) => format!("{}{}", AXIS_PREFIX, variant_name(axis)),
+        };
+    }
+}
+
+impl TryFrom<String> for Binding {
+    type Error = String;
+
+    fn try_from(name: String) -> Result<Self, String> {
+        if let Some(button) = name.strip_prefix(BUTTON_PREFIX) {
+            return parse_variant(button).map(Binding::Button);
+        }
+        if let Some(axis) = name.strip_prefix(AXIS_PREFIX) {
+            return parse_variant(axis).map(Binding::Axis);
+        }
+        return egui::Key::from_name(&name)
+            .map(Binding::Key)
+            .ok_or_else(|| format!("unknown key {}", name));
+    }
+}
+
+/// Name of the unit variant `value` as serialized.
+fn variant_name<T: Serialize>(value: T) -> String {
+    return match toml::Value::try_from(value) {
+        Ok(toml::Value::String(name)) => name,
+        _ => unreachable!("unit variants serialize to their name"),
+    };
+}
+
+fn parse_variant<T: DeserializeOwned>(name: &str) -> Result<T, String> {
+    return T::deserialize(toml::Value::String(name.to_string()))
+        .map_err(|_| format!("unknown gamepad input {}", name));
+}
+
+/// Named set of bindings, to switch between players or controllers.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Profile {
+    pub name: String,
+    /// Presses per second of the turbo buttons.
+    #[serde(default = "default_turbo_rate")]
+    pub turbo_a_rate: u32,
+    #[serde(default = "default_turbo_rate")]
+    pub turbo_b_rate: u32,
+    #[serde(default)]
+    pub bindings: BTreeMap<Action, Vec<Binding>>,
+}
+
+fn default_turbo_rate() -> u32 {
+    return DEFAULT_TURBO_RATE;
+}
+
+impl Profile {
+    /// Arrows, X and Z on the keyboard, the d-pad or left stick and the
+    /// face buttons on gamepads.
+    pub fn new(name: String) -> Self {
+        use Binding::{Axis, Button, Key};
+        let bindings = [
+            (
+                Action::Right,
+                vec![
+                    Key(egui::Key::ArrowRight),
+                    Button(PadButton::DpadRight),
+                    Axis(PadAxis::LeftStickRight),
+                ],
+            ),
+            (
+                Action::Left,
+                vec![
+                    Key(egui::Key::ArrowLeft),
+                    Button(PadButton::DpadLeft),
+                    Axis(PadAxis::LeftStickLeft),
+                ],
+            ),
+            (
+                Action::Up,
+                vec![
+                    Key(egui::Key::ArrowUp),
+                    Button(PadButton::DpadUp),
+                    Axis(PadAxis::LeftStickUp),
+                ],
+            ),
+            (
+                Action::Down,
+                vec![
+                    Key(egui::Key::ArrowDown),
+                    Button(PadButton::DpadDown),
+                    Axis(PadAxis::LeftStickDown),
+                ],
+            ),
+            (Action::A, vec![Key(egui::Key::X), Button(PadButton::East)]),
+            (Action::B, vec![Key(egui::Key::Z), Button(PadButton::South)]),
+            (
+                Action::Select,
+                vec![Key(egui::Key::Backspace), Button(PadButton::Select)],
+            ),
+            (
+                Action::Start,
+                vec![Key(egui::Key::Enter), Button(PadButton::Start)],
+            ),
+            (
+                Action::TurboA,
+                vec![Key(egui::Key::S), Button(PadButton::North)],
+            ),
+            (
+                Action::TurboB,
+                vec![Key(egui::Key::A), Button(PadButton::West)],
+            ),
+        ];
+        return Self {
+            name,
+            turbo_a_rate: DEFAULT_TURBO_RATE,
+            turbo_b_rate: DEFAULT_TURBO_RATE,
+            bindings: bindings.into_iter().collect(),
+        };
+    }
+
+    pub fn get_bindings(&self, action: Action) -> &[Binding] {
+        return self.bindings.get(&action).map_or(&[], Vec::as_slice);
+    }
+
+    /// Presses per second of the turbo `action`.
+    pub fn get_turbo_rate(&self, action: Action) -> u32 {
+        let rate = match action {
+            Action::TurboB => self.turbo_b_rate,
+            _ => self.turbo_a_rate,
+        };
+        return rate.clamp(1, MAX_TURBO_RATE);
+    }
+
+    /// Binds `binding` to `action` alone, taking it from any other action.
+    pub fn bind(&mut self, action: Action, binding: Binding) {
+        for bindings in self.bindings.values_mut() {
+            bindings.retain(|other| *other != binding);
+        }
+        self.bindings.entry(action).or_default().push(binding);
+    }
+
+    pub fn unbind(&mut self, action: Action, binding: Binding) {
+        if let Some(bindings) = self.bindings.get_mut(&action) {
+            bindings.retain(|other| *other != binding);
+        }
+    }
+}
+
+/// Input profiles, saved along with the rest of the configuration.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InputConfig {
+    /// Name of the profile in use.
+    pub active: String,
+    /// Never empty.
+    #[serde(rename = "profile")]
+    pub profiles: Vec<Profile>,
+}
+
+impl Default for InputConfig {
+    fn default() -> Self {
+        Self {
+            active: DEFAULT_PROFILE.to_string(),
+            profiles: vec![Profile::new(DEFAULT_PROFILE.to_string())],
+        }
+    }
+}
+
+impl InputConfig {
+    /// Profile in use, the first one if it was removed from the file.
+    pub fn get_active(&self) -> &Profile {
+        return self
+            .profiles
+            .iter()
+            .find(|profile| profile.name == self.active)
+            .unwrap_or(&self.profiles[0]);
+    }
+
+    pub fn get_active_mut(&mut self) -> &mut Profile {
+        let index = self
+            .profiles
+            .iter()
+            .position(|profile| profile.name == self.active)
+            .unwrap_or(0);
+        return &mut self.profiles[index];
+    }
+
+    /// Adds a copy of the active profile named `name`, and switches to it.
+    pub fn add_profile(&mut self, name: String) {
+        let mut profile = self.get_active().clone();
+        profile.name = name.clone();
+        self.profiles.push(profile);
+        self.active = name;
+    }
+
+    /// Removes the active profile unless it is the last one, switching to
+    /// the first one left.
+    pub fn remove_active(&mut self) {
+        if self.profiles.len() > 1 {
+            let name = self.get_active().name.clone();
+            self.profiles.retain(|profile| profile.name != name);
+            self.active = self.profiles[0].name.clone();
+        }
+    }
+}
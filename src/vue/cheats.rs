@@ -0,0 +1,89 @@
+use crate::EmulatorApp;
+use crate::emulator::Command;
+use crate::emulator::cheats::Cheat;
+
+/// Lists the cheats of the ROM to enable, rename or remove them, and adds
+/// new ones.
+pub fn show(ui: &mut egui::Ui, app: &mut EmulatorApp) {
+    ui.separator();
+    ui.heading("Cheats").on_hover_text(
+        "GameShark codes (01VVAAAA) write to RAM every frame, Game Genie codes \
+         (ABC-DEF or ABC-DEF-GHI) replace ROM bytes. Separate several codes \
+         with +.",
+    );
+    let cheats = app.snapshot.cheats.clone();
+    egui::Grid::new("cheats").striped(true).show(ui, |ui| {
+        for (index, cheat) in cheats.iter().enumerate() {
+            show_cheat(ui, app, index, cheat);
+            ui.end_row();
+        }
+    });
+    ui.horizontal(|ui| {
+        ui.add(
+            egui::TextEdit::singleline(&mut app.cheat_name)
+                .hint_text("Name")
+                .desired_width(80.0),
+        );
+        ui.add(
+            egui::TextEdit::singleline(&mut app.cheat_code)
+                .hint_text("Code")
+                .desired_width(120.0),
+        );
+        if ui.button("Add").clicked() {
+            match Cheat::new(app.cheat_name.trim().to_string(), app.cheat_code.clone()) {
+                Ok(cheat) => {
+                    app.emulator.send(Command::AddCheat(cheat));
+                    app.cheat_name.clear();
+                    app.cheat_code.clear();
+                    app.cheat_error = None;
+                }
+                Err(error) => app.cheat_error = Some(error.to_string()),
+            }
+        }
+    });
+    if let Some(error) = &app.cheat_error {
+        ui.colored_label(ui.visuals().error_fg_color, error);
+    }
+}
+
+fn show_cheat(ui: &mut egui::Ui, app: &mut EmulatorApp, index: usize, cheat: &Cheat) {
+    let mut enabled = cheat.enabled;
+    if ui.checkbox(&mut enabled, "").changed() {
+        app.emulator.send(Command::SetCheatEnabled(index, enabled));
+    }
+    match &mut app.cheat_rename {
+        Some((renamed, name)) if *renamed == index => {
+            let response = ui.add(egui::TextEdit::singleline(name).desired_width(80.0));
+            if response.lost_focus() {
+                if ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+                    app.emulator
+                        .send(Command::RenameCheat(index, name.trim().to_string()));
+                }
+                app.cheat_rename = None;
+            } else {
+                response.request_focus();
+            }
+        }
+        _ => {
+            let name = if cheat.name.is_empty() {
+                "Unnamed"
+            } else {
+                &cheat.name
+            };
+            if ui
+                .add(egui::Label::new(name).sense(egui::Sense::click()))
+                .on_hover_text("Double-click to rename")
+                .double_clicked()
+            {
+                app.cheat_rename = Some((index, cheat.name.clone()));
+            }
+        }
+    }
+    let code = ui.monospace(&cheat.code);
+    if let Err(error) = cheat.parse() {
+        code.on_hover_text(error.to_string());
+    }
+    if ui.button("🗑").on_hover_text("Remove").clicked() {
+        app.emulator.send(Command::RemoveCheat(index));
+    }
+}
@@ -0,0 +1,184 @@
+use crate::EmulatorApp;
+use crate::vue::bindings::{Action, Binding, MAX_TURBO_RATE, Profile};
+use crate::vue::gamepad;
+
+/// Shows the controls window, to pick an input profile and rebind its
+/// actions.
+pub fn show(ctx: &egui::Context, app: &mut EmulatorApp) {
+    if app.binding_action.is_some() {
+        take_binding(ctx, app);
+        // Keeps polling the gamepads while the emulation is paused.
+        ctx.request_repaint();
+    }
+    let mut open = app.controls_open;
+    egui::Window::new("Controls")
+        .open(&mut open)
+        .resizable(false)
+        .show(ctx, |ui| {
+            show_profiles(ui, app);
+            ui.separator();
+            show_bindings(ui, app);
+            ui.separator();
+            show_turbo_rates(ui, app);
+            ui.separator();
+            show_gamepads(ui, app);
+        });
+    app.controls_open = open;
+    if !open {
+        app.binding_action = None;
+    }
+}
+
+/// Binds the next key pressed or gamepad input to the action waiting for
+/// one, Escape cancelling.
+fn take_binding(ctx: &egui::Context, app: &mut EmulatorApp) {
+    let key = ctx.input(|i| {
+        i.events.iter().find_map(|event| match event {
+            egui::Event::Key {
+                key,
+                pressed: true,
+                repeat: false,
+                ..
+            } => Some(*key),
+            _ => None,
+        })
+    });
+    let binding = match key {
+        Some(egui::Key::Escape) => {
+            app.binding_action = None;
+            return;
+        }
+        Some(key) => Binding::Key(key),
+        None => match app.gamepads.take_last_input() {
+            Some(binding) => binding,
+            None => return,
+        },
+    };
+    let action = app.binding_action.take().unwrap();
+    app.config.input.get_active_mut().bind(action, binding);
+    app.config.save();
+}
+
+fn show_profiles(ui: &mut egui::Ui, app: &mut EmulatorApp) {
+    let config = &mut app.config.input;
+    let mut changed = false;
+    ui.horizontal(|ui| {
+        ui.label("Profile");
+        let active = config.get_active().name.clone();
+        egui::ComboBox::from_id_salt("input_profile")
+            .selected_text(&active)
+            .show_ui(ui, |ui| {
+                for profile in &config.profiles {
+                    changed |= ui
+                        .selectable_value(&mut config.active, profile.name.clone(), &profile.name)
+                        .changed();
+                }
+            });
+        if ui
+            .add_enabled(config.profiles.len() > 1, egui::Button::new("🗑"))
+            .on_hover_text("Remove this profile")
+            .clicked()
+        {
+            config.remove_active();
+            changed = true;
+        }
+        if ui
+            .button("⟲")
+            .on_hover_text("Restore the default bindings of this profile")
+            .clicked()
+        {
+            let profile = config.get_active_mut();
+            *profile = Profile::new(profile.name.clone());
+            changed = true;
+        }
+    });
+    ui.horizontal(|ui| {
+        ui.add(
+            egui::TextEdit::singleline(&mut app.profile_name)
+                .hint_text("New profile")
+                .desired_width(120.0),
+        );
+        let name = app.profile_name.trim();
+        let taken = config.profiles.iter().any(|profile| profile.name == name);
+        if ui
+            .add_enabled(!name.is_empty() && !taken, egui::Button::new("Add"))
+            .on_hover_text("Copy this profile under a new name")
+            .clicked()
+        {
+            config.add_profile(name.to_string());
+            app.profile_name.clear();
+            changed = true;
+        }
+    });
+    if changed {
+        app.config.save();
+    }
+}
+
+/// Lists the inputs bound to each action, a click removing one.
+fn show_bindings(ui: &mut egui::Ui, app: &mut EmulatorApp) {
+    let mut removed = None;
+    egui::Grid::new("bindings").striped(true).show(ui, |ui| {
+        let profile = app.config.input.get_active();
+        for action in Action::ALL {
+            ui.label(action.get_name());
+            ui.horizontal(|ui| {
+                for binding in profile.get_bindings(action) {
+                    if ui
+                        .small_button(binding.to_string())
+                        .on_hover_text("Click to remove")
+                        .clicked()
+                    {
+                        removed = Some((action, *binding));
+                    }
+                }
+                if app.binding_action == Some(action) {
+                    ui.weak("Press a key or a gamepad button, Escape to cancel");
+                } else if ui
+                    .small_button("➕")
+                    .on_hover_text("Bind an input")
+                    .clicked()
+                {
+                    // Forgets the gamepad inputs made before.
+                    app.gamepads.take_last_input();
+                    app.binding_action = Some(action);
+                }
+            });
+            ui.end_row();
+        }
+    });
+    if let Some((action, binding)) = removed {
+        app.config.input.get_active_mut().unbind(action, binding);
+        app.config.save();
+    }
+}
+
+fn show_turbo_rates(ui: &mut egui::Ui, app: &mut EmulatorApp) {
+    let profile = app.config.input.get_active_mut();
+    let mut changed = false;
+    for (name, rate) in [
+        ("Turbo A", &mut profile.turbo_a_rate),
+        ("Turbo B", &mut profile.turbo_b_rate),
+    ] {
+        changed |= ui
+            .add(egui::Slider::new(rate, 1..=MAX_TURBO_RATE).text(format!("{} presses/s", name)))
+            .changed();
+    }
+    if changed {
+        app.config.save();
+    }
+}
+
+fn show_gamepads(ui: &mut egui::Ui, app: &EmulatorApp) {
+    if !gamepad::SUPPORTED {
+        ui.weak("Built without gamepad support");
+        return;
+    }
+    let names = app.gamepads.get_names();
+    if names.is_empty() {
+        ui.weak("No gamepad connected");
+    }
+    for name in names {
+        ui.label(format!("🎮 {}", name));
+    }
+}
@@ -1,34 +1,201 @@
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
 use crate::EmulatorApp;
+use crate::emulator::{Command, FAST_FORWARD_SPEED, FRAME_RATE};
+use crate::hardware::cpu::Register;
 use crate::hardware::memory::MemoryMap;
-use crate::interpreter::disassembler::Instruction;
-
-pub fn show(
-    ctx: &egui::Context,
-    _frame: &mut eframe::Frame,
-    app: &mut EmulatorApp,
-    instruction: &Instruction,
-) {
+use crate::hardware::ppu::Renderer;
+
+/// Speeds offered for slow motion, along with normal speed.
+const SPEEDS: [f64; 5] = [0.125, 0.25, 0.5, 0.75, 1.0];
+
+pub fn show(ctx: &egui::Context, _frame: &mut eframe::Frame, app: &mut EmulatorApp) {
     egui::SidePanel::left("memory_panel")
         .resizable(true) // Allow resizing the panel
-        .show(ctx, |ui| {
-            show_mem_map(ui, &mut app.mem_map);
+        .show_animated(ctx, app.config.debugger.memory_panel, |ui| {
+            show_mem_map(ui, &app.snapshot.mem_map);
+        });
+    egui::SidePanel::right("audio_panel")
+        .resizable(true)
+        .show_animated(ctx, app.config.debugger.tools_panel, |ui| {
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                super::audio::show(ui, app);
+                super::states::show(ui, app);
+                super::cheats::show(ui, app);
+            });
         });
     egui::CentralPanel::default().show(ctx, |ui| {
+        let snapshot = &app.snapshot;
+        super::screen::show(
+            ui,
+            &snapshot.framebuffer,
+            app.scale,
+            &mut app.screen_texture,
+        );
         ui.heading("CPU State");
-        ui.label(format!("Registers: {:X?}", app.cpu));
-        ui.label(format!("Next instruction: {:X?}", instruction));
+        ui.label(format!("Registers: {:X?}", snapshot.cpu));
+        match &snapshot.instruction {
+            Some(instruction) => ui.label(format!("Next instruction: {:X?}", instruction)),
+            None => ui.label("Next instruction: cannot be decoded"),
+        };
         ui.horizontal(|ui| {
-            if ui.button(if app.pause_flag { "▶" } else { "⏸" }).clicked() {
-                app.pause_flag = !app.pause_flag;
+            if ui.button(if snapshot.paused { "▶" } else { "⏸" }).clicked() {
+                app.emulator.send(Command::SetPaused(!snapshot.paused));
+            }
+            if ui
+                .add_enabled(snapshot.paused, egui::Button::new("⏭"))
+                .clicked()
+            {
+                app.emulator.send(Command::Step);
+            }
+            if ui
+                .add_enabled(snapshot.paused, egui::Button::new("⏯"))
+                .on_hover_text("Advance one frame")
+                .clicked()
+            {
+                app.emulator.send(Command::AdvanceFrame);
             }
-            if ui.button("⏭").clicked() {
-                app.step_flag = true;
+            let mut pixel_fifo = snapshot.renderer == Renderer::PixelFifo;
+            if ui.checkbox(&mut pixel_fifo, "Pixel FIFO").changed() {
+                app.emulator.send(Command::SetRenderer(if pixel_fifo {
+                    Renderer::PixelFifo
+                } else {
+                    Renderer::Scanline
+                }));
             }
+            let mut trace = snapshot.trace;
+            if ui.checkbox(&mut trace, "Trace").changed() {
+                app.emulator.send(Command::SetTrace(trace));
+            }
+            ui.toggle_value(&mut app.ram_search.open, "🔍 RAM search");
         });
+        show_speed(ui, app);
+        show_rewind(ui, app);
+        show_breakpoints(ui, app);
+        super::input::show(ui, app);
+        show_recorder(ui, app);
+        super::movie::show(ui, app);
+        super::gbs::show(ui, app);
+    });
+    super::search::show(ctx, app);
+}
+
+fn show_speed(ui: &mut egui::Ui, app: &mut EmulatorApp) {
+    let snapshot = &app.snapshot;
+    ui.horizontal(|ui| {
+        let mut speed = snapshot.speed;
+        egui::ComboBox::from_label("Speed")
+            .selected_text(format!("{}%", speed * 100.0))
+            .show_ui(ui, |ui| {
+                for option in SPEEDS {
+                    ui.selectable_value(&mut speed, option, format!("{}%", option * 100.0));
+                }
+            });
+        if speed != snapshot.speed {
+            app.emulator.send(Command::SetSpeed(speed));
+        }
+        let mut unlimited = snapshot.unlimited;
+        if ui.checkbox(&mut unlimited, "Unlimited").changed() {
+            app.emulator.send(Command::SetUnlimited(unlimited));
+        }
+        ui.label(format!(
+            "{:.0}% ({:.1} FPS)",
+            snapshot.fps / FRAME_RATE * 100.0,
+            snapshot.fps
+        ))
+        .on_hover_text(format!(
+            "Hold Tab to fast-forward at {}%",
+            FAST_FORWARD_SPEED * 100.0
+        ));
+    });
+}
+
+fn show_rewind(ui: &mut egui::Ui, app: &mut EmulatorApp) {
+    let snapshot = &app.snapshot;
+    ui.horizontal(|ui| {
+        let mut budget = snapshot.rewind_budget >> 20;
+        let response = ui.add(
+            egui::DragValue::new(&mut budget)
+                .range(0..=1024)
+                .suffix(" MiB"),
+        );
+        ui.label("Rewind buffer").on_hover_text(format!(
+            "Hold {} to rewind, 0 MiB disables it",
+            super::input::REWIND_KEY.name()
+        ));
+        if response.changed() {
+            app.emulator.send(Command::SetRewindBudget(budget << 20));
+        }
+        ui.label(format!(
+            "{:.1} MiB used, {:.1} s{}",
+            snapshot.rewind_used as f64 / (1 << 20) as f64,
+            snapshot.rewind_length,
+            if snapshot.rewinding { " ⏪" } else { "" }
+        ));
+    });
+}
+
+fn show_breakpoints(ui: &mut egui::Ui, app: &mut EmulatorApp) {
+    ui.horizontal(|ui| {
+        ui.label("Breakpoint");
+        let response = ui.add(
+            egui::TextEdit::singleline(&mut app.breakpoint_input)
+                .hint_text("PC, in hex")
+                .desired_width(60.0),
+        );
+        let submitted = response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+        let address = u16::from_str_radix(app.breakpoint_input.trim(), 16).ok();
+        if (ui
+            .add_enabled(address.is_some(), egui::Button::new("Add"))
+            .clicked()
+            || submitted)
+            && let Some(address) = address
+        {
+            app.emulator.send(Command::AddBreakpoint(address));
+            app.breakpoint_input.clear();
+        }
+    });
+    let pc = app.snapshot.cpu.read_word(&Register::PC);
+    ui.horizontal_wrapped(|ui| {
+        for &address in &app.snapshot.breakpoints {
+            let label = format!("{:04X} ✖", address);
+            let button = egui::Button::new(label).selected(address == pc);
+            if ui.add(button).on_hover_text("Remove").clicked() {
+                app.emulator.send(Command::RemoveBreakpoint(address));
+            }
+        }
+    });
+}
+
+fn show_recorder(ui: &mut egui::Ui, app: &mut EmulatorApp) {
+    ui.horizontal(|ui| {
+        if let Some(recording) = &app.snapshot.recording {
+            let stems = if recording.stems { " with stems" } else { "" };
+            ui.label(format!(
+                "Recording {}{} ({:.1} s)",
+                recording.path.display(),
+                stems,
+                recording.duration
+            ));
+            if ui.button("⏹ Stop").clicked() {
+                app.emulator.send(Command::StopRecording);
+            }
+            return;
+        }
+        if ui.button("⏺ Record WAV").clicked() {
+            let seconds = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |duration| duration.as_secs());
+            let path = PathBuf::from(format!("recording-{}.wav", seconds));
+            app.emulator
+                .send(Command::StartRecording(path, app.record_stems));
+        }
+        ui.checkbox(&mut app.record_stems, "Channel stems");
     });
 }
 
-fn show_mem_map(ui: &mut egui::Ui, mem_map: &mut MemoryMap) {
+fn show_mem_map(ui: &mut egui::Ui, mem_map: &MemoryMap) {
     use egui_extras::{Column, TableBuilder};
     TableBuilder::new(ui)
         .striped(true)
@@ -53,7 +220,7 @@ fn show_mem_map(ui: &mut egui::Ui, mem_map: &mut MemoryMap) {
                 for x in 0..16 {
                     row.col(|ui| {
                         ui.centered_and_justified(|ui| {
-                            ui.label(format!("{:02X}", mem_map.read_byte(x + y * 16).unwrap()))
+                            ui.label(format!("{:02X}", mem_map.peek_byte(x + y * 16).unwrap()))
                         });
                     });
                 }
@@ -0,0 +1,325 @@
+use std::path::{Path, PathBuf};
+
+use crate::EmulatorApp;
+use crate::emulator::Command;
+use crate::emulator::loader::{self, ARCHIVE_EXTENSIONS, LoadError, ROM_EXTENSIONS, RomSource};
+
+/// Directory listed by the built-in file browser, which unlike the native
+/// dialogs works without any desktop portal.
+pub struct FileBrowser {
+    dir: PathBuf,
+    /// Subdirectories then ROMs and archives, each sorted by name, along
+    /// with whether they are directories.
+    entries: Vec<(PathBuf, bool)>,
+    /// Zip archive listed instead of `dir`, along with the ROMs in it.
+    archive: Option<(PathBuf, Vec<String>)>,
+    error: Option<String>,
+}
+
+impl FileBrowser {
+    pub fn new(dir: PathBuf) -> Self {
+        let mut browser = Self {
+            dir: std::path::absolute(&dir).unwrap_or(dir),
+            entries: vec![],
+            archive: None,
+            error: None,
+        };
+        browser.refresh();
+        return browser;
+    }
+
+    fn enter(&mut self, dir: PathBuf) {
+        self.dir = dir;
+        self.refresh();
+    }
+
+    /// Lists the directory again, leaving out the hidden files.
+    fn refresh(&mut self) {
+        self.entries.clear();
+        self.error = None;
+        let entries = match std::fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(error) => {
+                self.error = Some(error.to_string());
+                return;
+            }
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let is_dir = path.is_dir();
+            let hidden = entry.file_name().to_string_lossy().starts_with('.');
+            if !hidden && (is_dir || is_openable(&path)) {
+                self.entries.push((path, is_dir));
+            }
+        }
+        self.entries
+            .sort_by(|(a, a_dir), (b, b_dir)| b_dir.cmp(a_dir).then_with(|| a.cmp(b)));
+    }
+
+    /// Lists the ROMs of a zip archive to pick one, or returns the only
+    /// one.
+    fn open_zip(&mut self, path: PathBuf) -> Option<RomSource> {
+        self.error = None;
+        let entries = match loader::list_zip(&path) {
+            Ok(entries) if entries.is_empty() => {
+                self.error = Some(format!("{}: {}", path.display(), LoadError::NoRom));
+                return None;
+            }
+            Ok(entries) => entries,
+            Err(error) => {
+                self.error = Some(format!("{}: {}", path.display(), error));
+                return None;
+            }
+        };
+        if let [entry] = entries.as_slice() {
+            return Some(RomSource {
+                path,
+                entry: Some(entry.clone()),
+            });
+        }
+        if let Some(dir) = path.parent() {
+            self.dir = dir.to_path_buf();
+        }
+        self.archive = Some((path, entries));
+        return None;
+    }
+}
+
+fn is_openable(path: &Path) -> bool {
+    return loader::has_extension(path, &ROM_EXTENSIONS)
+        || loader::has_extension(path, &ARCHIVE_EXTENSIONS);
+}
+
+fn is_zip(path: &Path) -> bool {
+    return loader::has_extension(path, &["zip"]);
+}
+
+/// Shows the menu bar and the file browser, and opens the ROMs dropped
+/// onto the window.
+pub fn show(ctx: &egui::Context, app: &mut EmulatorApp) {
+    egui::TopBottomPanel::top("menu_bar").show(ctx, |ui| {
+        egui::menu::bar(ui, |ui| {
+            ui.menu_button("File", |ui| show_file_menu(ui, app));
+            ui.menu_button("View", |ui| {
+                let debugger = &mut app.config.debugger;
+                let memory = ui.checkbox(&mut debugger.memory_panel, "Memory panel");
+                let tools = ui.checkbox(&mut debugger.tools_panel, "Tools panel");
+                if memory.changed() || tools.changed() {
+                    app.config.save();
+                }
+            });
+            ui.menu_button("Settings", |ui| {
+                if ui.button("Preferences…").clicked() {
+                    app.preferences.open = true;
+                    ui.close_menu();
+                }
+                if ui.button("Controls…").clicked() {
+                    app.controls_open = true;
+                    ui.close_menu();
+                }
+            });
+        });
+    });
+    show_browser(ctx, app);
+    handle_drops(ctx, app);
+}
+
+fn show_file_menu(ui: &mut egui::Ui, app: &mut EmulatorApp) {
+    if ui.button("Open ROM…").clicked() {
+        // Start next to the ROM running.
+        let dir = app
+            .snapshot
+            .rom_source
+            .as_ref()
+            .and_then(|source| source.path.parent())
+            .map_or(PathBuf::from("."), Path::to_path_buf);
+        app.browser = Some(FileBrowser::new(dir));
+        ui.close_menu();
+    }
+    ui.menu_button("Open recent", |ui| {
+        let sources = app.recent.get_sources();
+        if sources.is_empty() {
+            ui.label("No recent ROM");
+        }
+        let mut opened = None;
+        for source in sources {
+            let path = source
+                .entry
+                .as_ref()
+                .map_or(source.path.as_path(), Path::new);
+            let name = path.file_name().unwrap_or(path.as_os_str());
+            if ui
+                .button(name.to_string_lossy())
+                .on_hover_text(source.to_string())
+                .clicked()
+            {
+                opened = Some(source.clone());
+            }
+        }
+        if let Some(source) = opened {
+            app.emulator.send(Command::OpenRom(source));
+            ui.close_menu();
+        }
+        ui.separator();
+        if ui
+            .add_enabled(!sources.is_empty(), egui::Button::new("Clear"))
+            .clicked()
+        {
+            app.recent.clear();
+            ui.close_menu();
+        }
+    });
+    ui.separator();
+    if ui
+        .button("Reset")
+        .on_hover_text("Restart the game, the work RAM keeps its contents")
+        .clicked()
+    {
+        app.emulator.send(Command::Reset);
+        ui.close_menu();
+    }
+    if ui
+        .button("Power cycle")
+        .on_hover_text("Turn the console off and on again")
+        .clicked()
+    {
+        app.emulator.send(Command::PowerCycle);
+        ui.close_menu();
+    }
+    ui.separator();
+    if ui.button("Quit").clicked() {
+        ui.ctx().send_viewport_cmd(egui::ViewportCommand::Close);
+    }
+}
+
+fn show_browser(ctx: &egui::Context, app: &mut EmulatorApp) {
+    let Some(browser) = &mut app.browser else {
+        return;
+    };
+    let mut open = true;
+    let mut picked = None;
+    egui::Window::new("Open ROM")
+        .open(&mut open)
+        .default_height(400.0)
+        .show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                let parent = match &browser.archive {
+                    Some(_) => Some(browser.dir.clone()),
+                    None => browser.dir.parent().map(Path::to_path_buf),
+                };
+                if ui
+                    .add_enabled(parent.is_some(), egui::Button::new("⬆"))
+                    .on_hover_text("Parent directory")
+                    .clicked()
+                {
+                    browser.archive = None;
+                    browser.enter(parent.unwrap());
+                }
+                let shown = match &browser.archive {
+                    Some((path, _)) => path,
+                    None => &browser.dir,
+                };
+                ui.label(shown.display().to_string());
+            });
+            ui.separator();
+            if let Some(error) = &browser.error {
+                ui.colored_label(ui.visuals().error_fg_color, error);
+            }
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                picked = match &browser.archive {
+                    Some((path, entries)) => show_archive(ui, path, entries),
+                    None => show_dir(ui, browser),
+                };
+            });
+        });
+    if let Some(source) = picked {
+        app.emulator.send(Command::OpenRom(source));
+        open = false;
+    }
+    if !open {
+        app.browser = None;
+    }
+}
+
+/// Lists the directory, returns the ROM picked.
+fn show_dir(ui: &mut egui::Ui, browser: &mut FileBrowser) -> Option<RomSource> {
+    let mut clicked = None;
+    for (path, is_dir) in &browser.entries {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let label = if *is_dir {
+            format!("📁 {}", name)
+        } else if is_zip(path) {
+            format!("🗜 {}", name)
+        } else {
+            name.to_string()
+        };
+        if ui.selectable_label(false, label).clicked() {
+            clicked = Some((path.clone(), *is_dir));
+        }
+    }
+    return match clicked {
+        Some((dir, true)) => {
+            browser.enter(dir);
+            None
+        }
+        Some((path, false)) if is_zip(&path) => browser.open_zip(path),
+        Some((path, false)) => Some(RomSource::new(path)),
+        None => None,
+    };
+}
+
+/// Lists the ROMs in a zip archive, returns the one picked.
+fn show_archive(ui: &mut egui::Ui, path: &Path, entries: &[String]) -> Option<RomSource> {
+    let mut picked = None;
+    for entry in entries {
+        if ui.selectable_label(false, entry).clicked() {
+            picked = Some(RomSource {
+                path: path.to_path_buf(),
+                entry: Some(entry.clone()),
+            });
+        }
+    }
+    return picked;
+}
+
+/// Opens the first ROM or archive among the files dropped, and tells where
+/// to drop them while they hover over the window. Archives holding several
+/// ROMs open in the browser to pick one.
+fn handle_drops(ctx: &egui::Context, app: &mut EmulatorApp) {
+    if ctx.input(|i| !i.raw.hovered_files.is_empty()) {
+        let painter = ctx.layer_painter(egui::LayerId::new(
+            egui::Order::Foreground,
+            egui::Id::new("drop_overlay"),
+        ));
+        let rect = ctx.screen_rect();
+        painter.rect_filled(rect, 0.0, egui::Color32::from_black_alpha(180));
+        painter.text(
+            rect.center(),
+            egui::Align2::CENTER_CENTER,
+            "Drop a ROM or an archive to open it",
+            egui::FontId::proportional(24.0),
+            egui::Color32::WHITE,
+        );
+    }
+    let dropped = ctx.input(|i| {
+        i.raw
+            .dropped_files
+            .iter()
+            .filter_map(|file| file.path.clone())
+            .find(|path| is_openable(path))
+    });
+    let Some(path) = dropped else {
+        return;
+    };
+    if !is_zip(&path) {
+        app.emulator.send(Command::OpenRom(RomSource::new(path)));
+        return;
+    }
+    let dir = path.parent().map_or(PathBuf::from("."), Path::to_path_buf);
+    let mut browser = FileBrowser::new(dir);
+    match browser.open_zip(path) {
+        Some(source) => app.emulator.send(Command::OpenRom(source)),
+        // Shows the archive, or why it could not be read.
+        None => app.browser = Some(browser),
+    }
+}
@@ -0,0 +1,177 @@
+use crate::vue::bindings::Binding;
+#[cfg(feature = "gamepad")]
+use crate::vue::bindings::{PadAxis, PadButton};
+
+/// Whether gamepads are compiled in.
+pub const SUPPORTED: bool = cfg!(feature = "gamepad");
+/// Stick position, from -1 to 1, past which it counts as pushed.
+#[cfg(feature = "gamepad")]
+const AXIS_THRESHOLD: f32 = 0.5;
+
+/// Gamepads connected to the host, polled on every UI update. There are
+/// none when built without the `gamepad` feature.
+pub struct Gamepads {
+    #[cfg(feature = "gamepad")]
+    gilrs: Option<gilrs::Gilrs>,
+    /// Button pressed or stick pushed last, to bind it.
+    last_input: Option<Binding>,
+}
+
+impl Gamepads {
+    #[cfg(feature = "gamepad")]
+    pub fn new() -> Self {
+        let gilrs = match gilrs::Gilrs::new() {
+            Ok(gilrs) => Some(gilrs),
+            Err(error) => {
+                eprintln!("Could not open the gamepads: {}", error);
+                None
+            }
+        };
+        return Self {
+            gilrs,
+            last_input: None,
+        };
+    }
+
+    #[cfg(not(feature = "gamepad"))]
+    pub fn new() -> Self {
+        return Self { last_input: None };
+    }
+
+    /// Takes the events received since the last call, which updates the
+    /// state of the gamepads.
+    pub fn poll(&mut self) {
+        #[cfg(feature = "gamepad")]
+        if let Some(gilrs) = &mut self.gilrs {
+            while let Some(event) = gilrs.next_event() {
+                let input = match event.event {
+                    gilrs::EventType::ButtonPressed(button, _) => {
+                        to_pad_button(button).map(Binding::Button)
+                    }
+                    gilrs::EventType::AxisChanged(axis, value, _)
+                        if value.abs() >= AXIS_THRESHOLD =>
+                    {
+                        to_pad_axis(axis, value).map(Binding::Axis)
+                    }
+                    _ => None,
+                };
+                if input.is_some() {
+                    self.last_input = input;
+                }
+            }
+        }
+    }
+
+    /// Button pressed or stick pushed since the last call, if any.
+    pub fn take_last_input(&mut self) -> Option<Binding> {
+        return self.last_input.take();
+    }
+
+    /// Whether a gamepad has `binding` held, keys never are.
+    #[cfg(feature = "gamepad")]
+    pub fn is_held(&self, binding: Binding) -> bool {
+        let Some(gilrs) = &self.gilrs else {
+            return false;
+        };
+        return gilrs.gamepads().any(|(_, gamepad)| match binding {
+            Binding::Key(_) => false,
+            Binding::Button(button) => gamepad.is_pressed(from_pad_button(button)),
+            Binding::Axis(axis) => {
+                let (axis, direction) = from_pad_axis(axis);
+                gamepad.value(axis) * direction >= AXIS_THRESHOLD
+            }
+        });
+    }
+
+    #[cfg(not(feature = "gamepad"))]
+    pub fn is_held(&self, _binding: Binding) -> bool {
+        return false;
+    }
+
+    /// Names of the gamepads connected.
+    #[cfg(feature = "gamepad")]
+    pub fn get_names(&self) -> Vec<String> {
+        return self
+            .gilrs
+            .iter()
+            .flat_map(|gilrs| {
+                gilrs
+                    .gamepads()
+                    .map(|(_, gamepad)| gamepad.name().to_string())
+            })
+            .collect();
+    }
+
+    #[cfg(not(feature = "gamepad"))]
+    pub fn get_names(&self) -> Vec<String> {
+        return vec![];
+    }
+}
+
+#[cfg(feature = "gamepad")]
+const BUTTONS: [(PadButton, gilrs::Button); 16] = [
+    (PadButton::South, gilrs::Button::South),
+    (PadButton::East, gilrs::Button::East),
+    (PadButton::North, gilrs::Button::North),
+    (PadButton::West, gilrs::Button::West),
+    (PadButton::LeftBumper, gilrs::Button::LeftTrigger),
+    (PadButton::RightBumper, gilrs::Button::RightTrigger),
+    (PadButton::LeftTrigger, gilrs::Button::LeftTrigger2),
+    (PadButton::RightTrigger, gilrs::Button::RightTrigger2),
+    (PadButton::Select, gilrs::Button::Select),
+    (PadButton::Start, gilrs::Button::Start),
+    (PadButton::LeftStick, gilrs::Button::LeftThumb),
+    (PadButton::RightStick, gilrs::Button::RightThumb),
+    (PadButton::DpadUp, gilrs::Button::DPadUp),
+    (PadButton::DpadDown, gilrs::Button::DPadDown),
+    (PadButton::DpadLeft, gilrs::Button::DPadLeft),
+    (PadButton::DpadRight, gilrs::Button::DPadRight),
+];
+
+/// Stick directions, with the axis and the sign of its values along them.
+/// Up is positive.
+#[cfg(feature = "gamepad")]
+const AXES: [(PadAxis, gilrs::Axis, f32); 8] = [
+    (PadAxis::LeftStickLeft, gilrs::Axis::LeftStickX, -1.0),
+    (PadAxis::LeftStickRight, gilrs::Axis::LeftStickX, 1.0),
+    (PadAxis::LeftStickUp, gilrs::Axis::LeftStickY, 1.0),
+    (PadAxis::LeftStickDown, gilrs::Axis::LeftStickY, -1.0),
+    (PadAxis::RightStickLeft, gilrs::Axis::RightStickX, -1.0),
+    (PadAxis::RightStickRight, gilrs::Axis::RightStickX, 1.0),
+    (PadAxis::RightStickUp, gilrs::Axis::RightStickY, 1.0),
+    (PadAxis::RightStickDown, gilrs::Axis::RightStickY, -1.0),
+];
+
+#[cfg(feature = "gamepad")]
+fn to_pad_button(button: gilrs::Button) -> Option<PadButton> {
+    return BUTTONS
+        .iter()
+        .find(|(_, other)| *other == button)
+        .map(|(pad_button, _)| *pad_button);
+}
+
+#[cfg(feature = "gamepad")]
+fn from_pad_button(button: PadButton) -> gilrs::Button {
+    return BUTTONS
+        .iter()
+        .find(|(other, _)| *other == button)
+        .map(|(_, gilrs_button)| *gilrs_button)
+        .unwrap();
+}
+
+#[cfg(feature = "gamepad")]
+fn to_pad_axis(axis: gilrs::Axis, value: f32) -> Option<PadAxis> {
+    return AXES
+        .iter()
+        .find(|(_, other, direction)| *other == axis && value * direction > 0.0)
+        .map(|(pad_axis, _, _)| *pad_axis);
+}
+
+#[cfg(feature = "gamepad")]
+fn from_pad_axis(axis: PadAxis) -> (gilrs::Axis, f32) {
+    return AXES
+        .iter()
+        .find(|(other, _, _)| *other == axis)
+        .map(|(_, gilrs_axis, direction)| (*gilrs_axis, *direction))
+        .unwrap();
+}
@@ -0,0 +1,41 @@
+use crate::EmulatorApp;
+use crate::emulator::Command;
+use crate::hardware::apu::CLOCK_RATE;
+
+/// Shows the loaded GBS tags and the track navigation.
+pub fn show(ui: &mut egui::Ui, app: &mut EmulatorApp) {
+    let Some(gbs) = &app.snapshot.gbs else {
+        return;
+    };
+    let header = &gbs.header;
+    ui.heading(if header.title.is_empty() {
+        "GBS player"
+    } else {
+        &header.title
+    });
+    ui.label(format!("Author: {}", header.author));
+    ui.label(format!("Copyright: {}", header.copyright));
+    let rate = CLOCK_RATE as f64 / gbs.period as f64;
+    ui.label(if gbs.on_timer {
+        format!("Played on the timer, {:.2} Hz", rate)
+    } else {
+        format!("Played on VBlank, {:.2} Hz", rate)
+    });
+    let track = gbs.track;
+    let song_count = header.song_count;
+    ui.horizontal(|ui| {
+        if ui.add_enabled(track > 0, egui::Button::new("⏮")).clicked() {
+            app.emulator.send(Command::StartGbsTrack(track - 1));
+        }
+        ui.label(format!("Track {} / {}", track as u32 + 1, song_count));
+        if ui
+            .add_enabled(track as u32 + 1 < song_count as u32, egui::Button::new("⏭"))
+            .clicked()
+        {
+            app.emulator.send(Command::StartGbsTrack(track + 1));
+        }
+        if ui.button("⟲").on_hover_text("Restart the track").clicked() {
+            app.emulator.send(Command::StartGbsTrack(track));
+        }
+    });
+}
@@ -0,0 +1,88 @@
+use crate::EmulatorApp;
+use crate::emulator::Command;
+use crate::emulator::state::SLOT_COUNT;
+use crate::hardware::joypad::Button;
+use crate::vue::bindings::{Action, Binding};
+use crate::vue::gamepad::Gamepads;
+
+const FAST_FORWARD_KEY: egui::Key = egui::Key::Tab;
+pub const REWIND_KEY: egui::Key = egui::Key::R;
+/// Keys loading each save state slot, or saving it along with Shift.
+pub const SLOT_KEYS: [egui::Key; SLOT_COUNT] = [
+    egui::Key::F1,
+    egui::Key::F2,
+    egui::Key::F3,
+    egui::Key::F4,
+    egui::Key::F5,
+    egui::Key::F6,
+    egui::Key::F7,
+    egui::Key::F8,
+    egui::Key::F9,
+    egui::Key::F10,
+];
+
+/// Sends the buttons, the fast-forward, rewind and save state keys pressed
+/// or released on the keyboard or gamepads since the last call. The keys are
+/// ignored while a text field has the focus or an input is being bound.
+pub fn poll(ctx: &egui::Context, app: &mut EmulatorApp) {
+    app.gamepads.poll();
+    let typing = ctx.wants_keyboard_input() || app.binding_action.is_some();
+    let profile = app.config.input.get_active();
+    for (index, action) in Action::ALL.iter().enumerate() {
+        let held = !typing
+            && profile
+                .get_bindings(*action)
+                .iter()
+                .any(|binding| is_held(ctx, &app.gamepads, *binding));
+        if held == app.held_actions[index] {
+            continue;
+        }
+        app.held_actions[index] = held;
+        let button = action.get_button();
+        app.emulator.send(if action.is_turbo() {
+            Command::SetTurbo(button, held.then(|| profile.get_turbo_rate(*action)))
+        } else {
+            Command::SetButton(button, held)
+        });
+    }
+    let fast_forward = !typing && ctx.input(|i| i.key_down(FAST_FORWARD_KEY));
+    if fast_forward != app.fast_forward {
+        app.fast_forward = fast_forward;
+        app.emulator.send(Command::SetFastForward(fast_forward));
+    }
+    let rewinding = !typing && ctx.input(|i| i.key_down(REWIND_KEY));
+    if rewinding != app.rewinding {
+        app.rewinding = rewinding;
+        app.emulator.send(Command::SetRewinding(rewinding));
+    }
+    if typing {
+        return;
+    }
+    for (slot, key) in SLOT_KEYS.iter().enumerate() {
+        if ctx.input(|i| i.key_pressed(*key)) {
+            app.emulator.send(if ctx.input(|i| i.modifiers.shift) {
+                Command::SaveState(slot)
+            } else {
+                Command::LoadState(slot)
+            });
+        }
+    }
+}
+
+fn is_held(ctx: &egui::Context, gamepads: &Gamepads, binding: Binding) -> bool {
+    return match binding {
+        Binding::Key(key) => ctx.input(|i| i.key_down(key)),
+        _ => gamepads.is_held(binding),
+    };
+}
+
+/// Shows the buttons held, as seen by the emulated joypad.
+pub fn show(ui: &mut egui::Ui, app: &EmulatorApp) {
+    let joypad = app.snapshot.mem_map.get_joypad();
+    let held = Button::ALL
+        .iter()
+        .filter(|button| joypad.is_pressed(**button))
+        .map(|button| format!("{:?}", button))
+        .collect::<Vec<_>>();
+    ui.label(format!("Joypad: {}", held.join(" ")));
+}
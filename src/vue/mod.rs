@@ -1 +1,15 @@
+pub mod audio;
+pub mod bindings;
+pub mod cheats;
+pub mod controls;
 pub mod debug;
+pub mod files;
+pub mod gamepad;
+pub mod gbs;
+pub mod input;
+pub mod movie;
+pub mod recent;
+pub mod screen;
+pub mod search;
+pub mod settings;
+pub mod states;
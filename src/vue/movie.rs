@@ -0,0 +1,50 @@
+use std::path::PathBuf;
+
+use crate::EmulatorApp;
+use crate::emulator::Command;
+
+/// Records or plays back an input movie, showing its progress.
+pub fn show(ui: &mut egui::Ui, app: &mut EmulatorApp) {
+    ui.horizontal(|ui| {
+        if let Some(movie) = &app.snapshot.movie {
+            let progress = match movie.length {
+                Some(length) => format!("Playing frame {} / {}", movie.frame, length),
+                None => format!("Recording frame {}", movie.frame),
+            };
+            ui.label(progress);
+            if let Some(frame) = movie.desync {
+                ui.colored_label(
+                    ui.visuals().error_fg_color,
+                    format!("desynced at frame {}", frame),
+                );
+            }
+            if ui.button("⏹ Stop").clicked() {
+                app.emulator.send(Command::StopMovie);
+            }
+            return;
+        }
+        ui.add(
+            egui::TextEdit::singleline(&mut app.movie_path)
+                .hint_text("Movie file")
+                .desired_width(140.0),
+        );
+        let path = app.movie_path.trim();
+        if ui
+            .add_enabled(!path.is_empty(), egui::Button::new("⏺ Record movie"))
+            .clicked()
+        {
+            app.emulator.send(Command::StartMovieRecording(
+                PathBuf::from(path),
+                app.movie_from_state,
+            ));
+        }
+        if ui
+            .add_enabled(!path.is_empty(), egui::Button::new("▶ Play movie"))
+            .clicked()
+        {
+            app.emulator.send(Command::PlayMovie(PathBuf::from(path)));
+        }
+        ui.checkbox(&mut app.movie_from_state, "From current state")
+            .on_hover_text("Otherwise the recording starts from power on");
+    });
+}
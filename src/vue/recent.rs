@@ -0,0 +1,88 @@
+use std::path::PathBuf;
+
+use crate::emulator::loader::RomSource;
+
+/// ROMs remembered in the File menu.
+const MAX_RECENT: usize = 10;
+const FILE_NAME: &str = "recent-roms";
+/// Separates the archive from the entry picked in it.
+const ENTRY_SEPARATOR: char = '\t';
+
+/// ROMs opened lately, newest first, kept in the user configuration
+/// directory one per line.
+pub struct RecentRoms {
+    sources: Vec<RomSource>,
+}
+
+impl RecentRoms {
+    /// Reads the list saved by a previous run, empty when there is none.
+    pub fn load() -> Self {
+        let sources = file_path()
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .map(|contents| {
+                contents
+                    .lines()
+                    .filter(|line| !line.is_empty())
+                    .map(parse_line)
+                    .take(MAX_RECENT)
+                    .collect()
+            })
+            .unwrap_or_default();
+        return Self { sources };
+    }
+
+    pub fn get_sources(&self) -> &[RomSource] {
+        return &self.sources;
+    }
+
+    /// Moves `source` to the top of the list, and saves it.
+    pub fn add(&mut self, source: &RomSource) {
+        let source = RomSource {
+            path: std::path::absolute(&source.path).unwrap_or(source.path.clone()),
+            entry: source.entry.clone(),
+        };
+        self.sources.retain(|other| *other != source);
+        self.sources.insert(0, source);
+        self.sources.truncate(MAX_RECENT);
+        self.save();
+    }
+
+    pub fn clear(&mut self) {
+        self.sources.clear();
+        self.save();
+    }
+
+    fn save(&self) {
+        let Some(path) = file_path() else {
+            return;
+        };
+        let mut contents = String::new();
+        for source in &self.sources {
+            contents.push_str(&source.path.to_string_lossy());
+            if let Some(entry) = &source.entry {
+                contents.push(ENTRY_SEPARATOR);
+                contents.push_str(entry);
+            }
+            contents.push('\n');
+        }
+        let result = std::fs::create_dir_all(path.parent().unwrap())
+            .and_then(|()| std::fs::write(&path, contents));
+        if let Err(error) = result {
+            eprintln!("Could not save the recent ROMs: {}", error);
+        }
+    }
+}
+
+fn parse_line(line: &str) -> RomSource {
+    return match line.split_once(ENTRY_SEPARATOR) {
+        Some((path, entry)) => RomSource {
+            path: PathBuf::from(path),
+            entry: Some(entry.to_string()),
+        },
+        None => RomSource::new(PathBuf::from(line)),
+    };
+}
+
+fn file_path() -> Option<PathBuf> {
+    return dirs::config_dir().map(|dir| dir.join(env!("CARGO_PKG_NAME")).join(FILE_NAME));
+}
@@ -0,0 +1,25 @@
+use crate::hardware::ppu::{Color, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+/// Shows the screen, each Game Boy pixel being `scale` points wide.
+pub fn show(
+    ui: &mut egui::Ui,
+    framebuffer: &[Color],
+    scale: f32,
+    texture: &mut Option<egui::TextureHandle>,
+) {
+    let pixels: Vec<u8> = framebuffer.iter().flatten().copied().collect();
+    let image = egui::ColorImage::from_rgb([SCREEN_WIDTH, SCREEN_HEIGHT], &pixels);
+    let texture = match texture {
+        Some(texture) => {
+            texture.set(image, egui::TextureOptions::NEAREST);
+            texture
+        }
+        None => texture.insert(ui.ctx().load_texture(
+            "screen",
+            image,
+            egui::TextureOptions::NEAREST,
+        )),
+    };
+    let size = egui::vec2(SCREEN_WIDTH as f32 * scale, SCREEN_HEIGHT as f32 * scale);
+    ui.add(egui::Image::new((texture.id(), size)));
+}
@@ -0,0 +1,436 @@
+use crate::EmulatorApp;
+use crate::emulator::Command;
+use crate::emulator::cheats::Cheat;
+use crate::hardware::memory::{MemoryMap, Model};
+
+use egui_extras::{Column, TableBuilder};
+use std::ops::Range;
+
+/// RAM searched: cartridge RAM and WRAM, then HRAM. On CGB, the switchable
+/// WRAM is searched in each of its banks.
+const REGIONS: [Range<u16>; 2] = [0xA000..0xE000, 0xFF80..0xFFFF];
+const BANKED_WRAM: Range<u16> = 0xD000..0xE000;
+const WRAM_BANKS: std::ops::RangeInclusive<u8> = 1..=7;
+/// GameShark types writing to RAM, to any or to a given WRAM bank.
+const GAMESHARK_WRITE: u8 = 0x01;
+const GAMESHARK_WRAM_BANK: u8 = 0x90;
+const ROW_HEIGHT: f32 = 18.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ValueSize {
+    Byte,
+    /// Little endian, like the CPU reads them.
+    Word,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Interpretation {
+    Unsigned,
+    Signed,
+    /// A decimal digit per nibble, values with other nibbles never match.
+    Bcd,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Greater,
+    Less,
+}
+
+impl Comparison {
+    const ALL: [Comparison; 4] = [
+        Comparison::Equal,
+        Comparison::NotEqual,
+        Comparison::Greater,
+        Comparison::Less,
+    ];
+
+    fn holds(&self, current: i64, other: i64) -> bool {
+        return match self {
+            Comparison::Equal => current == other,
+            Comparison::NotEqual => current != other,
+            Comparison::Greater => current > other,
+            Comparison::Less => current < other,
+        };
+    }
+
+    fn symbol(&self) -> &'static str {
+        return match self {
+            Comparison::Equal => "=",
+            Comparison::NotEqual => "≠",
+            Comparison::Greater => ">",
+            Comparison::Less => "<",
+        };
+    }
+}
+
+/// Address in the RAM searched, along with its WRAM bank on CGB when in
+/// the switchable one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Location {
+    address: u16,
+    bank: Option<u8>,
+}
+
+impl Location {
+    /// Next address, in the same WRAM bank unless out of the switchable
+    /// WRAM.
+    fn next(&self) -> Self {
+        let address = self.address.wrapping_add(1);
+        return Self {
+            address,
+            bank: self.bank.filter(|_| BANKED_WRAM.contains(&address)),
+        };
+    }
+
+    fn read(&self, mem_map: &MemoryMap) -> u8 {
+        return match self.bank {
+            Some(bank) => mem_map.peek_wram(bank as usize, self.address as usize),
+            None => mem_map.peek_byte(self.address as usize).unwrap(),
+        };
+    }
+}
+
+impl std::fmt::Display for Location {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        return match self.bank {
+            Some(bank) => write!(f, "{:X}:{:04X}", bank, self.address),
+            None => write!(f, "{:04X}", self.address),
+        };
+    }
+}
+
+/// Location still matching, with the 16 bits it held at the last filter so
+/// that the size and interpretation can change between filters.
+#[derive(Clone, Copy)]
+struct Candidate {
+    location: Location,
+    previous: u16,
+}
+
+/// Location shown with its current value.
+struct Watch {
+    location: Location,
+    size: ValueSize,
+    interpretation: Interpretation,
+}
+
+/// Finds where a game keeps a value by filtering RAM addresses down from a
+/// snapshot, comparing them each time with their previous value or with a
+/// given one.
+pub struct RamSearch {
+    pub open: bool,
+    size: ValueSize,
+    interpretation: Interpretation,
+    comparison: Comparison,
+    /// Value compared with, the previous values when empty.
+    value: String,
+    /// None until the first snapshot.
+    candidates: Option<Vec<Candidate>>,
+    watches: Vec<Watch>,
+    error: Option<String>,
+}
+
+impl RamSearch {
+    pub fn new() -> Self {
+        Self {
+            open: false,
+            size: ValueSize::Byte,
+            interpretation: Interpretation::Unsigned,
+            comparison: Comparison::Equal,
+            value: String::new(),
+            candidates: None,
+            watches: vec![],
+            error: None,
+        }
+    }
+
+    /// Starts over from every address, with their current values.
+    fn snapshot(&mut self, mem_map: &MemoryMap) {
+        let cgb = mem_map.get_model() == Model::CGB;
+        let candidates = REGIONS
+            .iter()
+            .flat_map(|region| region.clone())
+            .flat_map(|address| {
+                let banks: Vec<Option<u8>> = if cgb && BANKED_WRAM.contains(&address) {
+                    WRAM_BANKS.map(Some).collect()
+                } else {
+                    vec![None]
+                };
+                banks
+                    .into_iter()
+                    .map(move |bank| Location { address, bank })
+            })
+            .map(|location| Candidate {
+                location,
+                previous: read_raw(mem_map, location),
+            })
+            .collect();
+        self.candidates = Some(candidates);
+        self.error = None;
+    }
+
+    /// Keeps the candidates whose current value compares as asked, and
+    /// makes it their previous value.
+    fn filter(&mut self, mem_map: &MemoryMap) {
+        let value = self.value.trim();
+        let operand = if value.is_empty() {
+            None
+        } else {
+            match parse_value(value) {
+                Some(operand) => Some(operand),
+                None => {
+                    self.error = Some(format!("{} is not a number", value));
+                    return;
+                }
+            }
+        };
+        self.error = None;
+        let (size, interpretation, comparison) = (self.size, self.interpretation, self.comparison);
+        let Some(candidates) = &mut self.candidates else {
+            return;
+        };
+        candidates.retain_mut(|candidate| {
+            let raw = read_raw(mem_map, candidate.location);
+            let current = interpret(raw, size, interpretation);
+            let other = operand.or(interpret(candidate.previous, size, interpretation));
+            candidate.previous = raw;
+            return matches!((current, other), (Some(current), Some(other))
+                if comparison.holds(current, other));
+        });
+    }
+
+    fn format(&self, raw: u16) -> String {
+        return format_value(raw, self.size, self.interpretation);
+    }
+}
+
+/// The 16 bits at `location`, the byte there being the lowest.
+fn read_raw(mem_map: &MemoryMap, location: Location) -> u16 {
+    return location.read(mem_map) as u16 | (location.next().read(mem_map) as u16) << 8;
+}
+
+fn interpret(raw: u16, size: ValueSize, interpretation: Interpretation) -> Option<i64> {
+    let (raw, digits) = match size {
+        ValueSize::Byte => (raw & 0xFF, 2),
+        ValueSize::Word => (raw, 4),
+    };
+    return match interpretation {
+        Interpretation::Unsigned => Some(raw as i64),
+        Interpretation::Signed => Some(match size {
+            ValueSize::Byte => raw as u8 as i8 as i64,
+            ValueSize::Word => raw as i16 as i64,
+        }),
+        Interpretation::Bcd => {
+            let mut value = 0;
+            for digit in (0..digits).rev() {
+                let nibble = (raw >> (digit * 4)) & 0xF;
+                if nibble > 9 {
+                    return None;
+                }
+                value = value * 10 + nibble as i64;
+            }
+            Some(value)
+        }
+    };
+}
+
+fn format_value(raw: u16, size: ValueSize, interpretation: Interpretation) -> String {
+    let hex = match size {
+        ValueSize::Byte => format!("{:02X}", raw & 0xFF),
+        ValueSize::Word => format!("{:04X}", raw),
+    };
+    return match interpret(raw, size, interpretation) {
+        Some(value) => format!("{} ({})", value, hex),
+        None => format!("- ({})", hex),
+    };
+}
+
+/// Decimal, or hexadecimal after 0x.
+fn parse_value(value: &str) -> Option<i64> {
+    return match value.strip_prefix("0x") {
+        Some(hex) => i64::from_str_radix(hex, 16).ok(),
+        None => value.parse().ok(),
+    };
+}
+
+/// GameShark codes writing the `size` value at `location` held now, to
+/// keep it there. The codes write to the WRAM bank of the location, as the
+/// game may switch to another one.
+fn freeze(mem_map: &MemoryMap, location: Location, size: ValueSize) -> Cheat {
+    let raw = read_raw(mem_map, location);
+    let bytes = match size {
+        ValueSize::Byte => vec![(location, raw as u8)],
+        ValueSize::Word => vec![(location, raw as u8), (location.next(), (raw >> 8) as u8)],
+    };
+    let code = bytes
+        .iter()
+        .map(|(location, value)| {
+            let kind = location
+                .bank
+                .map_or(GAMESHARK_WRITE, |bank| GAMESHARK_WRAM_BANK | bank);
+            let address = location.address;
+            format!(
+                "{:02X}{:02X}{:02X}{:02X}",
+                kind,
+                value,
+                address & 0xFF,
+                address >> 8
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("+");
+    return Cheat::new(format!("Freeze {}", location), code)
+        .expect("RAM addresses make valid GameShark codes");
+}
+
+pub fn show(ctx: &egui::Context, app: &mut EmulatorApp) {
+    let mut open = app.ram_search.open;
+    egui::Window::new("RAM search")
+        .open(&mut open)
+        .default_width(380.0)
+        .show(ctx, |ui| {
+            show_search(ui, app);
+            ui.separator();
+            show_watches(ui, app);
+        });
+    app.ram_search.open &= open;
+}
+
+fn show_search(ui: &mut egui::Ui, app: &mut EmulatorApp) {
+    let search = &mut app.ram_search;
+    let mem_map = &app.snapshot.mem_map;
+    ui.horizontal(|ui| {
+        ui.radio_value(&mut search.size, ValueSize::Byte, "8-bit");
+        ui.radio_value(&mut search.size, ValueSize::Word, "16-bit");
+        ui.separator();
+        ui.radio_value(
+            &mut search.interpretation,
+            Interpretation::Unsigned,
+            "Unsigned",
+        );
+        ui.radio_value(&mut search.interpretation, Interpretation::Signed, "Signed");
+        ui.radio_value(&mut search.interpretation, Interpretation::Bcd, "BCD");
+    });
+    ui.horizontal(|ui| {
+        if ui
+            .button("Snapshot")
+            .on_hover_text("Start over from every address")
+            .clicked()
+        {
+            search.snapshot(mem_map);
+        }
+        egui::ComboBox::from_id_salt("ram_search_comparison")
+            .width(40.0)
+            .selected_text(search.comparison.symbol())
+            .show_ui(ui, |ui| {
+                for comparison in Comparison::ALL {
+                    ui.selectable_value(&mut search.comparison, comparison, comparison.symbol());
+                }
+            });
+        ui.add(
+            egui::TextEdit::singleline(&mut search.value)
+                .hint_text("previous value")
+                .desired_width(100.0),
+        );
+        if ui
+            .add_enabled(search.candidates.is_some(), egui::Button::new("Filter"))
+            .clicked()
+        {
+            search.filter(mem_map);
+        }
+    });
+    if let Some(error) = &search.error {
+        ui.colored_label(ui.visuals().error_fg_color, error);
+    }
+    let Some(candidates) = &search.candidates else {
+        ui.label("Take a snapshot of the RAM to start searching");
+        return;
+    };
+    ui.label(format!("{} candidates", candidates.len()));
+    let mut watched = None;
+    let mut frozen = None;
+    TableBuilder::new(ui)
+        .id_salt("ram_search_results")
+        .striped(true)
+        .max_scroll_height(240.0)
+        .column(Column::auto().at_least(50.0))
+        .columns(Column::auto().at_least(90.0), 2)
+        .column(Column::remainder())
+        .header(ROW_HEIGHT, |mut header| {
+            for title in ["Address", "Current", "Previous", ""] {
+                header.col(|ui| {
+                    ui.strong(title);
+                });
+            }
+        })
+        .body(|body| {
+            body.rows(ROW_HEIGHT, candidates.len(), |mut row| {
+                let candidate = candidates[row.index()];
+                row.col(|ui| {
+                    ui.monospace(candidate.location.to_string());
+                });
+                row.col(|ui| {
+                    ui.label(search.format(read_raw(mem_map, candidate.location)));
+                });
+                row.col(|ui| {
+                    ui.label(search.format(candidate.previous));
+                });
+                row.col(|ui| {
+                    if ui.small_button("👁").on_hover_text("Watch").clicked() {
+                        watched = Some(candidate.location);
+                    }
+                    if ui.small_button("❄").on_hover_text("Freeze").clicked() {
+                        frozen = Some(candidate.location);
+                    }
+                });
+            });
+        });
+    if let Some(location) = watched {
+        search.watches.push(Watch {
+            location,
+            size: search.size,
+            interpretation: search.interpretation,
+        });
+    }
+    if let Some(location) = frozen {
+        let cheat = freeze(mem_map, location, search.size);
+        app.emulator.send(Command::AddCheat(cheat));
+    }
+}
+
+/// Lists the addresses watched with their current value, to freeze them
+/// or stop watching them.
+fn show_watches(ui: &mut egui::Ui, app: &mut EmulatorApp) {
+    ui.strong("Watches");
+    if app.ram_search.watches.is_empty() {
+        ui.label("Watch a result to follow its value");
+        return;
+    }
+    let mem_map = &app.snapshot.mem_map;
+    let mut removed = None;
+    egui::Grid::new("ram_watches").striped(true).show(ui, |ui| {
+        for (index, watch) in app.ram_search.watches.iter().enumerate() {
+            ui.monospace(watch.location.to_string());
+            let raw = read_raw(mem_map, watch.location);
+            ui.label(format_value(raw, watch.size, watch.interpretation));
+            if ui.small_button("❄").on_hover_text("Freeze").clicked() {
+                let cheat = freeze(mem_map, watch.location, watch.size);
+                app.emulator.send(Command::AddCheat(cheat));
+            }
+            if ui
+                .small_button("🗑")
+                .on_hover_text("Stop watching")
+                .clicked()
+            {
+                removed = Some(index);
+            }
+            ui.end_row();
+        }
+    });
+    if let Some(index) = removed {
+        app.ram_search.watches.remove(index);
+    }
+}
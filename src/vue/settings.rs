@@ -0,0 +1,191 @@
+use std::path::PathBuf;
+
+use crate::EmulatorApp;
+use crate::cli::{ModelOption, SCALES};
+use crate::config::Config;
+use crate::emulator::Command;
+use crate::hardware::ppu::{Color, DMG_PALETTE};
+
+/// DMG palettes offered, along with the custom one configured.
+const PALETTES: [(&str, [Color; 4]); 3] = [
+    ("Green", DMG_PALETTE),
+    (
+        "Grey",
+        [
+            [0xFF, 0xFF, 0xFF],
+            [0xAA, 0xAA, 0xAA],
+            [0x55, 0x55, 0x55],
+            [0x00, 0x00, 0x00],
+        ],
+    ),
+    (
+        "Pocket",
+        [
+            [0xC4, 0xCF, 0xA1],
+            [0x8B, 0x95, 0x6D],
+            [0x4D, 0x53, 0x3C],
+            [0x1F, 0x1F, 0x1F],
+        ],
+    ),
+];
+
+/// Preferences window, with the paths being typed in.
+pub struct Preferences {
+    pub open: bool,
+    save_dir: String,
+    dmg_boot_rom: String,
+    cgb_boot_rom: String,
+}
+
+impl Preferences {
+    pub fn new(config: &Config) -> Self {
+        let path = |path: &Option<PathBuf>| {
+            path.as_ref()
+                .map_or(String::new(), |path| path.display().to_string())
+        };
+        return Self {
+            open: false,
+            save_dir: path(&config.save_dir),
+            dmg_boot_rom: path(&config.boot_roms.dmg),
+            cgb_boot_rom: path(&config.boot_roms.cgb),
+        };
+    }
+}
+
+/// Whether the edit made through `response` is over, to save it once rather
+/// than on every step of a drag.
+fn is_done(response: &egui::Response) -> bool {
+    return response.drag_stopped() || response.changed() && !response.dragged();
+}
+
+/// Path typed in, None when left empty.
+fn parse_path(text: &str) -> Option<PathBuf> {
+    let text = text.trim();
+    return (!text.is_empty()).then(|| PathBuf::from(text));
+}
+
+pub fn show(ctx: &egui::Context, app: &mut EmulatorApp) {
+    let mut open = app.preferences.open;
+    egui::Window::new("Preferences")
+        .open(&mut open)
+        .resizable(false)
+        .show(ctx, |ui| {
+            ui.heading("Display");
+            show_display(ui, app);
+            ui.separator();
+            ui.heading("Audio");
+            show_audio(ui, app);
+            ui.separator();
+            ui.heading("System");
+            show_system(ui, app);
+        });
+    app.preferences.open = open;
+}
+
+fn show_display(ui: &mut egui::Ui, app: &mut EmulatorApp) {
+    let response = ui.add(egui::Slider::new(&mut app.scale, SCALES).text("Scale"));
+    if is_done(&response) {
+        app.config.window.scale = app.scale;
+        app.config.save();
+    }
+    let mut changed = false;
+    ui.horizontal(|ui| {
+        for (name, palette) in PALETTES {
+            changed |= ui
+                .selectable_value(&mut app.config.palette, palette, name)
+                .changed();
+        }
+    });
+    ui.horizontal(|ui| {
+        for color in &mut app.config.palette {
+            changed |= egui::color_picker::color_edit_button_srgb(ui, color).changed();
+        }
+    })
+    .response
+    .on_hover_text("DMG shades, from lightest to darkest");
+    if changed {
+        app.emulator.send(Command::SetPalette(app.config.palette));
+        app.config.save();
+    }
+}
+
+fn show_audio(ui: &mut egui::Ui, app: &mut EmulatorApp) {
+    let audio = &mut app.config.audio;
+    let mut done = false;
+    ui.horizontal(|ui| {
+        let response = ui.add_enabled(
+            !audio.muted,
+            egui::Slider::new(&mut audio.volume, 0..=100)
+                .text("Volume")
+                .suffix("%"),
+        );
+        if response.changed() {
+            app.emulator.send(Command::SetVolume(audio.get_gain()));
+        }
+        done |= is_done(&response);
+        if ui.checkbox(&mut audio.muted, "Mute").changed() {
+            app.emulator.send(Command::SetVolume(audio.get_gain()));
+            done = true;
+        }
+    });
+    if done {
+        app.config.save();
+    }
+}
+
+fn show_system(ui: &mut egui::Ui, app: &mut EmulatorApp) {
+    let preferences = &mut app.preferences;
+    let config = &mut app.config;
+    let mut changed = false;
+    egui::Grid::new("system_preferences").show(ui, |ui| {
+        ui.label("Model");
+        ui.horizontal(|ui| {
+            for (model, name) in [
+                (None, "Auto"),
+                (Some(ModelOption::Dmg), "DMG"),
+                (Some(ModelOption::Cgb), "CGB"),
+            ] {
+                changed |= ui.radio_value(&mut config.model, model, name).changed();
+            }
+        })
+        .response
+        .on_hover_text("Applies from the next start, unless given on the command line");
+        ui.end_row();
+        for (name, text, path) in [
+            (
+                "DMG boot ROM",
+                &mut preferences.dmg_boot_rom,
+                &mut config.boot_roms.dmg,
+            ),
+            (
+                "CGB boot ROM",
+                &mut preferences.cgb_boot_rom,
+                &mut config.boot_roms.cgb,
+            ),
+        ] {
+            ui.label(name);
+            let response = ui
+                .add(egui::TextEdit::singleline(text).hint_text("None"))
+                .on_hover_text("Applies from the next start, unless given on the command line");
+            if response.lost_focus() && parse_path(text) != *path {
+                *path = parse_path(text);
+                changed = true;
+            }
+            ui.end_row();
+        }
+        ui.label("Save directory");
+        let response = ui
+            .add(egui::TextEdit::singleline(&mut preferences.save_dir).hint_text("Next to the ROM"))
+            .on_hover_text("Where the save states and cheats go");
+        let save_dir = parse_path(&preferences.save_dir);
+        if response.lost_focus() && save_dir != config.save_dir {
+            app.emulator.send(Command::SetSaveDir(save_dir.clone()));
+            config.save_dir = save_dir;
+            changed = true;
+        }
+        ui.end_row();
+    });
+    if changed {
+        config.save();
+    }
+}
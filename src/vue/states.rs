@@ -0,0 +1,125 @@
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use crate::EmulatorApp;
+use crate::emulator::Command;
+use crate::emulator::state::{SLOT_COUNT, Slot, THUMBNAIL_HEIGHT, THUMBNAIL_WIDTH};
+use crate::hardware::ppu::Color;
+
+const COLUMNS: usize = 2;
+
+/// Shows the save state slots with their thumbnails, to save or load them.
+pub fn show(ui: &mut egui::Ui, app: &mut EmulatorApp) {
+    ui.separator();
+    ui.heading("Save states").on_hover_text(format!(
+        "{:?}-{:?} load a slot, with Shift to save it",
+        super::input::SLOT_KEYS[0],
+        super::input::SLOT_KEYS[SLOT_COUNT - 1]
+    ));
+    if let Some(message) = &app.snapshot.message {
+        ui.label(message);
+    }
+    show_bess(ui, app);
+    let slots = app.snapshot.slots.clone();
+    egui::Grid::new("save_states").show(ui, |ui| {
+        for (slot, contents) in slots.iter().enumerate() {
+            ui.vertical(|ui| show_slot(ui, app, slot, contents));
+            if (slot + 1) % COLUMNS == 0 {
+                ui.end_row();
+            }
+        }
+    });
+}
+
+/// Imports or exports the state as a BESS file, to share it with other
+/// emulators.
+fn show_bess(ui: &mut egui::Ui, app: &mut EmulatorApp) {
+    ui.horizontal(|ui| {
+        ui.add(
+            egui::TextEdit::singleline(&mut app.bess_path)
+                .hint_text("BESS file")
+                .desired_width(140.0),
+        );
+        let path = app.bess_path.trim();
+        if ui
+            .add_enabled(!path.is_empty(), egui::Button::new("Import"))
+            .clicked()
+        {
+            app.emulator.send(Command::ImportBess(PathBuf::from(path)));
+        }
+        if ui
+            .add_enabled(!path.is_empty(), egui::Button::new("Export"))
+            .clicked()
+        {
+            app.emulator.send(Command::ExportBess(PathBuf::from(path)));
+        }
+    });
+}
+
+fn show_slot(ui: &mut egui::Ui, app: &mut EmulatorApp, slot: usize, contents: &Slot) {
+    let size = egui::vec2(THUMBNAIL_WIDTH as f32, THUMBNAIL_HEIGHT as f32);
+    match contents {
+        Slot::Saved {
+            thumbnail,
+            modified,
+        } => {
+            let texture = load_thumbnail(ui, app, slot, thumbnail, *modified);
+            ui.add(egui::Image::new((texture.id(), size)));
+            ui.label(format!("Slot {}, {}", slot + 1, format_age(*modified)));
+        }
+        Slot::Empty => {
+            ui.add_sized(size, egui::Label::new("Empty"));
+            ui.label(format!("Slot {}", slot + 1));
+        }
+        Slot::Unreadable(error) => {
+            ui.add_sized(size, egui::Label::new("Unreadable"))
+                .on_hover_text(error);
+            ui.label(format!("Slot {}", slot + 1));
+        }
+    }
+    ui.horizontal(|ui| {
+        if ui.button("Save").clicked() {
+            app.emulator.send(Command::SaveState(slot));
+        }
+        let saved = matches!(contents, Slot::Saved { .. });
+        if ui.add_enabled(saved, egui::Button::new("Load")).clicked() {
+            app.emulator.send(Command::LoadState(slot));
+        }
+    });
+}
+
+/// Returns the texture of the slot thumbnail, only uploading it again once
+/// the slot was saved over.
+fn load_thumbnail(
+    ui: &egui::Ui,
+    app: &mut EmulatorApp,
+    slot: usize,
+    thumbnail: &[Color],
+    modified: SystemTime,
+) -> egui::TextureHandle {
+    if let Some((loaded, texture)) = &app.slot_textures[slot]
+        && *loaded == modified
+    {
+        return texture.clone();
+    }
+    let pixels: Vec<u8> = thumbnail.iter().flatten().copied().collect();
+    let image = egui::ColorImage::from_rgb([THUMBNAIL_WIDTH, THUMBNAIL_HEIGHT], &pixels);
+    let texture =
+        ui.ctx()
+            .load_texture(format!("slot{}", slot), image, egui::TextureOptions::LINEAR);
+    app.slot_textures[slot] = Some((modified, texture.clone()));
+    return texture;
+}
+
+/// How long ago a slot was saved, roughly.
+fn format_age(modified: SystemTime) -> String {
+    let seconds = SystemTime::now()
+        .duration_since(modified)
+        .map_or(0, |age| age.as_secs());
+    return match seconds {
+        0..60 => "just now".to_string(),
+        60..3600 => format!("{} min ago", seconds / 60),
+        3600..86400 => format!("{} h ago", seconds / 3600),
+        _ => format!("{} days ago", seconds / 86400),
+    };
+}